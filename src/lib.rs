@@ -1,2 +1,16 @@
 pub mod sm;
+pub mod sm_async;
 pub mod sm_ext;
+pub mod sm_global;
+pub mod sm_graph;
+pub mod sm_intercept;
+// Items which appear in the bounds of sm::Threading, and so must be public,
+// but are not part of the API of the crate
+mod sm_internal;
+pub mod sm_mission;
+pub mod sm_observer;
+pub mod sm_parallel;
+pub mod sm_rate;
+pub mod sm_thread;
+pub mod sm_trace;
+pub mod sm_validate;
@@ -1,25 +1,215 @@
 use core::fmt;
 use std::{
     any::{type_name, Any, TypeId},
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::Display,
+    future::{poll_fn, Future},
     marker::PhantomData,
+    ops::{Deref, DerefMut},
+    panic::{self, AssertUnwindSafe},
+    pin::{pin, Pin},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    task::Poll,
+    time::Instant,
 };
 
+pub(crate) use crate::sm_internal::{IncomeAdapters, StateEntryError, StateInternal, StepHook};
+
+/// Function pointer used to construct a fresh instance of a registered state
+pub(crate) type StateFactory<Data, K = Local> = fn() -> Box<<K as Threading>::State<Data>>;
+
+/// Function pointer used to deliver an event of a specific type to a state of a specific type
+type EventHandler<Data> = fn(&mut dyn StateInternal<Data>, &dyn Any, &mut Data) -> BoxedOutcome;
+
+/// Determines what happens to an instance of a state when the state machine transitions away from it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Retention {
+    /// The instance is dropped and a new instance is created for every transition into the state
+    #[default]
+    Fresh,
+    /// The instance is kept by the runner and re-entered through State::resume
+    /// the next time the state machine transitions into the state
+    Persistent,
+}
+
+/// Whether a StateMachine and its runners may be sent to another thread
+/// 
+/// Implemented by Local and Sendable only
+pub trait Threading: 'static {
+    /// The type of the instances of the states of the state machine
+    type State<Data: 'static>: ?Sized + StateInternal<Data>;
+    /// The type of the events dispatched to a runner
+    type Event: ?Sized + Any;
+    /// The type of the observers of a runner
+    type Hook<Data: 'static>: ?Sized + StepHook<Data>;
+
+    /// Returns `state` as a state of any state machine, dropping the bounds of this Threading
+    fn state_mut<D: 'static>(state: &mut Self::State<D>) -> &mut dyn StateInternal<D>;
+    /// Returns `event` as an event of any runner, dropping the bounds of this Threading
+    fn event(event: &Self::Event) -> &dyn Any;
+    /// Converts an event sent through an EventSender into an event of a runner
+    fn sent_event(event: Box<dyn Any + Send>) -> Box<Self::Event>;
+}
+
+/// The default Threading of a StateMachine, whose states, events and observers need not be Send
+/// 
+/// Runners of such a state machine can only be run on the thread which created them
+#[derive(Debug)]
+pub struct Local;
+
+impl Threading for Local {
+    type State<Data: 'static> = dyn StateInternal<Data>;
+    type Event = dyn Any;
+    type Hook<Data: 'static> = dyn StepHook<Data>;
+
+    fn state_mut<D: 'static>(state: &mut Self::State<D>) -> &mut dyn StateInternal<D> {
+        state
+    }
+
+    fn event(event: &Self::Event) -> &dyn Any {
+        event
+    }
+
+    fn sent_event(event: Box<dyn Any + Send>) -> Box<Self::Event> {
+        event
+    }
+}
+
+/// The Threading of a StateMachine which only accepts states which are Send,
+/// and whose runners only accept events and observers which are Send
+/// 
+/// A runner of such a state machine is Send if its data is,
+/// so an OwnedStateMachineRunner may be moved to another thread once created
+#[derive(Debug)]
+pub struct Sendable;
+
+impl Threading for Sendable {
+    type State<Data: 'static> = dyn StateInternal<Data> + Send;
+    type Event = dyn Any + Send;
+    type Hook<Data: 'static> = dyn StepHook<Data> + Send;
+
+    fn state_mut<D: 'static>(state: &mut Self::State<D>) -> &mut dyn StateInternal<D> {
+        state
+    }
+
+    fn event(event: &Self::Event) -> &dyn Any {
+        event
+    }
+
+    fn sent_event(event: Box<dyn Any + Send>) -> Box<Self::Event> {
+        event
+    }
+}
+
+/// Everything a StateMachine knows about one of its states
+pub(crate) struct Registration<Data: 'static, K: Threading = Local> {
+    pub(crate) factory: StateFactory<Data, K>,
+    /// The name of the state, read once when the state is added
+    pub(crate) name: String,
+    pub(crate) retention: Retention,
+    pub(crate) income: TypeId,
+    pub(crate) income_name: &'static str,
+    pub(crate) declared_transitions: fn() -> Option<Vec<DeclaredTransition>>,
+}
+
+/// The state which the state machine transitions to instead of stopping on an error
+struct ErrorState {
+    state: TypeId,
+    state_name: &'static str,
+    /// Converts the error into the Income of the error state
+    income: fn(RunErrorKind) -> Box<dyn Any>,
+}
+
+/// The outcome used to enter the error state or the bail out state instead of a state's own outcome
+struct Replacement {
+    state: TypeId,
+    data: Box<dyn Any>,
+    name: String,
+}
+
+impl Outcome for Replacement {
+    fn state_type(&self) -> TypeId {
+        self.state
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        self.data
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+
+/// Instances of states with Retention::Persistent which have been transitioned away from
+/// 
+/// Each instance of a state machine keeps its own history
+pub(crate) struct History<Data: 'static, K: Threading = Local>(
+    HashMap<TypeId, Box<K::State<Data>>>,
+);
+
+// Manually implemented because derive macro requires D: Default
+impl<D, K: Threading> Default for History<D, K> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+/// The global transitions of a state machine, see sm_global
+pub(crate) trait GlobalHook<Data>: Any + Send + Sync {
+    /// Returns the outcome of the first global transition which fires in the state of type `state`,
+    /// among those evaluated before its handle if `before_handle` is true and after it otherwise
+    fn fire(&self, before_handle: bool, state: TypeId, data: &Data) -> Option<BoxedOutcome>;
+    /// Returns the name of every global transition along with the TypeId and type name of its target
+    fn targets(&self) -> Vec<(String, TypeId, &'static str)>;
+}
+
+/// The interceptors of a state machine, see sm_intercept
+pub(crate) trait InterceptHook<Data>: Any + Send + Sync {
+    /// Returns the outcome to take instead of `outcome` leaving the state of type `start`,
+    /// or the name of the transition which was vetoed
+    fn intercept(
+        &self,
+        start: TypeId,
+        outcome: BoxedOutcome,
+        data: &Data,
+    ) -> Result<BoxedOutcome, String>;
+}
+
+/// The wiring of the Wired outcomes of a state machine, see sm_mission
+pub(crate) trait WiringHook: Any + Send + Sync {
+    /// Points `outcome` at its target if it is a Wired outcome of the state of type `state`
+    /// which is wired
+    fn follow(&self, state: TypeId, outcome: BoxedOutcome) -> BoxedOutcome;
+}
 
 /// The struct which holds all the states in a state machine
 /// 
 /// This struct itself does not _run_ any state machine,
 /// a StateMachineRunner contains a reference to a StateMachine
 /// and is an instance of the machine
-pub struct StateMachine<Data: 'static> {
-    states: HashMap<TypeId, fn() -> Box<dyn StateInternal<Data>>>,
+/// 
+/// The Threading of a state machine decides whether its runners may be sent to another thread,
+/// see Local and Sendable
+pub struct StateMachine<Data: 'static, K: Threading = Local> {
+    states: HashMap<TypeId, Registration<Data, K>>,
+    /// Keyed by the TypeId of the state and then the TypeId of the event
+    event_handlers: HashMap<(TypeId, TypeId), EventHandler<Data>>,
+    error_state: Option<ErrorState>,
+    global_transitions: Option<Box<dyn GlobalHook<Data>>>,
+    interceptors: Option<Box<dyn InterceptHook<Data>>>,
+    income_adapters: IncomeAdapters,
+    wiring: Option<Box<dyn WiringHook>>,
 }
 
-impl<D: fmt::Debug> fmt::Debug for StateMachine<D> {
+impl<D: fmt::Debug, K: Threading> fmt::Debug for StateMachine<D, K> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let state_ids: HashMap<TypeId, TypeId> =
-            self.states.iter().map(|(k, v)| (*k, v.type_id())).collect();
+            self.states.iter().map(|(k, v)| (*k, v.factory.type_id())).collect();
         f.debug_struct("StateMachine")
             .field("states", &state_ids)
             .finish()
@@ -27,10 +217,24 @@ impl<D: fmt::Debug> fmt::Debug for StateMachine<D> {
 }
 
 // Manually implemented because derive macro requires D: Default
+// 
+// Only implemented for Local so that the Threading of a default state machine is inferred
 impl<D> Default for StateMachine<D> {
     fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<D, K: Threading> StateMachine<D, K> {
+    fn empty() -> Self {
         Self {
             states: Default::default(),
+            event_handlers: Default::default(),
+            error_state: None,
+            global_transitions: None,
+            interceptors: None,
+            income_adapters: IncomeAdapters::default(),
+            wiring: None,
         }
     }
 }
@@ -38,15 +242,166 @@ impl<D> Default for StateMachine<D> {
 impl<D> StateMachine<D> {
     /// Adds a state to the state machine
     /// The `Data` associated type of the state must match that of all the other states in the state machine
+    /// 
+    /// An instance of the state is created once to read its name
     pub fn add_state<T: State<Data = D>>(&mut self) {
+        self.register::<T>(|| Box::<T>::default() as _);
+    }
+
+    /// Adds a state to the state machine which receives events of type E
+    /// 
+    /// A state may receive several types of event by calling this method once for each event type
+    pub fn add_event_state<T: EventState<E, Data = D>, E: 'static>(&mut self) {
+        self.add_state::<T>();
+        self.register_event::<T, E>();
+    }
+}
+
+impl<D> StateMachine<D, Sendable> {
+    /// Creates a state machine which only accepts states which are Send,
+    /// so that its runners may be sent to another thread
+    pub fn sendable() -> Self {
+        Self::empty()
+    }
+
+    /// Adds a state to the state machine, which must be Send since the runners of the state machine
+    /// may be sent to another thread
+    /// 
+    /// Otherwise behaves like the add_state of a Local state machine
+    pub fn add_state<T: State<Data = D> + Send>(&mut self) {
+        self.register::<T>(|| Box::<T>::default() as _);
+    }
+
+    /// Adds a state to the state machine which receives events of type E, see add_state
+    pub fn add_event_state<T: EventState<E, Data = D> + Send, E: 'static>(&mut self) {
+        self.add_state::<T>();
+        self.register_event::<T, E>();
+    }
+}
+
+impl<D, K: Threading> StateMachine<D, K> {
+    /// Adds T to the state machine with `factory` creating its instances,
+    /// unless T is already in the state machine
+    fn register<T: State<Data = D>>(&mut self, factory: StateFactory<D, K>) {
         self.states
             .entry(TypeId::of::<T>())
-            .or_insert(|| Box::<T>::default() as _);
+            .or_insert_with(|| Registration {
+                factory,
+                name: T::default().name(),
+                retention: Retention::Fresh,
+                income: TypeId::of::<T::Income>(),
+                income_name: type_name::<T::Income>(),
+                declared_transitions: T::declared_transitions,
+            });
+    }
+
+    /// Delivers events of type E to T
+    fn register_event<T: EventState<E, Data = D>, E: 'static>(&mut self) {
+        self.event_handlers
+            .insert((TypeId::of::<T>(), TypeId::of::<E>()), deliver_event::<T, E, D>);
+    }
+
+    /// Sets whether instances of T are kept when the state machine transitions away from T
+    /// Returns false if T is not in the state machine
+    pub fn set_retention<T: State<Data = D>>(&mut self, retention: Retention) -> bool {
+        match self.states.get_mut(&TypeId::of::<T>()) {
+            Some(registration) => {
+                registration.retention = retention;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the state which the state machine transitions to when a transition
+    /// would otherwise end in StateNotFound or IncorrectTransition
+    /// 
+    /// T is entered with the error converted into its Income,
+    /// and the step reports the error through StepOutcome::Recovered.
+    /// Errors caused by a transition of T itself are not recovered from.
+    /// The transition to T is intercepted like any other, and the error is reported
+    /// without recovering if it is vetoed.
+    /// Panics are not recovered from.
+    /// Returns false if T is not in the state machine
+    pub fn set_error_state<T: State<Data = D>>(&mut self) -> bool
+    where
+        T::Income: From<RunErrorKind>,
+    {
+        if !self.states.contains_key(&TypeId::of::<T>()) {
+            return false;
+        }
+        self.error_state = Some(ErrorState {
+            state: TypeId::of::<T>(),
+            state_name: type_name::<T>(),
+            income: |error| Box::new(T::Income::from(error)),
+        });
+        true
+    }
+
+    /// Adds a conversion which is applied when a state with an Income of type I
+    /// is transitioned to with data of type R
+    /// 
+    /// Without an adapter such a transition ends in IncorrectTransition.
+    /// Adding a second adapter between the same types replaces the first
+    pub fn add_income_adapter<R: 'static, I: 'static>(
+        &mut self,
+        adapter: impl Fn(R) -> I + Send + Sync + 'static,
+    ) {
+        self.income_adapters.0.insert(
+            (TypeId::of::<R>(), TypeId::of::<I>()),
+            Box::new(move |received| {
+                let received = received
+                    .downcast()
+                    .expect("Adapters are only applied to data of the type they are keyed by");
+                Box::new(adapter(*received))
+            }),
+        );
+    }
+
+    pub(crate) fn global_hook_mut(&mut self) -> &mut Option<Box<dyn GlobalHook<D>>> {
+        &mut self.global_transitions
+    }
+
+    pub(crate) fn intercept_hook_mut(&mut self) -> &mut Option<Box<dyn InterceptHook<D>>> {
+        &mut self.interceptors
+    }
+
+    pub(crate) fn income_adapters(&self) -> &IncomeAdapters {
+        &self.income_adapters
+    }
+
+    pub(crate) fn wiring_hook(&self) -> Option<&dyn WiringHook> {
+        self.wiring.as_deref()
+    }
+
+    pub(crate) fn global_hook(&self) -> Option<&dyn GlobalHook<D>> {
+        self.global_transitions.as_deref()
+    }
+
+    /// Returns the TypeId and type name of the error state, see set_error_state
+    pub(crate) fn error_state(&self) -> Option<(TypeId, &'static str)> {
+        self.error_state
+            .as_ref()
+            .map(|error_state| (error_state.state, error_state.state_name))
+    }
+
+    pub(crate) fn wiring_hook_mut(&mut self) -> &mut Option<Box<dyn WiringHook>> {
+        &mut self.wiring
     }
 
     /// Returns true if T was already in the state machine
     pub fn remove_state<T: State<Data = D>>(&mut self) -> bool {
-        self.states.remove(&TypeId::of::<T>()).is_some()
+        let state_id = TypeId::of::<T>();
+        self.event_handlers
+            .retain(|(handler_state, _), _| *handler_state != state_id);
+        if self
+            .error_state
+            .as_ref()
+            .is_some_and(|error_state| error_state.state == state_id)
+        {
+            self.error_state = None;
+        }
+        self.states.remove(&state_id).is_some()
     }
 
     /// Create a state machine runner from the provided start state
@@ -55,63 +410,489 @@ impl<D> StateMachine<D> {
         &self,
         initial_data: D,
         start_transition_data: Start::Income,
-    ) -> Option<StateMachineRunner<D>> {
+    ) -> Option<StateMachineRunner<'_, D, K>> {
         StateMachineRunner::new::<Start>(self, initial_data, start_transition_data)
     }
 
-    fn make_state(&self, state: TypeId) -> Option<Box<dyn StateInternal<D>>> {
-        self.states.get(&state).map(|sh| sh())
+    /// Create a state machine runner which shares ownership of the state machine
+    /// Returns None if the provided start state is not present in the state machine
+    pub fn owned_runner<Start: State>(
+        self: &Arc<Self>,
+        initial_data: D,
+        start_transition_data: Start::Income,
+    ) -> Option<OwnedStateMachineRunner<D, K>> {
+        OwnedStateMachineRunner::new::<Start>(self.clone(), initial_data, start_transition_data)
+    }
+
+    /// Create a state machine runner from the provided start state which catches panics,
+    /// see StateMachineRunner::new_catching_panics
+    pub fn runner_catching_panics<Start: State>(
+        &self,
+        initial_data: D,
+        start_transition_data: Start::Income,
+    ) -> Option<Result<StateMachineRunner<'_, D, K>, RunError<D>>> {
+        StateMachineRunner::new_catching_panics::<Start>(self, initial_data, start_transition_data)
+    }
+
+    /// Returns the registration of every state in the state machine
+    pub(crate) fn registrations(&self) -> impl Iterator<Item = (&TypeId, &Registration<D, K>)> {
+        self.states.iter()
+    }
+
+    pub(crate) fn registration(&self, state: TypeId) -> Option<&Registration<D, K>> {
+        self.states.get(&state)
+    }
+
+    pub(crate) fn make_state(&self, state: TypeId) -> Option<Box<K::State<D>>> {
+        self.states.get(&state).map(|registration| (registration.factory)())
+    }
+
+    /// Takes the retained instance of a state from history, or creates a new one
+    /// Returns the state and whether it was retained
+    fn take_or_make_state(
+        &self,
+        state: TypeId,
+        history: &mut History<D, K>,
+    ) -> Option<(Box<K::State<D>>, bool)> {
+        match history.0.remove(&state) {
+            Some(retained) => Some((retained, true)),
+            None => self.make_state(state).map(|state| (state, false)),
+        }
+    }
+
+    /// Keeps `state` in history if its retention is Retention::Persistent, otherwise drops it
+    /// 
+    /// The exit or suspend method of `state` must already have been run, see leave_state
+    pub(crate) fn retain_state(&self, state: Box<K::State<D>>, history: &mut History<D, K>) {
+        let state_id = <K::State<D> as Any>::type_id(&*state);
+        if self.is_persistent(state_id) {
+            history.0.insert(state_id, state);
+        }
+    }
+
+    /// Runs the suspend method of `state` if its instance is kept once it is left,
+    /// otherwise runs its exit method
+    fn leave_state(&self, state: &mut K::State<D>, data: &mut D) {
+        if self.is_persistent(<K::State<D> as Any>::type_id(state)) {
+            state.suspend(data);
+        } else {
+            state.exit(data);
+        }
+    }
+
+    fn is_persistent(&self, state: TypeId) -> bool {
+        self.states
+            .get(&state)
+            .is_some_and(|registration| registration.retention == Retention::Persistent)
+    }
+
+    /// Enters `state` with the provided data, resuming it if it was retained
+    fn enter_state(
+        &self,
+        state: &mut Box<K::State<D>>,
+        meta: Box<dyn Any>,
+        retained: bool,
+    ) -> Result<(), StateEntryError> {
+        if retained {
+            state.resume(meta, &self.income_adapters)
+        } else {
+            state.enter(meta, &self.income_adapters)
+        }
+    }
+
+    /// Creates and enters the provided start state
+    /// Returns None if the provided start state is not present in the state machine
+    pub(crate) fn start_state<Start: State>(
+        &self,
+        history: &mut History<D, K>,
+        start: Start::Income,
+    ) -> Option<Box<K::State<D>>> {
+        let state = self.start_state_with(TypeId::of::<Start>(), history, Box::new(start))?;
+        Some(state.expect("Start::Income will always match Start transition expected data"))
+    }
+
+    /// The same as start_state for a start state only known by its TypeId
+    /// Returns an error if `income` is not the Income of the start state
+    pub(crate) fn start_state_with(
+        &self,
+        start: TypeId,
+        history: &mut History<D, K>,
+        income: Box<dyn Any>,
+    ) -> Option<Result<Box<K::State<D>>, StateEntryError>> {
+        let (mut state, retained) = self.take_or_make_state(start, history)?;
+        Some(self.enter_state(&mut state, income, retained).map(|_| state))
+    }
+
+    /// Performs one step of `state` against `data`, replacing `state` on transition
+    ///
+    /// This is the logic shared by every runner of this machine,
+    /// including machines nested inside of other states
+    pub(crate) fn step_state(
+        &self,
+        state: &mut Box<K::State<D>>,
+        history: &mut History<D, K>,
+        data: &mut D,
+    ) -> StepReport {
+        if let Some(outcome) = self.global_outcome(&**state, true, data) {
+            return self.resolve_outcome(state, history, outcome, data);
+        }
+        let outcome = state.handle(data);
+        let outcome = self
+            .global_outcome(&**state, false, data)
+            .unwrap_or(outcome);
+        self.resolve_outcome(state, history, outcome, data)
+    }
+
+    /// The same as step_state, but awaits the handle of the current state
+    pub(crate) async fn step_state_async(
+        &self,
+        state: &mut Box<K::State<D>>,
+        history: &mut History<D, K>,
+        data: &mut D,
+    ) -> StepReport {
+        if let Some(outcome) = self.global_outcome(&**state, true, data) {
+            return self.resolve_outcome(state, history, outcome, data);
+        }
+        let outcome = state.handle_async(data).await;
+        let outcome = self
+            .global_outcome(&**state, false, data)
+            .unwrap_or(outcome);
+        self.resolve_outcome(state, history, outcome, data)
+    }
+
+    /// Returns the outcome of the global transition which fires in `state`, if any,
+    /// among those evaluated before its handle if `before_handle` is true and after it otherwise
+    fn global_outcome(
+        &self,
+        state: &K::State<D>,
+        before_handle: bool,
+        data: &D,
+    ) -> Option<BoxedOutcome> {
+        let hook = self.global_transitions.as_ref()?;
+        let state_id = <K::State<D> as Any>::type_id(state);
+        hook.fire(before_handle, state_id, data)
+    }
+
+    /// Returns true if `state` was added with add_event_state for events of type `event`
+    fn receives_event(&self, state: &K::State<D>, event: TypeId) -> bool {
+        let state_id = <K::State<D> as Any>::type_id(state);
+        self.event_handlers.contains_key(&(state_id, event))
+    }
+
+    /// Delivers event to `state`, replacing `state` on transition
+    /// Returns None if `state` does not receive events of the given type
+    pub(crate) fn step_event(
+        &self,
+        state: &mut Box<K::State<D>>,
+        history: &mut History<D, K>,
+        event: &dyn Any,
+        data: &mut D,
+    ) -> Option<StepReport> {
+        let state_id = <K::State<D> as Any>::type_id(&**state);
+        let handler = self.event_handlers.get(&(state_id, event.type_id()))?;
+        let outcome = handler(K::state_mut(&mut **state), event, data);
+        Some(self.resolve_outcome(state, history, outcome, data))
+    }
+
+    /// Applies the outcome returned by the handle of `state`
+    /// 
+    /// The exit method of `state` is run once for every outcome which leaves it,
+    /// including those which end in an error
+    fn resolve_outcome(
+        &self,
+        state: &mut Box<K::State<D>>,
+        history: &mut History<D, K>,
+        outcome: BoxedOutcome,
+        data: &mut D,
+    ) -> StepReport {
+        let state_id = <K::State<D> as Any>::type_id(&**state);
+        let outcome = match &self.wiring {
+            Some(hook) => hook.follow(state_id, outcome),
+            None => outcome,
+        };
+        if outcome.state_type() == state_id {
+            return StepReport::Continue;
+        }
+        let outcome = match self.intercept(state_id, outcome, data) {
+            Ok(outcome) if outcome.state_type() == state_id => return StepReport::Continue,
+            Ok(outcome) => outcome,
+            Err(transition) => {
+                return StepReport::Vetoed {
+                    start: state.name(),
+                    transition,
+                }
+            }
+        };
+        let new_state_id = outcome.state_type();
+        if new_state_id == TypeId::of::<()>() {
+            state.exit(data);
+            return StepReport::Complete {
+                start: state.name(),
+                transition: outcome.name(),
+            };
+        }
+        self.leave_state(&mut **state, data);
+        let start = state.name();
+        let transition = outcome.name();
+        let Some((new_state, retained)) = self.take_or_make_state(new_state_id, history) else {
+            let report = StepReport::StateNotFound {
+                start,
+                transition,
+                end: new_state_id,
+            };
+            return self.recover(state, history, state_id, true, report, data);
+        };
+        let old_state = std::mem::replace(state, new_state);
+        self.retain_state(old_state, history);
+        let end = state.name();
+        match self.enter_state(state, outcome.data(), retained) {
+            Ok(_) => StepReport::Transition {
+                start,
+                transition,
+                end,
+            },
+            Err(error) => {
+                let report = StepReport::IncorrectTransition {
+                    start,
+                    transition,
+                    end,
+                    expected_type: error.expected,
+                    received_data: error.received,
+                };
+                self.recover(state, history, state_id, retained, report, data)
+            }
+        }
+    }
+
+    /// Runs the interceptors of the machine on `outcome` leaving the state of type `start`
+    /// Returns the outcome to take, or the name of the transition which was vetoed
+    pub(crate) fn intercept(
+        &self,
+        start: TypeId,
+        outcome: BoxedOutcome,
+        data: &D,
+    ) -> Result<BoxedOutcome, String> {
+        match &self.interceptors {
+            Some(hook) => hook.intercept(start, outcome, data),
+            None => Ok(outcome),
+        }
+    }
+
+    /// Replaces `state` with the error state of the machine after the error in `report`
+    /// Returns `report` unchanged if the machine has no error state, if the error state itself
+    /// caused the error, or if the transition to the error state was vetoed
+    /// or redirected to a state which could not be entered
+    /// 
+    /// `state` is either the state which was left, or the state which could not be entered,
+    /// and is kept in history if `retain_old` is true.
+    /// `start` is the TypeId of the state which was left
+    fn recover(
+        &self,
+        state: &mut Box<K::State<D>>,
+        history: &mut History<D, K>,
+        start: TypeId,
+        retain_old: bool,
+        report: StepReport,
+        data: &D,
+    ) -> StepReport {
+        let (Some(error_state), Some(error)) = (&self.error_state, report.error_kind()) else {
+            return report;
+        };
+        // Recovering from an error of the error state could repeat the error forever
+        if start == error_state.state {
+            return report;
+        }
+        let outcome = Box::new(Replacement {
+            state: error_state.state,
+            data: (error_state.income)(error.clone()),
+            name: "(Error)".to_string(),
+        });
+        let Ok(outcome) = self.intercept(start, outcome, data) else {
+            return report;
+        };
+        match self.replace_state(state, history, outcome, retain_old) {
+            Some(end) => StepReport::Recovered { error, end },
+            None => report,
+        }
+    }
+
+    /// Replaces `state` with the state which `outcome` transitions to, outside of any step
+    /// Returns the name of the new state,
+    /// or None if it is not in the state machine or `outcome` does not match its Income
+    /// 
+    /// The replaced state is kept in history if `retain_old` is true, otherwise it is dropped
+    pub(crate) fn replace_state(
+        &self,
+        state: &mut Box<K::State<D>>,
+        history: &mut History<D, K>,
+        outcome: BoxedOutcome,
+        retain_old: bool,
+    ) -> Option<String> {
+        let (mut new_state, retained) = self.take_or_make_state(outcome.state_type(), history)?;
+        if self.enter_state(&mut new_state, outcome.data(), retained).is_err() {
+            if retained {
+                self.retain_state(new_state, history);
+            }
+            return None;
+        }
+        let old_state = std::mem::replace(state, new_state);
+        if retain_old {
+            self.retain_state(old_state, history);
+        }
+        Some(state.name())
+    }
+}
+
+
+/// The state machine used by a runner, which is either borrowed or shared
+enum MachineRef<'a, Data: 'static, K: Threading> {
+    Borrowed(&'a StateMachine<Data, K>),
+    Shared(Arc<StateMachine<Data, K>>),
+}
+
+impl<'a, D, K: Threading> Deref for MachineRef<'a, D, K> {
+    type Target = StateMachine<D, K>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            MachineRef::Borrowed(machine) => machine,
+            MachineRef::Shared(machine) => machine,
+        }
     }
 }
 
+/// A runner of a state machine, whose steps are reported through StepOutcome
+/// 
+/// Implemented by StateMachineRunner and OwnedStateMachineRunner
+pub trait Runner {
+    type Data: 'static;
+}
 
 /// The state machine runner runs an instance of a given StateMachine
-pub struct StateMachineRunner<'a, Data: 'static> {
-    machine: &'a StateMachine<Data>,
+/// 
+/// The runner borrows its StateMachine, see OwnedStateMachineRunner for a runner
+/// which shares ownership of it instead
+pub struct StateMachineRunner<'a, Data: 'static, K: Threading = Local> {
+    machine: MachineRef<'a, Data, K>,
     pub data: Data,
-    state: Box<dyn StateInternal<Data>>,
+    state: Box<K::State<Data>>,
+    history: History<Data, K>,
+    /// Each event along with the name of its type
+    events: VecDeque<(Box<K::Event>, &'static str)>,
+    /// The channel behind the event senders of the runner, created by the first event_sender
+    inbox: Option<(Sender<SentEvent>, Receiver<SentEvent>)>,
+    catch_panics: bool,
+    observers: Vec<Box<K::Hook<Data>>>,
+    deadline: Option<Instant>,
+    step_budget: Option<u64>,
+    steps: u64,
+    bail_out: Option<BailOut>,
 }
 
-impl<'a, D: fmt::Debug + 'static> fmt::Debug for StateMachineRunner<'a, D> {
+
+/// The state which a runner transitions to once it reaches one of its limits
+struct BailOut {
+    state: TypeId,
+    state_name: &'static str,
+    /// Converts the limit into the Income of the bail out state
+    income: fn(Limit) -> Box<dyn Any>,
+}
+
+impl<'a, D, K: Threading> Runner for StateMachineRunner<'a, D, K> {
+    type Data = D;
+}
+
+impl<'a, D: fmt::Debug + 'static, K: Threading> fmt::Debug for StateMachineRunner<'a, D, K> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("StateMachineRunner")
-            .field("machine", &self.machine)
+            .field("machine", &*self.machine)
             .field("data", &self.data)
-            .field("state", &self.state.type_id())
+            .field("state", &<K::State<D> as Any>::type_id(&*self.state))
+            .field("events", &self.events.len())
+            .field("catch_panics", &self.catch_panics)
+            .field("observers", &self.observers.len())
+            .field("deadline", &self.deadline)
+            .field("step_budget", &self.step_budget)
+            .field("steps", &self.steps)
             .finish()
     }
 }
 
-pub enum StepOutcome<'a, Data: 'static> {
+pub enum StepOutcome<R: Runner> {
     Continue {
-        machine: StateMachineRunner<'a, Data>,
+        machine: R,
     },
     Transition {
-        machine: StateMachineRunner<'a, Data>,
+        machine: R,
         start: String,
         transition: String,
         end: String,
     },
     Complete {
-        data: Data,
+        data: R::Data,
         start: String,
         transition: String,
     },
     StateNotFound {
+        data: R::Data,
         start: String,
         transition: String,
         end: TypeId,
     },
     IncorrectTransition {
+        data: R::Data,
         start: String,
         transition: String,
         end: String,
         expected_type: TypeId,
         received_data: Box<dyn Any>,
     },
+    /// The step would have ended in `error`,
+    /// but the state machine transitioned to its error state `end` instead
+    Recovered {
+        machine: R,
+        error: RunErrorKind,
+        end: String,
+    },
+    /// An interceptor vetoed the transition, so the state machine stayed in `start`
+    Vetoed {
+        machine: R,
+        start: String,
+        transition: String,
+    },
+    /// The current state `state` does not receive events of type `event`,
+    /// so the event was dropped without running any state method
+    Unhandled {
+        machine: R,
+        state: String,
+        event: String,
+    },
+    /// The runner reached `limit` before stepping `state`, and stopped after exiting `state`
+    LimitReached {
+        data: R::Data,
+        state: String,
+        limit: Limit,
+    },
+    /// The runner reached `limit` before stepping `start`,
+    /// and transitioned to its bail out state `end` instead
+    BailOut {
+        machine: R,
+        start: String,
+        limit: Limit,
+        end: String,
+    },
+    /// A method of a state panicked while panics were being caught by the runner
+    /// 
+    /// `state` is the state whose method panicked, and `data` may have been left
+    /// partially modified by that method
+    Panicked {
+        state: String,
+        message: String,
+        data: R::Data,
+    },
 }
 
-impl<'a, D> StepOutcome<'a, D> {
+impl<R: Runner> StepOutcome<R> {
     /// Returns false if and only if Self == StepOutcome::Continue
     pub fn is_notable(&self) -> bool {
         !matches!(self, StepOutcome::Continue { .. })
@@ -125,7 +906,10 @@ impl<'a, D> StepOutcome<'a, D> {
     }
 }
 
-impl<'a, D: fmt::Debug + 'static> fmt::Debug for StepOutcome<'a, D> {
+impl<R: Runner + fmt::Debug> fmt::Debug for StepOutcome<R>
+where
+    R::Data: fmt::Debug,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Continue { machine } => f
@@ -155,16 +939,19 @@ impl<'a, D: fmt::Debug + 'static> fmt::Debug for StepOutcome<'a, D> {
                 .field("transition", transition)
                 .finish(),
             Self::StateNotFound {
+                data,
                 start,
                 transition,
                 end,
             } => f
                 .debug_struct("StateNotFound")
+                .field("data", data)
                 .field("start", start)
                 .field("transition", transition)
                 .field("end", end)
                 .finish(),
             Self::IncorrectTransition {
+                data,
                 start,
                 transition,
                 end,
@@ -172,22 +959,81 @@ impl<'a, D: fmt::Debug + 'static> fmt::Debug for StepOutcome<'a, D> {
                 received_data,
             } => f
                 .debug_struct("IncorrectTransition")
+                .field("data", data)
                 .field("start", start)
                 .field("transition", transition)
                 .field("end", end)
                 .field("expected_type", expected_type)
                 .field("received_data", received_data)
                 .finish(),
-        }
-    }
-}
-
-impl<'a, D> Display for StepOutcome<'a, D> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            StepOutcome::Continue { .. } => {Ok(())}
-            StepOutcome::Transition {
-                start,
+            Self::Recovered {
+                machine,
+                error,
+                end,
+            } => f
+                .debug_struct("Recovered")
+                .field("machine", machine)
+                .field("error", error)
+                .field("end", end)
+                .finish(),
+            Self::Vetoed {
+                machine,
+                start,
+                transition,
+            } => f
+                .debug_struct("Vetoed")
+                .field("machine", machine)
+                .field("start", start)
+                .field("transition", transition)
+                .finish(),
+            Self::Unhandled {
+                machine,
+                state,
+                event,
+            } => f
+                .debug_struct("Unhandled")
+                .field("machine", machine)
+                .field("state", state)
+                .field("event", event)
+                .finish(),
+            Self::LimitReached { data, state, limit } => f
+                .debug_struct("LimitReached")
+                .field("data", data)
+                .field("state", state)
+                .field("limit", limit)
+                .finish(),
+            Self::BailOut {
+                machine,
+                start,
+                limit,
+                end,
+            } => f
+                .debug_struct("BailOut")
+                .field("machine", machine)
+                .field("start", start)
+                .field("limit", limit)
+                .field("end", end)
+                .finish(),
+            Self::Panicked {
+                state,
+                message,
+                data,
+            } => f
+                .debug_struct("Panicked")
+                .field("state", state)
+                .field("message", message)
+                .field("data", data)
+                .finish(),
+        }
+    }
+}
+
+impl<R: Runner> Display for StepOutcome<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepOutcome::Continue { .. } => {Ok(())}
+            StepOutcome::Transition {
+                start,
                 transition,
                 end,
                 ..
@@ -203,6 +1049,7 @@ impl<'a, D> Display for StepOutcome<'a, D> {
                 start,
                 transition,
                 end,
+                ..
             } => {
                 write!(f, "{start} --[{transition}]--> {end:?}? ABORT!").and(
                     write!(f, "Type {end:?} does not exist in the state machine"))
@@ -214,567 +1061,2526 @@ impl<'a, D> Display for StepOutcome<'a, D> {
                 end,
                 expected_type,
                 received_data,
+                ..
             } => {
                 write!(f, "{start} --[{transition}!]--> {end}").and(
                     write!(f, "{end} expected incoming data of type {expected_type:?} but received data of type {:?} from transition {transition}", (**received_data).type_id()))
             }
+            StepOutcome::Recovered { error, end, .. } => {
+                write!(f, "{error} RECOVERING --> {end}")
+            }
+            StepOutcome::Vetoed {
+                start, transition, ..
+            } => {
+                write!(f, "{start} --[{transition}]--X VETOED")
+            }
+            StepOutcome::Unhandled { state, event, .. } => {
+                write!(f, "{state} --[{event}]--X UNHANDLED")
+            }
+            StepOutcome::LimitReached { state, limit, .. } => {
+                write!(f, "{state} STOPPED! {limit}")
+            }
+            StepOutcome::BailOut {
+                start, limit, end, ..
+            } => {
+                write!(f, "{start} --[{limit}]--> {end}")
+            }
+            StepOutcome::Panicked { state, message, .. } => {
+                write!(f, "{state} PANICKED! {message}")
+            }
         }
     }
 }
 
-impl<'a, D> From<StepOutcome<'a, D>> for Result<StateMachineRunner<'a, D>, Option<D>> {
-    fn from(value: StepOutcome<'a, D>) -> Self {
-        match value {
-            StepOutcome::Continue { machine } => Ok(machine),
-            StepOutcome::Transition { machine, .. } => Ok(machine),
-            StepOutcome::Complete { data, .. } => Err(Some(data)),
-            StepOutcome::StateNotFound { .. } => Err(None),
-            StepOutcome::IncorrectTransition { .. } => Err(None),
-        }
-    }
+/// The result of stepping a state in place, without ownership of the runner or its data
+///
+/// Mirrors StepOutcome and is used where the data is only borrowed,
+/// such as by machines nested inside of other states
+#[derive(Debug)]
+pub enum StepReport {
+    Continue,
+    Transition {
+        start: String,
+        transition: String,
+        end: String,
+    },
+    Complete {
+        start: String,
+        transition: String,
+    },
+    StateNotFound {
+        start: String,
+        transition: String,
+        end: TypeId,
+    },
+    IncorrectTransition {
+        start: String,
+        transition: String,
+        end: String,
+        expected_type: TypeId,
+        received_data: Box<dyn Any>,
+    },
+    /// The step would have ended in `error`,
+    /// but the state machine transitioned to its error state instead
+    Recovered {
+        error: RunErrorKind,
+        end: String,
+    },
+    /// An interceptor vetoed the transition, so the state machine stayed in `start`
+    Vetoed {
+        start: String,
+        transition: String,
+    },
 }
 
-impl<'a, D> StateMachineRunner<'a, D> {
-    /// Create a state machine runner from the provided start state
-    /// Returns None if the provided start state is not present in the given state machine
-    pub fn new<Start: State>(
-        machine: &'a StateMachine<D>,
-        data: D,
-        start: Start::Income,
-    ) -> Option<Self> {
-        let state_id = TypeId::of::<Start>();
-        let mut state = machine.make_state(state_id)?;
-        state
-            .enter(Box::new(start))
-            .expect("Start::Income will always match Start transition expected data");
-        Some(Self {
-            machine,
-            data,
-            state,
-        })
+impl StepReport {
+    /// Returns false if and only if Self == StepReport::Continue
+    pub fn is_notable(&self) -> bool {
+        !matches!(self, StepReport::Continue)
     }
 
-    /// Perform one step of the state machine
-    /// Returns an outcome representing all possible outcomes of the step
-    pub fn step(mut self) -> StepOutcome<'a, D> {
-        let outcome = self.state.handle(&mut self.data);
-        if outcome.state_type() == <dyn StateInternal<_> as Any>::type_id(&*self.state) {
-            return StepOutcome::Continue { machine: self };
-        }
-        let start = self.state.name();
-        let transition = outcome.name();
-        let new_state_id = outcome.state_type();
-        if new_state_id == TypeId::of::<()>() {
-            return StepOutcome::Complete {
-                data: self.data,
+    /// Returns true if the report is StateNotFound or IncorrectTransition
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            StepReport::StateNotFound { .. } | StepReport::IncorrectTransition { .. }
+        )
+    }
+
+    /// Returns the error the step ran into, including errors which were recovered from
+    pub fn error_kind(&self) -> Option<RunErrorKind> {
+        match self {
+            StepReport::StateNotFound {
                 start,
                 transition,
-            };
+                end,
+            } => Some(RunErrorKind::StateNotFound {
+                start: start.clone(),
+                transition: transition.clone(),
+                end: *end,
+            }),
+            StepReport::IncorrectTransition {
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            } => Some(RunErrorKind::IncorrectTransition {
+                start: start.clone(),
+                transition: transition.clone(),
+                end: end.clone(),
+                expected_type: *expected_type,
+                received_type: (**received_data).type_id(),
+            }),
+            StepReport::Recovered { error, .. } => Some(error.clone()),
+            _ => None,
         }
-        let Some(state) = self.machine.make_state(new_state_id) else {
-            return StepOutcome::StateNotFound {
+    }
+}
+
+impl Display for StepReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepReport::Continue => Ok(()),
+            StepReport::Transition {
                 start,
                 transition,
-                end: new_state_id,
-            };
-        };
-        self.state = state;
-        let end = self.state.name();
-        match self.state.enter(outcome.data()) {
-            Ok(_) => StepOutcome::Transition {
-                machine: self,
+                end,
+            } => {
+                write!(f, "{start} --[{transition}]--> {end}")
+            }
+            StepReport::Complete { start, transition } => {
+                write!(f, "{start} --[{transition}]--> END")
+            }
+            StepReport::StateNotFound {
                 start,
                 transition,
                 end,
-            },
-            Err(data) => StepOutcome::IncorrectTransition {
+            } => {
+                write!(f, "{start} --[{transition}]--> {end:?}? ABORT!").and(
+                    write!(f, "Type {end:?} does not exist in the state machine"))
+            }
+            StepReport::IncorrectTransition {
                 start,
                 transition,
                 end,
-                expected_type: data.expected,
-                received_data: data.received,
-            },
-        }
-    }
-
-    /// Run the state machine until it either errors or completes
-    pub fn run_to_completion(mut self) -> Option<D> {
-        loop {
-            self = match self.step().into() {
-                Ok(machine) => machine,
-                Err(data) => return data,
+                expected_type,
+                received_data,
+            } => {
+                write!(f, "{start} --[{transition}!]--> {end}").and(
+                    write!(f, "{end} expected incoming data of type {expected_type:?} but received data of type {:?} from transition {transition}", (**received_data).type_id()))
             }
-        }
-    }
-
-    /// Run to completion but print all notable steps
-    pub fn run_to_completion_verbose(mut self) -> Option<D> {
-        loop {
-            let result = self.step();
-            result.print_if_notable();
-            self = match result.into() {
-                Ok(machine) => machine,
-                Err(data) => return data,
+            StepReport::Recovered { error, end } => {
+                write!(f, "{error} RECOVERING --> {end}")
+            }
+            StepReport::Vetoed { start, transition } => {
+                write!(f, "{start} --[{transition}]--X VETOED")
             }
         }
     }
 }
 
-/// Internal type used to represent possible missing state errors
-#[derive(Debug)]
-struct StateEntryError {
-    expected: TypeId,
-    received: Box<dyn Any>,
+/// A limit on how long a runner may run for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The deadline set with StateMachineRunner::set_deadline passed
+    Deadline(Instant),
+    /// The runner took every step of the budget set with StateMachineRunner::set_step_budget
+    StepBudget(u64),
 }
 
-impl StateEntryError {
-    fn from_any<T: 'static>(any: Box<dyn Any>) -> Self {
-        Self {
-            expected: TypeId::of::<T>(),
-            received: any,
+impl Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Deadline(_) => write!(f, "(Deadline)"),
+            Limit::StepBudget(steps) => write!(f, "(Step budget of {steps})"),
         }
     }
 }
 
-/// Internal representation of a state which is object safe without specifying the associated types
-trait StateInternal<Data>: Any {
-    fn enter(&mut self, meta: Box<dyn Any>) -> Result<(), StateEntryError>;
-    fn handle(&mut self, data: &mut Data) -> BoxedOutcome;
-    fn name(&self) -> String;
+/// The reason a state machine stopped without completing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunErrorKind {
+    /// The state machine transitioned to a state which is not in the state machine
+    StateNotFound {
+        start: String,
+        transition: String,
+        end: TypeId,
+    },
+    /// The state machine transitioned to a state with data which does not match its Income
+    IncorrectTransition {
+        start: String,
+        transition: String,
+        end: String,
+        expected_type: TypeId,
+        received_type: TypeId,
+    },
+    /// A method of a state panicked while panics were being caught by the runner
+    Panicked { state: String, message: String },
+    /// The runner reached a limit while it had no bail out state
+    LimitReached { state: String, limit: Limit },
+    /// The state machine was stopped from outside while in state, see StateMachineRunner::stop
+    Stopped { state: String },
 }
 
-/// The trait needed to represent a state in a state machine
-/// 
-/// Each type implementing State can only have one associated Data type,
-/// however allowing one State type to work with multiple data types is possible using
-/// a generic State type and a generic trait impl
-pub trait State: Default + 'static {
-    type Income: 'static;
-    type Transition: IntoOutcome;
-    type Data;
-
-    /// This method is run once when initially transitioning to a state
-    /// 
-    /// previous contains the data sent by the previous state through its Outcome
-    #[allow(unused)]
-    fn init(&mut self, previous: Box<Self::Income>) {}
-    /// This method contains the logic of the state and returns a transition to indicate
-    /// which state the state machine should go to next (which may include the current state)
-    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition;
-
-    /// This method returns a state name used for debugging and readability
-    /// 
-    /// The return value of this method is not used for logic anywhere in the state machine
-    fn name(&self) -> String {
-        type_name::<Self>().to_string()
+impl RunErrorKind {
+    /// The name of the state the state machine was in when the error occurred
+    pub fn state(&self) -> &str {
+        match self {
+            RunErrorKind::StateNotFound { start, .. }
+            | RunErrorKind::IncorrectTransition { start, .. } => start,
+            RunErrorKind::Panicked { state, .. }
+            | RunErrorKind::LimitReached { state, .. }
+            | RunErrorKind::Stopped { state } => state,
+        }
     }
 }
 
-impl<T, I, O, D> StateInternal<D> for T
-where
-    T: State<Income = I, Transition = O, Data = D>,
-    I: 'static,
-    O: IntoOutcome + 'static,
-{
-    fn enter(&mut self, meta: Box<dyn Any>) -> Result<(), StateEntryError> {
-        self.init(meta.downcast().map_err(StateEntryError::from_any::<I>)?);
-        Ok(())
+impl Display for RunErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunErrorKind::StateNotFound {
+                start,
+                transition,
+                end,
+            } => {
+                write!(f, "{start} --[{transition}]--> {end:?}? ABORT!").and(
+                    write!(f, "Type {end:?} does not exist in the state machine"))
+            }
+            RunErrorKind::IncorrectTransition {
+                start,
+                transition,
+                end,
+                expected_type,
+                received_type,
+            } => {
+                write!(f, "{start} --[{transition}!]--> {end}").and(
+                    write!(f, "{end} expected incoming data of type {expected_type:?} but received data of type {received_type:?} from transition {transition}"))
+            }
+            RunErrorKind::Panicked { state, message } => {
+                write!(f, "{state} PANICKED! {message}")
+            }
+            RunErrorKind::LimitReached { state, limit } => {
+                write!(f, "{state} STOPPED! {limit}")
+            }
+            RunErrorKind::Stopped { state } => write!(f, "{state} STOPPED!"),
+        }
     }
+}
 
-    fn handle(&mut self, data: &mut D) -> BoxedOutcome {
-        self.handle(data).into_outcome()
-    }
+/// The error returned when a state machine stops without completing
+/// 
+/// Contains the data of the state machine as it was when the error occurred
+pub struct RunError<Data> {
+    pub kind: RunErrorKind,
+    pub data: Data,
+}
 
-    fn name(&self) -> String {
-        <Self as State>::name(self)
+impl<D> RunError<D> {
+    /// Discards the data of the state machine
+    pub fn into_kind(self) -> RunErrorKind {
+        self.kind
     }
 }
 
-/// An Outcome type useful for transitioning from one state to itself
-#[derive(Debug, Default)]
-pub struct ContinueOutcome<T: State>(PhantomData<T>);
-
-/// An Outcome type useful for transiting from one state to another
-/// 
-/// Using this type as a Transition or a return from IntoOutcome ensures that
-/// the provided data is accurate to the type being transitioned to, 
-/// preventing one possible source of error
-#[derive(Debug)]
-pub struct OutcomeData<T: State>(T::Income, String);
-
-impl<T: State> Default for OutcomeData<T> where T::Income: Default {
-    fn default() -> Self {
-        Self::new(Default::default())
+// Manually implemented so that the error can be unwrapped for any data type
+impl<D> fmt::Debug for RunError<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunError")
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
     }
 }
 
-impl<T> OutcomeData<T>
-where
-    T: State,
-{
-    /// Construct a new outcome with the default name
-    pub fn new(data: T::Income) -> OutcomeData<T> {
-        OutcomeData(data, type_name::<T>().to_string())
+impl<D> Display for RunError<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)
     }
+}
 
-    pub const fn with_name(data: T::Income, name: String) -> OutcomeData<T> {
-        OutcomeData(data, name)
+impl<D> std::error::Error for RunError<D> {}
+
+impl<R: Runner> StepOutcome<R> {
+    /// Returns the runner if the state machine is still running,
+    /// otherwise returns the data of the completed state machine or the error it stopped with
+    #[allow(clippy::type_complexity)]
+    pub fn into_runner(self) -> Result<R, Result<R::Data, RunError<R::Data>>> {
+        let (kind, data) = match self {
+            StepOutcome::Continue { machine } => return Ok(machine),
+            StepOutcome::Transition { machine, .. } => return Ok(machine),
+            StepOutcome::Recovered { machine, .. } => return Ok(machine),
+            StepOutcome::BailOut { machine, .. } => return Ok(machine),
+            StepOutcome::Vetoed { machine, .. } => return Ok(machine),
+            StepOutcome::Unhandled { machine, .. } => return Ok(machine),
+            StepOutcome::Complete { data, .. } => return Err(Ok(data)),
+            StepOutcome::StateNotFound {
+                data,
+                start,
+                transition,
+                end,
+            } => (
+                RunErrorKind::StateNotFound {
+                    start,
+                    transition,
+                    end,
+                },
+                data,
+            ),
+            StepOutcome::IncorrectTransition {
+                data,
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            } => (
+                RunErrorKind::IncorrectTransition {
+                    start,
+                    transition,
+                    end,
+                    expected_type,
+                    received_type: (*received_data).type_id(),
+                },
+                data,
+            ),
+            StepOutcome::Panicked {
+                state,
+                message,
+                data,
+            } => (RunErrorKind::Panicked { state, message }, data),
+            StepOutcome::LimitReached { data, state, limit } => {
+                (RunErrorKind::LimitReached { state, limit }, data)
+            }
+        };
+        Err(Err(RunError { kind, data }))
+    }
+
+    /// Replaces the runner held by the outcome, if any, with `map` applied to it
+    pub(crate) fn map_runner<T: Runner<Data = R::Data>>(
+        self,
+        map: impl FnOnce(R) -> T,
+    ) -> StepOutcome<T> {
+        match self {
+            StepOutcome::Continue { machine } => StepOutcome::Continue {
+                machine: map(machine),
+            },
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => StepOutcome::Transition {
+                machine: map(machine),
+                start,
+                transition,
+                end,
+            },
+            StepOutcome::Complete {
+                data,
+                start,
+                transition,
+            } => StepOutcome::Complete {
+                data,
+                start,
+                transition,
+            },
+            StepOutcome::StateNotFound {
+                data,
+                start,
+                transition,
+                end,
+            } => StepOutcome::StateNotFound {
+                data,
+                start,
+                transition,
+                end,
+            },
+            StepOutcome::IncorrectTransition {
+                data,
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            } => StepOutcome::IncorrectTransition {
+                data,
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            },
+            StepOutcome::Recovered {
+                machine,
+                error,
+                end,
+            } => StepOutcome::Recovered {
+                machine: map(machine),
+                error,
+                end,
+            },
+            StepOutcome::Vetoed {
+                machine,
+                start,
+                transition,
+            } => StepOutcome::Vetoed {
+                machine: map(machine),
+                start,
+                transition,
+            },
+            StepOutcome::Unhandled {
+                machine,
+                state,
+                event,
+            } => StepOutcome::Unhandled {
+                machine: map(machine),
+                state,
+                event,
+            },
+            StepOutcome::LimitReached { data, state, limit } => {
+                StepOutcome::LimitReached { data, state, limit }
+            }
+            StepOutcome::BailOut {
+                machine,
+                start,
+                limit,
+                end,
+            } => StepOutcome::BailOut {
+                machine: map(machine),
+                start,
+                limit,
+                end,
+            },
+            StepOutcome::Panicked {
+                state,
+                message,
+                data,
+            } => StepOutcome::Panicked {
+                state,
+                message,
+                data,
+            },
+        }
     }
 }
 
-/// If data does not return the incoming data associated with the state corresponding
-/// to the result of state_type then the state machine will return an error
-pub trait Outcome {
-    /// The typeid of the next state
-    fn state_type(&self) -> TypeId;
+impl<R: Runner> From<StepOutcome<R>> for Result<R, Option<R::Data>> {
+    fn from(value: StepOutcome<R>) -> Self {
+        value.into_runner().map_err(Result::ok)
+    }
+}
 
-    /// If transitioning to a new state, this method is called to provide the `Income` data
+/// Extracts the message of a caught panic from its payload
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+impl<'a, D, K: Threading> StateMachineRunner<'a, D, K> {
+    /// Create a state machine runner from the provided start state
+    /// Returns None if the provided start state is not present in the given state machine
+    pub fn new<Start: State>(
+        machine: &'a StateMachine<D, K>,
+        data: D,
+        start: Start::Income,
+    ) -> Option<Self> {
+        Self::from_machine::<Start>(MachineRef::Borrowed(machine), data, start)
+    }
+
+    /// Create a state machine runner from the provided start state with catch_panics enabled
     /// 
-    /// If transitioning from one state to itself, this method is not called,
-    /// so its return value is arbitrary
-    fn data(self: Box<Self>) -> Box<dyn Any>;
+    /// A panic in the init method of the start state is caught as well,
+    /// returning the data in a RunError of kind RunErrorKind::Panicked.
+    /// Returns None if the provided start state is not present in the given state machine
+    pub fn new_catching_panics<Start: State>(
+        machine: &'a StateMachine<D, K>,
+        data: D,
+        start: Start::Income,
+    ) -> Option<Result<Self, RunError<D>>> {
+        Self::from_machine_catching_panics::<Start>(MachineRef::Borrowed(machine), data, start)
+    }
 
-    /// This method returns a state name used for debugging and readability
+    /// Create a state machine runner starting in the state with TypeId `start`
+    /// Returns None if the start state is not present in the state machine
+    /// or if `income` is not its Income
+    pub(crate) fn with_start(
+        machine: &'a StateMachine<D, K>,
+        data: D,
+        start: TypeId,
+        income: Box<dyn Any>,
+    ) -> Option<Self> {
+        let mut history = History::default();
+        let state = machine.start_state_with(start, &mut history, income)?.ok()?;
+        Some(Self::started(MachineRef::Borrowed(machine), data, state, history))
+    }
+
+    fn from_machine<Start: State>(
+        machine: MachineRef<'a, D, K>,
+        data: D,
+        start: Start::Income,
+    ) -> Option<Self> {
+        let mut history = History::default();
+        let state = machine.start_state::<Start>(&mut history, start)?;
+        Some(Self::started(machine, data, state, history))
+    }
+
+    fn from_machine_catching_panics<Start: State>(
+        machine: MachineRef<'a, D, K>,
+        data: D,
+        start: Start::Income,
+    ) -> Option<Result<Self, RunError<D>>> {
+        let name = machine.registration(TypeId::of::<Start>())?.name.clone();
+        let mut history = History::default();
+        let started = panic::catch_unwind(AssertUnwindSafe(|| {
+            machine.start_state::<Start>(&mut history, start)
+        }));
+        match started {
+            Ok(state) => {
+                let mut runner = Self::started(machine, data, state?, history);
+                runner.catch_panics = true;
+                Some(Ok(runner))
+            }
+            Err(payload) => Some(Err(RunError {
+                kind: RunErrorKind::Panicked {
+                    state: name,
+                    message: panic_message(&*payload),
+                },
+                data,
+            })),
+        }
+    }
+
+    fn started(
+        machine: MachineRef<'a, D, K>,
+        data: D,
+        state: Box<K::State<D>>,
+        history: History<D, K>,
+    ) -> Self {
+        Self {
+            machine,
+            data,
+            state,
+            history,
+            events: VecDeque::new(),
+            inbox: None,
+            catch_panics: false,
+            observers: Vec::new(),
+            deadline: None,
+            step_budget: None,
+            steps: 0,
+            bail_out: None,
+        }
+    }
+
+    /// Sets a deadline after which the runner stops stepping its states
     /// 
-    /// The return value of this method is not used for logic anywhere in the state machine
-    fn name(&self) -> String {
-        type_name::<Self>().to_string()
+    /// Limits are checked before every step and before every event is processed.
+    /// Once a limit is reached, the current state is exited
+    /// and the runner either stops with StepOutcome::LimitReached or,
+    /// if a bail out state was set, transitions to it with StepOutcome::BailOut
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Sets the maximum number of steps the runner takes, counting from its creation
+    /// 
+    /// Events processed with process_event do not count as steps,
+    /// but no event is processed once the budget is spent.
+    /// See set_deadline for what happens once the budget is spent
+    pub fn set_step_budget(&mut self, steps: u64) {
+        self.step_budget = Some(steps);
+    }
+
+    /// Sets the state which the runner transitions to once it reaches a limit
+    /// 
+    /// T is entered with the limit converted into its Income.
+    /// The transition to T is intercepted like any other,
+    /// and the runner stops with StepOutcome::LimitReached if it is vetoed.
+    /// Both limits are cleared once the runner bails out,
+    /// so that the bail out state may run until the state machine completes.
+    /// Returns false if T is not in the state machine
+    pub fn set_bail_out_state<T: State<Data = D>>(&mut self) -> bool
+    where
+        T::Income: From<Limit>,
+    {
+        if self.machine.registration(TypeId::of::<T>()).is_none() {
+            return false;
+        }
+        self.bail_out = Some(BailOut {
+            state: TypeId::of::<T>(),
+            state_name: type_name::<T>(),
+            income: |limit| Box::new(T::Income::from(limit)),
+        });
+        true
+    }
+
+    /// Returns the limit which the runner has reached, if any
+    fn reached_limit(&self) -> Option<Limit> {
+        if let Some(steps) = self.step_budget.filter(|steps| self.steps >= *steps) {
+            return Some(Limit::StepBudget(steps));
+        }
+        self.deadline
+            .filter(|deadline| Instant::now() >= *deadline)
+            .map(Limit::Deadline)
+    }
+
+    /// Exits the current state and either stops or bails out after reaching `limit`
+    /// 
+    /// The runner stops as if it had no bail out state
+    /// if the transition to the bail out state is vetoed by an interceptor.
+    /// Panics while leaving the current state or entering the bail out state
+    /// are caught like those of a step
+    fn reach_limit(mut self, limit: Limit) -> StepOutcome<Self> {
+        let start = self.state.name();
+        let bailed_out = self.catching_panics(|runner| {
+            let Some(bail_out) = runner.bail_out.take() else {
+                runner.state.exit(&mut runner.data);
+                return None;
+            };
+            runner.machine.leave_state(&mut *runner.state, &mut runner.data);
+            runner.bail_out(bail_out, limit)
+        });
+        let bailed_out = match bailed_out {
+            Ok(bailed_out) => bailed_out,
+            Err(payload) => return self.into_panicked(payload),
+        };
+        let at = Instant::now();
+        let Some((transition, end)) = bailed_out else {
+            for observer in &mut self.observers {
+                observer.limit(&start, limit, None, at, &self.data);
+            }
+            return StepOutcome::LimitReached {
+                data: self.data,
+                state: start,
+                limit,
+            };
+        };
+        self.deadline = None;
+        self.step_budget = None;
+        for observer in &mut self.observers {
+            observer.limit(&start, limit, Some((&transition, &end)), at, &self.data);
+        }
+        StepOutcome::BailOut {
+            machine: self,
+            start,
+            limit,
+            end,
+        }
+    }
+
+    /// Stops the state machine outside of any step, after running the exit of the current state
+    /// 
+    /// Returns the data along with RunErrorKind::Stopped,
+    /// or with RunErrorKind::Panicked if the exit panicked while panics were being caught
+    pub fn stop(mut self) -> RunError<D> {
+        let state = self.state.name();
+        if let Err(payload) = self.catching_panics(|runner| runner.state.exit(&mut runner.data)) {
+            let Err(Err(error)) = self.into_panicked(payload).into_runner() else {
+                unreachable!("A runner which panicked has stopped with an error");
+            };
+            return error;
+        }
+        let kind = RunErrorKind::Stopped { state };
+        let at = Instant::now();
+        for observer in &mut self.observers {
+            observer.error(&kind, at, &self.data);
+        }
+        RunError {
+            kind,
+            data: self.data,
+        }
+    }
+
+    /// Replaces the current state with the bail out state, or with the state an interceptor
+    /// redirected to
+    /// Returns the name of the transition and of the new state,
+    /// or None if the transition was vetoed or the new state could not be entered
+    fn bail_out(&mut self, bail_out: BailOut, limit: Limit) -> Option<(String, String)> {
+        let start = <K::State<D> as Any>::type_id(&*self.state);
+        let outcome = Box::new(Replacement {
+            state: bail_out.state,
+            data: (bail_out.income)(limit),
+            name: limit.to_string(),
+        });
+        let outcome = self.machine.intercept(start, outcome, &self.data).ok()?;
+        let transition = outcome.name();
+        let end = self
+            .machine
+            .replace_state(&mut self.state, &mut self.history, outcome, true)?;
+        Some((transition, end))
+    }
+
+    /// Returns the TypeId and type name of the bail out state, see set_bail_out_state
+    pub(crate) fn bail_out_state(&self) -> Option<(TypeId, &'static str)> {
+        self.bail_out
+            .as_ref()
+            .map(|bail_out| (bail_out.state, bail_out.state_name))
+    }
+
+    pub(crate) fn machine(&self) -> &StateMachine<D, K> {
+        &self.machine
+    }
+
+    pub(crate) fn add_step_hook(&mut self, hook: Box<K::Hook<D>>) {
+        self.observers.push(hook);
+    }
+
+    /// Sets whether panics in the methods of states are caught by the runner
+    /// 
+    /// While enabled, a panic during a step ends the state machine with StepOutcome::Panicked,
+    /// which returns the data instead of unwinding through the caller.
+    /// The exit method of the panicking state is not run.
+    /// Disabled by default
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.catch_panics = catch_panics;
+    }
+
+    /// Runs `f` against the runner, catching any panic if catch_panics is enabled
+    fn catching_panics<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> Result<T, Box<dyn Any + Send>> {
+        if self.catch_panics {
+            panic::catch_unwind(AssertUnwindSafe(|| f(self)))
+        } else {
+            Ok(f(self))
+        }
+    }
+
+    /// Runs `step` against the runner, catching any panic if catch_panics is enabled
+    /// 
+    /// `event` is the type name of the event delivered by the step, if any
+    fn catch_step(
+        mut self,
+        event: Option<&str>,
+        step: impl FnOnce(&mut Self) -> Option<StepReport>,
+    ) -> StepOutcome<Self> {
+        let report = match self.catching_panics(step) {
+            Ok(report) => report,
+            Err(payload) => return self.into_panicked(payload),
+        };
+        match report {
+            Some(report) => self.into_outcome(report, event),
+            None => StepOutcome::Continue { machine: self },
+        }
+    }
+
+    /// Ends the runner after a state panicked with `payload`
+    /// 
+    /// The current state is the one which panicked,
+    /// since a state is only replaced by a new state just before the new state is entered
+    fn into_panicked(mut self, payload: Box<dyn Any + Send>) -> StepOutcome<Self> {
+        let message = panic_message(&*payload);
+        let state = self.state.name();
+        if !self.observers.is_empty() {
+            let error = RunErrorKind::Panicked {
+                state: state.clone(),
+                message: message.clone(),
+            };
+            let at = Instant::now();
+            for observer in &mut self.observers {
+                observer.error(&error, at, &self.data);
+            }
+        }
+        StepOutcome::Panicked {
+            state,
+            message,
+            data: self.data,
+        }
+    }
+
+    /// Returns the name of the current state of the runner
+    pub fn state_name(&self) -> String {
+        self.state.name()
+    }
+
+    /// Returns the number of steps the runner has taken,
+    /// not counting events processed with process_event
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Returns the number of events which have been dispatched but not yet processed
+    /// 
+    /// Events sent through an EventSender are only counted once the runner received them
+    /// at the start of a step or of process_event
+    pub fn pending_events(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns a handle which sends events to the runner from any thread
+    /// 
+    /// Sent events are added to the back of the event queue at the start of every step
+    /// and of every process_event, in the order in which they were received.
+    /// All senders of a runner share the same channel
+    pub fn event_sender(&mut self) -> EventSender {
+        let (sender, _) = self.inbox.get_or_insert_with(mpsc::channel);
+        EventSender(sender.clone())
+    }
+
+    /// Moves the events received through the event senders of the runner to its event queue
+    fn receive_sent_events(&mut self) {
+        if let Some((_, receiver)) = &self.inbox {
+            for (event, name) in receiver.try_iter() {
+                self.events.push_back((K::sent_event(event), name));
+            }
+        }
+    }
+
+    /// Delivers the event at the front of the event queue to the current state
+    /// 
+    /// If the queue is empty, no state method is run and the outcome is Continue.
+    /// If the current state was not added with add_event_state for the type of the event,
+    /// the event is dropped without running any state method and the outcome is Unhandled.
+    /// Limits are checked like they are by step, leaving the event in the queue if one was reached
+    pub fn process_event(mut self) -> StepOutcome<Self> {
+        self.receive_sent_events();
+        if let Some(limit) = self.reached_limit() {
+            return self.reach_limit(limit);
+        }
+        let Some((event, event_name)) = self.events.pop_front() else {
+            return StepOutcome::Continue { machine: self };
+        };
+        let event = K::event(&*event);
+        if !self.machine.receives_event(&*self.state, event.type_id()) {
+            return StepOutcome::Unhandled {
+                state: self.state.name(),
+                event: event_name.to_string(),
+                machine: self,
+            };
+        }
+        self.catch_step(Some(event_name), |runner| {
+            runner
+                .machine
+                .step_event(&mut runner.state, &mut runner.history, event, &mut runner.data)
+        })
+    }
+
+    /// Perform one step of the state machine
+    /// Returns an outcome representing all possible outcomes of the step
+    pub fn step(mut self) -> StepOutcome<Self> {
+        self.receive_sent_events();
+        if let Some(limit) = self.reached_limit() {
+            return self.reach_limit(limit);
+        }
+        self.steps += 1;
+        self.catch_step(None, |runner| {
+            Some(
+                runner
+                    .machine
+                    .step_state(&mut runner.state, &mut runner.history, &mut runner.data),
+            )
+        })
+    }
+
+    /// Perform one step of the state machine, awaiting the handle of the current state
+    pub(crate) async fn step_async(mut self) -> StepOutcome<Self> {
+        self.receive_sent_events();
+        if let Some(limit) = self.reached_limit() {
+            return self.reach_limit(limit);
+        }
+        self.steps += 1;
+        let catch_panics = self.catch_panics;
+        let result = {
+            let mut step = pin!(self.machine.step_state_async(
+                &mut self.state,
+                &mut self.history,
+                &mut self.data
+            ));
+            poll_fn(|cx| {
+                if !catch_panics {
+                    return step.as_mut().poll(cx).map(Ok);
+                }
+                match panic::catch_unwind(AssertUnwindSafe(|| step.as_mut().poll(cx))) {
+                    Ok(poll) => poll.map(Ok),
+                    Err(payload) => Poll::Ready(Err(payload)),
+                }
+            })
+            .await
+        };
+        match result {
+            Ok(report) => self.into_outcome(report, None),
+            Err(payload) => self.into_panicked(payload),
+        }
+    }
+
+    /// Notifies the observers of the runner of a step which delivered `event` if any,
+    /// then attaches the runner or its data to the report of the step
+    fn into_outcome(mut self, report: StepReport, event: Option<&str>) -> StepOutcome<Self> {
+        if !self.observers.is_empty() {
+            let state = self.state.name();
+            let at = Instant::now();
+            for observer in &mut self.observers {
+                observer.step(&report, &state, event, at, &self.data);
+            }
+        }
+        match report {
+            StepReport::Continue => StepOutcome::Continue { machine: self },
+            StepReport::Transition {
+                start,
+                transition,
+                end,
+            } => StepOutcome::Transition {
+                machine: self,
+                start,
+                transition,
+                end,
+            },
+            StepReport::Complete { start, transition } => StepOutcome::Complete {
+                data: self.data,
+                start,
+                transition,
+            },
+            StepReport::StateNotFound {
+                start,
+                transition,
+                end,
+            } => StepOutcome::StateNotFound {
+                data: self.data,
+                start,
+                transition,
+                end,
+            },
+            StepReport::IncorrectTransition {
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            } => StepOutcome::IncorrectTransition {
+                data: self.data,
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            },
+            StepReport::Recovered { error, end } => StepOutcome::Recovered {
+                machine: self,
+                error,
+                end,
+            },
+            StepReport::Vetoed { start, transition } => StepOutcome::Vetoed {
+                machine: self,
+                start,
+                transition,
+            },
+        }
+    }
+
+    /// Run the state machine until it either errors or completes
+    pub fn run_to_completion(mut self) -> Result<D, RunError<D>> {
+        loop {
+            self = match self.step().into_runner() {
+                Ok(machine) => machine,
+                Err(result) => return result,
+            }
+        }
+    }
+
+    /// Run to completion but print all notable steps
+    pub fn run_to_completion_verbose(mut self) -> Result<D, RunError<D>> {
+        loop {
+            let result = self.step();
+            result.print_if_notable();
+            self = match result.into_runner() {
+                Ok(machine) => machine,
+                Err(result) => return result,
+            }
+        }
+    }
+}
+
+impl<'a, D> StateMachineRunner<'a, D> {
+    /// Adds an event to the back of the event queue of the runner
+    /// 
+    /// Events are only delivered by process_event, never by step
+    pub fn dispatch<E: 'static>(&mut self, event: E) {
+        self.events.push_back((Box::new(event), type_name::<E>()));
+    }
+}
+
+impl<'a, D> StateMachineRunner<'a, D, Sendable> {
+    /// Adds an event to the back of the event queue of the runner,
+    /// which must be Send since the runner may be sent to another thread
+    /// 
+    /// Otherwise behaves like the dispatch of a Local runner
+    pub fn dispatch<E: Send + 'static>(&mut self, event: E) {
+        self.events.push_back((Box::new(event), type_name::<E>()));
+    }
+}
+
+/// An event sent through an EventSender along with the name of its type
+type SentEvent = (Box<dyn Any + Send>, &'static str);
+
+/// A cloneable handle which sends events to a runner from any thread,
+/// see StateMachineRunner::event_sender
+#[derive(Debug, Clone)]
+pub struct EventSender(Sender<SentEvent>);
+
+impl EventSender {
+    /// Sends an event to the runner, which adds it to its event queue at the start of its next step
+    /// Returns false if the runner has been dropped
+    pub fn send<E: Send + 'static>(&self, event: E) -> bool {
+        self.0.send((Box::new(event), type_name::<E>())).is_ok()
+    }
+}
+
+/// A state machine runner which shares ownership of its StateMachine through an Arc
+/// 
+/// Unlike a runner borrowing its machine, an owned runner can be stored next to the machine,
+/// returned from a constructor, or kept for the lifetime of a long running service.
+/// The owned runner of a Sendable state machine is Send if its data is,
+/// so it can also be moved to another thread
+/// 
+/// The methods which take the runner by value are the same as those of StateMachineRunner,
+/// which the owned runner dereferences to for all other methods
+pub struct OwnedStateMachineRunner<Data: 'static, K: Threading = Local>(
+    StateMachineRunner<'static, Data, K>,
+);
+
+impl<D, K: Threading> Runner for OwnedStateMachineRunner<D, K> {
+    type Data = D;
+}
+
+impl<D: fmt::Debug + 'static, K: Threading> fmt::Debug for OwnedStateMachineRunner<D, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<D, K: Threading> Deref for OwnedStateMachineRunner<D, K> {
+    type Target = StateMachineRunner<'static, D, K>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<D, K: Threading> DerefMut for OwnedStateMachineRunner<D, K> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<D, K: Threading> OwnedStateMachineRunner<D, K> {
+    /// Create a state machine runner which shares ownership of the given state machine
+    /// Returns None if the provided start state is not present in the given state machine
+    pub fn new<Start: State>(
+        machine: Arc<StateMachine<D, K>>,
+        data: D,
+        start: Start::Income,
+    ) -> Option<Self> {
+        StateMachineRunner::from_machine::<Start>(MachineRef::Shared(machine), data, start)
+            .map(Self)
+    }
+
+    /// Create a state machine runner which shares ownership of the given state machine
+    /// with catch_panics enabled, see StateMachineRunner::new_catching_panics
+    pub fn new_catching_panics<Start: State>(
+        machine: Arc<StateMachine<D, K>>,
+        data: D,
+        start: Start::Income,
+    ) -> Option<Result<Self, RunError<D>>> {
+        let runner = StateMachineRunner::from_machine_catching_panics::<Start>(
+            MachineRef::Shared(machine),
+            data,
+            start,
+        )?;
+        Some(runner.map(Self))
+    }
+
+    /// Delivers the event at the front of the event queue to the current state,
+    /// see StateMachineRunner::process_event
+    pub fn process_event(self) -> StepOutcome<Self> {
+        self.0.process_event().map_runner(Self)
+    }
+
+    /// Perform one step of the state machine
+    /// Returns an outcome representing all possible outcomes of the step
+    pub fn step(self) -> StepOutcome<Self> {
+        self.0.step().map_runner(Self)
+    }
+
+    /// Stops the state machine outside of any step, see StateMachineRunner::stop
+    pub fn stop(self) -> RunError<D> {
+        self.0.stop()
+    }
+
+    /// Run the state machine until it either errors or completes
+    pub fn run_to_completion(self) -> Result<D, RunError<D>> {
+        self.0.run_to_completion()
+    }
+
+    /// Run to completion but print all notable steps
+    pub fn run_to_completion_verbose(self) -> Result<D, RunError<D>> {
+        self.0.run_to_completion_verbose()
+    }
+}
+
+
+/// The trait needed to represent a state in a state machine
+/// 
+/// Each type implementing State can only have one associated Data type,
+/// however allowing one State type to work with multiple data types is possible using
+/// a generic State type and a generic trait impl
+pub trait State: Default + 'static {
+    type Income: 'static;
+    type Transition: IntoOutcome;
+    type Data;
+
+    /// This method is run once when initially transitioning to a state
+    /// 
+    /// previous contains the data sent by the previous state through its Outcome
+    #[allow(unused, clippy::boxed_local)]
+    fn init(&mut self, previous: Box<Self::Income>) {}
+    /// This method is run instead of init when transitioning to a state with Retention::Persistent
+    /// whose instance was kept from a previous visit
+    /// 
+    /// The default implementation runs init, so states which should pick up where they left off
+    /// must override this method
+    fn resume(&mut self, previous: Box<Self::Income>) {
+        self.init(previous);
+    }
+    /// This method contains the logic of the state and returns a transition to indicate
+    /// which state the state machine should go to next (which may include the current state)
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run once when transitioning away from a state
+    /// 
+    /// It is run for every outcome which leaves the state, including transitions to `()`
+    /// and transitions which fail, before the state is dropped.
+    /// States whose instance is kept by Retention::Persistent run suspend instead
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
+    /// This method is run instead of exit when transitioning away from a state
+    /// with Retention::Persistent, whose instance is kept for its next visit
+    /// 
+    /// The default implementation runs exit
+    fn suspend(&mut self, data: &mut Self::Data) {
+        self.exit(data);
+    }
+
+    /// This method is used in place of handle when the state machine is run asynchronously
+    /// 
+    /// The default implementation runs handle, which is correct for all synchronous states;
+    /// AsyncStateStruct overrides it to await the wrapped AsyncState
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(std::future::ready(self.handle(data)))
+    }
+
+    /// This method returns a state name used for debugging and readability
+    /// 
+    /// The return value of this method is not used for logic anywhere in the state machine
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+
+    /// The transitions this state may take, used by StateMachine::validate
+    /// 
+    /// Returns None if the transitions of this state are not declared.
+    /// The default implementation returns the transitions declared by the Transition type
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        Self::Transition::declared_transitions()
+    }
+}
+
+impl<T, I, O, D> StateInternal<D> for T
+where
+    T: State<Income = I, Transition = O, Data = D>,
+    I: 'static,
+    O: IntoOutcome + 'static,
+{
+    fn enter(&mut self, meta: Box<dyn Any>, adapters: &IncomeAdapters) -> Result<(), StateEntryError> {
+        let meta = adapters.adapt::<I>(meta);
+        self.init(meta.downcast().map_err(StateEntryError::from_any::<I>)?);
+        Ok(())
+    }
+
+    fn resume(&mut self, meta: Box<dyn Any>, adapters: &IncomeAdapters) -> Result<(), StateEntryError> {
+        let meta = adapters.adapt::<I>(meta);
+        State::resume(self, meta.downcast().map_err(StateEntryError::from_any::<I>)?);
+        Ok(())
+    }
+
+    fn handle(&mut self, data: &mut D) -> BoxedOutcome {
+        self.handle(data).into_outcome()
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut D) -> BoxedFuture<'s, BoxedOutcome> {
+        Box::pin(async move { State::handle_async(self, data).await.into_outcome() })
+    }
+
+    fn exit(&mut self, data: &mut D) {
+        State::exit(self, data)
+    }
+
+    fn suspend(&mut self, data: &mut D) {
+        State::suspend(self, data)
+    }
+
+    fn name(&self) -> String {
+        <Self as State>::name(self)
+    }
+}
+
+/// The trait needed for a state to receive events dispatched to a StateMachineRunner
+/// 
+/// States which implement EventState must be added with StateMachine::add_event_state
+/// to receive events of type E
+pub trait EventState<E>: State {
+    /// This method is run when an event of type E is processed while this is the current state,
+    /// and its transition is applied in the same way as the transition returned by handle
+    fn on_event(&mut self, event: &E, data: &mut Self::Data) -> Self::Transition;
+}
+
+fn deliver_event<T, E, D>(state: &mut dyn StateInternal<D>, event: &dyn Any, data: &mut D) -> BoxedOutcome
+where
+    T: EventState<E, Data = D>,
+    E: 'static,
+{
+    let state = (state as &mut dyn Any)
+        .downcast_mut::<T>()
+        .expect("Event handlers are only called for the state they were registered with");
+    let event = event
+        .downcast_ref::<E>()
+        .expect("Event handlers are only called for the event type they were registered with");
+    state.on_event(event, data).into_outcome()
+}
+
+/// An Outcome type useful for transitioning from one state to itself
+#[derive(Debug, Default)]
+pub struct ContinueOutcome<T: State>(PhantomData<T>);
+
+/// An Outcome type useful for transiting from one state to another
+/// 
+/// Using this type as a Transition or a return from IntoOutcome ensures that
+/// the provided data is accurate to the type being transitioned to, 
+/// preventing one possible source of error
+#[derive(Debug)]
+pub struct OutcomeData<T: State>(T::Income, String);
+
+impl<T: State> Default for OutcomeData<T> where T::Income: Default {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T> OutcomeData<T>
+where
+    T: State,
+{
+    /// Construct a new outcome with the default name
+    pub fn new(data: T::Income) -> OutcomeData<T> {
+        OutcomeData(data, type_name::<T>().to_string())
+    }
+
+    pub const fn with_name(data: T::Income, name: String) -> OutcomeData<T> {
+        OutcomeData(data, name)
+    }
+}
+
+/// If data does not return the incoming data associated with the state corresponding
+/// to the result of state_type then the state machine will return an error
+pub trait Outcome {
+    /// The typeid of the next state
+    fn state_type(&self) -> TypeId;
+
+    /// If transitioning to a new state, this method is called to provide the `Income` data
+    /// 
+    /// If transitioning from one state to itself, this method is not called,
+    /// so its return value is arbitrary
+    fn data(self: Box<Self>) -> Box<dyn Any>;
+
+    /// This method returns a state name used for debugging and readability
+    /// 
+    /// The return value of this method is not used for logic anywhere in the state machine,
+    /// except to look up the wiring of Wired outcomes
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+
+    /// The transitions this outcome type may produce, used by StateMachine::validate
+    /// 
+    /// Returns None if the transitions of this outcome type are not declared
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+/// A transition which a state declares it may take
+/// 
+/// Transitions to the declaring state itself are treated as continuing in that state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeclaredTransition {
+    /// The name of the outcome, as returned by Outcome::name
+    pub name: String,
+    /// The TypeId of the state being transitioned to
+    pub target: TypeId,
+    pub target_name: &'static str,
+    /// The TypeId of the data returned by Outcome::data
+    pub income: TypeId,
+    pub income_name: &'static str,
+}
+
+impl DeclaredTransition {
+    /// A transition to T which provides T::Income, such as an OutcomeData<T>
+    pub fn to<T: State>(name: impl Into<String>) -> Self {
+        Self::with_income::<T, T::Income>(name)
+    }
+
+    /// A transition to T which provides data of type I
+    pub fn with_income<T: State, I: 'static>(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            target: TypeId::of::<T>(),
+            target_name: type_name::<T>(),
+            income: TypeId::of::<I>(),
+            income_name: type_name::<I>(),
+        }
+    }
+
+    /// The transition to `()` which completes the state machine
+    pub fn complete() -> Self {
+        Self {
+            name: ().name(),
+            target: TypeId::of::<()>(),
+            target_name: type_name::<()>(),
+            income: TypeId::of::<()>(),
+            income_name: type_name::<()>(),
+        }
+    }
+
+    /// Returns true if this is the transition to `()`
+    pub fn is_complete(&self) -> bool {
+        self.target == TypeId::of::<()>()
+    }
+}
+
+pub type BoxedOutcome = Box<dyn Outcome>;
+
+pub type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+impl Outcome for BoxedOutcome {
+    fn state_type(&self) -> TypeId {
+        (**self).state_type()
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        (*self).data()
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+}
+
+/// This trait defines which outcome a transition follows
+/// 
+/// IntoOutcome is blanket implemented for all types which implement Outcome,
+/// so for states with only a single possible transition, using an Outcome type
+/// directly for the transition is likely preferred
+pub trait IntoOutcome {
+    fn into_outcome(self) -> BoxedOutcome;
+
+    /// The transitions this type may produce, used by StateMachine::validate
+    /// 
+    /// Returns None if the transitions of this type are not declared
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+/// The singular success endpoint for all state machines
+/// 
+/// All end states or transitions must return () as their transition or outcome
+impl Outcome for () {
+    fn state_type(&self) -> TypeId {
+        TypeId::of::<()>()
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        Box::new(())
+    }
+
+    fn name(&self) -> String {
+        "(Complete)".to_string()
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        Some(vec![DeclaredTransition::complete()])
+    }
+}
+
+impl<T> Outcome for OutcomeData<T>
+where
+    T: State,
+{
+    fn state_type(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        Box::new(self.0)
+    }
+
+    fn name(&self) -> String {
+        self.1.clone()
+    }
+
+    /// Only the default name is declared, since names given with_name are only known at runtime
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        Some(vec![DeclaredTransition::to::<T>(type_name::<T>())])
+    }
+}
+
+impl<T> Outcome for ContinueOutcome<T>
+where
+    T: State,
+{
+    fn state_type(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        // Since this outcome is only used for states transitioning to themselves,
+        // this method will never be called and its return is arbitrary
+        // By returning a unit type, this Box does not allocate any heap memory
+        Box::new(())
+    }
+
+    fn name(&self) -> String {
+        format!("ContinueOutcome::<{}>", type_name::<T>())
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        Some(Vec::new())
+    }
+}
+
+impl<T> IntoOutcome for T
+where
+    T: Outcome + 'static,
+{
+    fn into_outcome(self) -> BoxedOutcome {
+        Box::new(self)
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        <T as Outcome>::declared_transitions()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::redundant_pattern, clippy::needless_return)]
+mod tests {
+    use super::StateMachine;
+    use crate::sm::{
+        BoxedOutcome, ContinueOutcome, EventState, IntoOutcome, Outcome, OutcomeData,
+        Limit, OwnedStateMachineRunner, Retention, RunError, RunErrorKind, State, StepOutcome,
+        StepReport,
+    };
+    use crate::{
+        sm_ext::{SubMachine, SubMachineState},
+        sm_global::GlobalTransition,
+    };
+    use std::{
+        any::TypeId, cell::RefCell, marker::PhantomData, rc::Rc, sync::Arc, thread, time::Instant,
+    };
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Data {
+        Normal,
+        IncorrectTransition,
+        Counting(u32),
+        // IncorrectStartTransition,
+    }
+
+    struct Start(bool);
+
+    impl Default for Start {
+        fn default() -> Self {
+            Self(true)
+        }
+    }
+
+    #[derive(Debug)]
+    enum StartTransition {
+        Continue,
+        Working,
+        WrongData,
+        // ContinueWrong,
+    }
+
+    impl Outcome for StartTransition {
+        fn state_type(&self) -> std::any::TypeId {
+            match self {
+                StartTransition::Working | StartTransition::WrongData => TypeId::of::<End>(),
+                StartTransition::Continue => TypeId::of::<Start>(),
+                // StartTransition::ContinueWrong => TypeId::of::<Start>(),
+            }
+        }
+
+        fn data(self: Box<Self>) -> Box<dyn std::any::Any> {
+            match *self {
+                StartTransition::Working => Box::new(150isize),
+                StartTransition::WrongData => Box::new(..),
+                StartTransition::Continue => Box::new(0usize),
+                // StartTransition::ContinueWrong => Box::new(..),
+            }
+        }
+
+        fn name(&self) -> String {
+            format!("{self:?}")
+        }
+    }
+
+    impl State for Start {
+        type Income = usize;
+        type Transition = StartTransition;
+        type Data = Data;
+
+        fn init(&mut self, previous: Box<Self::Income>) {
+            assert!(self.0);
+            if *previous < 10 {
+                self.0 = false;
+            }
+        }
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            if self.0 {
+                self.0 = false;
+                StartTransition::Continue
+            } else {
+                let transition = match data {
+                    Data::Normal => StartTransition::Working,
+                    Data::IncorrectTransition => StartTransition::WrongData,
+                    Data::Counting(_) => panic!("Data should not be initially set to unused"),
+                    // Data::IncorrectStartTransition => StartTransition::ContinueWrong,
+                };
+                *data = Data::Counting(10);
+                transition
+            }
+        }
+
+        fn name(&self) -> String {
+            "Start".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct End(i32);
+
+    struct EndTransition(bool);
+
+    impl IntoOutcome for EndTransition {
+        fn into_outcome(self) -> BoxedOutcome {
+            if self.0 {
+                ().into_outcome()
+            } else {
+                OutcomeData::<End>::with_name(0, "EndTransitionContinue".to_string()).into_outcome()
+            }
+        }
+    }
+
+    impl State for End {
+        type Income = isize;
+        type Transition = EndTransition;
+        type Data = Data;
+
+        fn init(&mut self, previous: Box<Self::Income>) {
+            assert_eq!(self.0, 0);
+            assert_eq!(*previous, 150);
+            self.0 = *previous as i32;
+        }
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            self.0 -= 1;
+            match data {
+                Data::Counting(i) => *i += 1,
+                _ => panic!("Data should be set here"),
+            };
+            if self.0 == 0 {
+                return EndTransition(true);
+            } else {
+                EndTransition(false)
+            }
+        }
+
+        fn name(&self) -> String {
+            "End".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct MissingState;
+
+    impl State for MissingState {
+        type Income = ();
+        type Transition = ();
+        type Data = Data;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+    }
+
+    #[test]
+    fn missing_start() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let runner = machine.runner::<MissingState>(Data::Normal, ());
+        assert!(runner.is_none());
+    }
+
+    #[test]
+    fn missing_state() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+
+        let runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        match runner.step() {
+            StepOutcome::StateNotFound {
+                data,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(data, Data::Counting(10));
+                assert_eq!(start, "Start");
+                assert_eq!(transition, "Working");
+                assert_eq!(end, TypeId::of::<End>());
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn run_error_returns_data() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+
+        let runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        let error = runner.run_to_completion().unwrap_err();
+        assert_eq!(
+            error.kind,
+            RunErrorKind::StateNotFound {
+                start: "Start".to_string(),
+                transition: "Working".to_string(),
+                end: TypeId::of::<End>(),
+            }
+        );
+        assert_eq!(error.data, Data::Counting(10));
+
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+        let runner = machine
+            .runner::<Start>(Data::IncorrectTransition, 0)
+            .unwrap();
+        let error: Box<dyn std::error::Error> = Box::new(runner.run_to_completion().unwrap_err());
+        assert!(error.to_string().starts_with("Start --[WrongData!]--> End"));
+    }
+
+    #[derive(Default)]
+    struct Bailout(Option<RunErrorKind>);
+
+    impl State for Bailout {
+        type Income = RunErrorKind;
+        type Transition = ();
+        type Data = Data;
+
+        fn init(&mut self, previous: Box<Self::Income>) {
+            self.0 = Some(*previous);
+        }
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            assert_eq!(*data, Data::Counting(10));
+            match self.0.take() {
+                Some(RunErrorKind::IncorrectTransition { .. }) => *data = Data::IncorrectTransition,
+                _ => *data = Data::Normal,
+            }
+        }
+
+        fn name(&self) -> String {
+            "Bailout".to_string()
+        }
+    }
+
+    #[test]
+    fn error_state() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        assert!(!machine.set_error_state::<Bailout>());
+        machine.add_state::<Bailout>();
+        assert!(machine.set_error_state::<Bailout>());
+
+        let runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        let runner = match runner.step() {
+            StepOutcome::Recovered {
+                machine,
+                error,
+                end,
+            } => {
+                assert_eq!(
+                    error,
+                    RunErrorKind::StateNotFound {
+                        start: "Start".to_string(),
+                        transition: "Working".to_string(),
+                        end: TypeId::of::<End>(),
+                    }
+                );
+                assert_eq!(end, "Bailout");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        assert_eq!(runner.run_to_completion().unwrap(), Data::Normal);
+
+        machine.add_state::<End>();
+        let runner = machine
+            .runner::<Start>(Data::IncorrectTransition, 0)
+            .unwrap();
+        assert_eq!(runner.run_to_completion().unwrap(), Data::IncorrectTransition);
+
+        assert!(machine.remove_state::<Bailout>());
+        let runner = machine
+            .runner::<Start>(Data::IncorrectTransition, 0)
+            .unwrap();
+        assert!(matches!(runner.step(), StepOutcome::IncorrectTransition { .. }));
+    }
+
+    #[derive(Default)]
+    struct Flounder;
+
+    impl State for Flounder {
+        type Income = RunErrorKind;
+        type Transition = OutcomeData<MissingState>;
+        type Data = Data;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::new(())
+        }
+
+        fn name(&self) -> String {
+            "Flounder".to_string()
+        }
+    }
+
+    #[test]
+    fn error_in_error_state() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<Flounder>();
+        assert!(machine.set_error_state::<Flounder>());
+
+        let runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        let runner = match runner.step() {
+            StepOutcome::Recovered { machine, end, .. } => {
+                assert_eq!(end, "Flounder");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        match runner.step() {
+            StepOutcome::StateNotFound { start, end, .. } => {
+                assert_eq!(start, "Flounder");
+                assert_eq!(end, TypeId::of::<MissingState>());
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn step_budget() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let mut runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        runner.set_step_budget(3);
+        let error = runner.run_to_completion().unwrap_err();
+        assert_eq!(
+            error.kind,
+            RunErrorKind::LimitReached {
+                state: "End".to_string(),
+                limit: Limit::StepBudget(3),
+            }
+        );
+        assert_eq!(error.data, Data::Counting(12));
+    }
+
+    #[test]
+    fn deadline() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let mut runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        let deadline = Instant::now();
+        runner.set_deadline(deadline);
+        match runner.step() {
+            StepOutcome::LimitReached { data, state, limit } => {
+                assert_eq!(data, Data::Normal);
+                assert_eq!(state, "Start");
+                assert_eq!(limit, Limit::Deadline(deadline));
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[derive(Default)]
+    struct ReturnHome(Option<Limit>);
+
+    impl State for ReturnHome {
+        type Income = Limit;
+        type Transition = ();
+        type Data = Data;
+
+        fn init(&mut self, previous: Box<Self::Income>) {
+            self.0 = Some(*previous);
+        }
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            assert_eq!(self.0, Some(Limit::StepBudget(1)));
+            *data = Data::Normal;
+        }
+
+        fn name(&self) -> String {
+            "ReturnHome".to_string()
+        }
+    }
+
+    #[test]
+    fn bail_out_state() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let mut runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        assert!(!runner.set_bail_out_state::<ReturnHome>());
+
+        machine.add_state::<ReturnHome>();
+        let mut runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        assert!(runner.set_bail_out_state::<ReturnHome>());
+        runner.set_step_budget(1);
+        runner = match runner.step() {
+            StepOutcome::Transition { machine, .. } => machine,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        runner = match runner.step() {
+            StepOutcome::BailOut {
+                machine,
+                start,
+                limit,
+                end,
+            } => {
+                assert_eq!(start, "End");
+                assert_eq!(limit, Limit::StepBudget(1));
+                assert_eq!(end, "ReturnHome");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        assert_eq!(runner.run_to_completion().unwrap(), Data::Normal);
+    }
+
+    #[derive(Default)]
+    struct Capsize;
+
+    impl State for Capsize {
+        type Income = Limit;
+        type Transition = ();
+        type Data = Data;
+
+        fn init(&mut self, _previous: Box<Self::Income>) {
+            panic!("Capsized")
+        }
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+    }
+
+    #[test]
+    fn panicking_bail_out_state() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<Capsize>();
+
+        let mut runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        assert!(runner.set_bail_out_state::<Capsize>());
+        runner.set_step_budget(0);
+        runner.set_catch_panics(true);
+        match runner.step() {
+            StepOutcome::Panicked { message, data, .. } => {
+                assert_eq!(message, "Capsized");
+                assert_eq!(data, Data::Normal);
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn wrong_transition_data() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let runner = machine
+            .runner::<Start>(Data::IncorrectTransition, 0)
+            .unwrap();
+        match runner.step() {
+            StepOutcome::IncorrectTransition {
+                data,
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            } => {
+                assert_eq!(data, Data::Counting(10));
+                assert_eq!(start, "Start");
+                assert_eq!(transition, "WrongData");
+                assert_eq!(end, "End");
+                assert_eq!(expected_type, TypeId::of::<<End as State>::Income>());
+                assert_eq!(
+                    received_data.downcast().expect("Given type should be .."),
+                    Box::new(..)
+                );
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+    #[test]
+    fn wrong_transition_data_2() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let mut runner = machine
+            .runner::<Start>(Data::IncorrectTransition, 100)
+            .unwrap();
+        match runner.step() {
+            StepOutcome::Continue { machine } => {
+                runner = machine;
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+
+        match runner.step() {
+            StepOutcome::IncorrectTransition {
+                data,
+                start,
+                transition,
+                end,
+                expected_type,
+                received_data,
+            } => {
+                assert_eq!(data, Data::Counting(10));
+                assert_eq!(start, "Start");
+                assert_eq!(transition, "WrongData");
+                assert_eq!(end, "End");
+                assert_eq!(expected_type, TypeId::of::<<End as State>::Income>());
+                assert_eq!(
+                    received_data.downcast().expect("Given type should be .."),
+                    Box::new(..)
+                );
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn income_adapter() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+        machine.add_income_adapter(|_: std::ops::RangeFull| 150isize);
+
+        let runner = machine
+            .runner::<Start>(Data::IncorrectTransition, 0)
+            .unwrap();
+        assert_eq!(runner.run_to_completion().unwrap(), Data::Counting(160));
+    }
+
+    #[test]
+    fn working() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let mut runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                runner = machine;
+                assert_eq!(start, "Start");
+                assert_eq!(transition, "Working");
+                assert_eq!(end, "End");
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+
+        for _ in 0..149 {
+            match runner.step() {
+                StepOutcome::Continue { machine } => {
+                    runner = machine;
+                }
+                e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+            }
+        }
+
+        match runner.step() {
+            StepOutcome::Complete {
+                data,
+                start,
+                transition,
+            } => {
+                assert_eq!(data, Data::Counting(160));
+                assert_eq!(start, "End");
+                assert_eq!(transition, "(Complete)");
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn working_2() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        let mut runner = machine.runner::<Start>(Data::Normal, 1000).unwrap();
+        match runner.step() {
+            StepOutcome::Continue { machine } => {
+                runner = machine;
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+
+        match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                runner = machine;
+                assert_eq!(start, "Start");
+                assert_eq!(transition, "Working");
+                assert_eq!(end, "End");
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
+
+        for _ in 0..149 {
+            match runner.step() {
+                StepOutcome::Continue { machine } => {
+                    runner = machine;
+                }
+                e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+            }
+        }
+
+        match runner.step() {
+            StepOutcome::Complete {
+                data,
+                start,
+                transition,
+            } => {
+                assert_eq!(data, Data::Counting(160));
+                assert_eq!(start, "End");
+                assert_eq!(transition, "(Complete)");
+            }
+            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        }
     }
-}
 
-pub type BoxedOutcome = Box<dyn Outcome>;
+    #[test]
+    fn working_3() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
 
-impl Outcome for BoxedOutcome {
-    fn state_type(&self) -> TypeId {
-        (**self).state_type()
+        let runner = machine.runner::<Start>(Data::Normal, 1000).unwrap();
+
+        let data = runner
+            .run_to_completion()
+            .expect("State machine should work successfully");
+        assert_eq!(data, Data::Counting(160));
     }
 
-    fn data(self: Box<Self>) -> Box<dyn Any> {
-        self
+    fn owned_mission() -> OwnedStateMachineRunner<Data> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+
+        Arc::new(machine)
+            .owned_runner::<Start>(Data::Normal, 1000)
+            .unwrap()
     }
 
-    fn name(&self) -> String {
-        (**self).name()
+    #[test]
+    fn owned_runner() {
+        let runner = owned_mission();
+        let data = runner
+            .run_to_completion()
+            .expect("State machine should work successfully");
+        assert_eq!(data, Data::Counting(160));
     }
-}
 
-/// This trait defines which outcome a transition follows
-/// 
-/// IntoOutcome is blanket implemented for all types which implement Outcome,
-/// so for states with only a single possible transition, using an Outcome type
-/// directly for the transition is likely preferred
-pub trait IntoOutcome {
-    fn into_outcome(self) -> BoxedOutcome;
-}
+    #[test]
+    fn owned_runner_on_thread() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
+        let machine = Arc::new(machine);
 
-/// The singular success endpoint for all state machines
-/// 
-/// All end states or transitions must return () as their transition or outcome
-impl Outcome for () {
-    fn state_type(&self) -> TypeId {
-        TypeId::of::<()>()
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let machine = machine.clone();
+                thread::spawn(move || {
+                    OwnedStateMachineRunner::new::<Start>(machine, Data::Normal, 0)
+                        .unwrap()
+                        .run_to_completion()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().unwrap(), Data::Counting(160));
+        }
     }
 
-    fn data(self: Box<Self>) -> Box<dyn Any> {
-        Box::new(())
+    #[test]
+    fn owned_runner_steps() {
+        let mut runner = owned_mission();
+        let data = loop {
+            runner = match runner.step() {
+                StepOutcome::Complete { data, .. } => break data,
+                outcome => outcome
+                    .into_runner()
+                    .expect("State machine should work successfully"),
+            };
+        };
+        assert_eq!(data, Data::Counting(160));
     }
 
-    fn name(&self) -> String {
-        "(Complete)".to_string()
-    }
-}
+    #[test]
+    fn sendable_runner() {
+        let mut machine = StateMachine::sendable();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
 
-impl<T> Outcome for OutcomeData<T>
-where
-    T: State,
-{
-    fn state_type(&self) -> TypeId {
-        TypeId::of::<T>()
+        let mut runner = Arc::new(machine)
+            .owned_runner::<Start>(Data::Normal, 0)
+            .unwrap();
+        runner.dispatch(0u8);
+        let handle = thread::spawn(move || {
+            assert_eq!(runner.pending_events(), 1);
+            runner.run_to_completion()
+        });
+        assert_eq!(handle.join().unwrap().unwrap(), Data::Counting(160));
     }
 
-    fn data(self: Box<Self>) -> Box<dyn Any> {
-        Box::new(self.0)
-    }
+    // #[test]
+    // fn unknown() {
+    //     let mut machine = StateMachine::default();
+    //     machine.add_state::<Start>();
+    //     machine.add_state::<End>();
 
-    fn name(&self) -> String {
-        self.1.clone()
-    }
-}
+    //     let runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+    //     match runner.step() {
+    //         StepOutcome::IncorrectTransition {
+    //             start,
+    //             transition,
+    //             end,
+    //             expected_type,
+    //             received_data,
+    //         } => {
+    //             assert_eq!(start, "Start");
+    //             assert_eq!(transition, "ContinueWrong");
+    //             assert_eq!(end, "End");
+    //             assert_eq!(expected_type, TypeId::of::<usize>());
+    //             assert_eq!(received_data.downcast().expect("Returned box should be of type .."), Box::new(..));
+    //         }
+    //         e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+    //     }
+    // }
 
-impl<T> Outcome for ContinueOutcome<T>
-where
-    T: State,
-{
-    fn state_type(&self) -> TypeId {
-        TypeId::of::<T>()
-    }
+    #[derive(Default)]
+    struct Launch;
 
-    fn data(self: Box<Self>) -> Box<dyn Any> {
-        // Since this outcome is only used for states transitioning to themselves,
-        // this method will never be called and its return is arbitrary
-        // By returning a unit type, this Box does not allocate any heap memory
-        Box::new(())
-    }
+    impl State for Launch {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = Vec<u8>;
 
-    fn name(&self) -> String {
-        format!("ContinueOutcome::<{}>", type_name::<T>())
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::<Orbit>::new(7).into_outcome()
+        }
     }
-}
 
-impl<T> IntoOutcome for T
-where
-    T: Outcome + 'static,
-{
-    fn into_outcome(self) -> BoxedOutcome {
-        Box::new(self)
-    }
-}
+    #[derive(Default)]
+    struct Orbit(u8);
 
-#[cfg(test)]
-mod tests {
-    use super::StateMachine;
-    use crate::sm::{BoxedOutcome, IntoOutcome, Outcome, OutcomeData, State, StepOutcome};
-    use std::{any::TypeId, marker::PhantomData};
+    impl State for Orbit {
+        type Income = u8;
+        type Transition = ();
+        type Data = Vec<u8>;
 
-    #[derive(Debug, PartialEq, Eq)]
-    enum Data {
-        Normal,
-        IncorrectTransition,
-        Counting(u32),
-        // IncorrectStartTransition,
+        fn init(&mut self, previous: Box<Self::Income>) {
+            self.0 = *previous;
+        }
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.push(self.0);
+        }
     }
 
-    struct Start(bool);
+    #[test]
+    fn boxed_outcome_income() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Launch>();
+        machine.add_state::<Orbit>();
 
-    impl Default for Start {
-        fn default() -> Self {
-            Self(true)
+        let runner = machine.runner::<Launch>(Vec::new(), ()).unwrap();
+        let runner = match runner.step() {
+            StepOutcome::Transition { machine, .. } => machine,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        match runner.step() {
+            StepOutcome::Complete { data, .. } => assert_eq!(data, [7]),
+            e => panic!("Unexpeced runner outcome {e:?}"),
         }
     }
 
-    #[derive(Debug)]
-    enum StartTransition {
-        Continue,
-        Working,
-        WrongData,
-        // ContinueWrong,
+    trait LikeI32: 'static {
+        fn get(&self) -> &i32;
+        fn get_mut(&mut self) -> &mut i32;
     }
 
-    impl Outcome for StartTransition {
-        fn state_type(&self) -> std::any::TypeId {
-            match self {
-                StartTransition::Working | StartTransition::WrongData => TypeId::of::<End>(),
-                StartTransition::Continue => TypeId::of::<Start>(),
-                // StartTransition::ContinueWrong => TypeId::of::<Start>(),
-            }
+    impl LikeI32 for i32 {
+        fn get(&self) -> &i32 {
+            self
         }
 
-        fn data(self: Box<Self>) -> Box<dyn std::any::Any> {
-            match *self {
-                StartTransition::Working => Box::new(150isize),
-                StartTransition::WrongData => Box::new(..),
-                StartTransition::Continue => Box::new(0usize),
-                // StartTransition::ContinueWrong => Box::new(..),
-            }
+        fn get_mut(&mut self) -> &mut i32 {
+            self
         }
+    }
 
-        fn name(&self) -> String {
-            format!("{self:?}")
+    impl LikeI32 for [i32; 1] {
+        fn get(&self) -> &i32 {
+            &self[0]
+        }
+
+        fn get_mut(&mut self) -> &mut i32 {
+            &mut self[0]
         }
     }
 
-    impl State for Start {
-        type Income = usize;
-        type Transition = StartTransition;
-        type Data = Data;
+    enum Void {}
 
-        fn init(&mut self, previous: Box<Self::Income>) {
-            assert!(self.0);
-            if *previous < 10 {
-                self.0 = false;
-            }
-        }
+    enum CollatzOutcome<D> {
+        Even,
+        Odd,
+        _Unused(D, Void),
+    }
 
-        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
-            if self.0 {
-                self.0 = false;
-                StartTransition::Continue
+    impl<D> CollatzOutcome<D> {
+        pub fn new(v: i32) -> Self {
+            if v % 2 == 0 {
+                Self::Even
             } else {
-                let transition = match data {
-                    Data::Normal => StartTransition::Working,
-                    Data::IncorrectTransition => StartTransition::WrongData,
-                    Data::Counting(_) => panic!("Data should not be initially set to unused"),
-                    // Data::IncorrectStartTransition => StartTransition::ContinueWrong,
-                };
-                *data = Data::Counting(10);
-                transition
+                Self::Odd
             }
         }
-
-        fn name(&self) -> String {
-            "Start".to_string()
-        }
     }
 
-    #[derive(Default)]
-    struct End(i32);
-
-    struct EndTransition(bool);
-
-    impl IntoOutcome for EndTransition {
+    impl<D: LikeI32> IntoOutcome for CollatzOutcome<D> {
         fn into_outcome(self) -> BoxedOutcome {
-            if self.0 {
-                ().into_outcome()
-            } else {
-                OutcomeData::<End>::with_name(0, "EndTransitionContinue".to_string()).into_outcome()
+            match self {
+                CollatzOutcome::Even => OutcomeData::<Even<D>>::with_name((), "CollatzOutcome::Even".to_string()).into_outcome(),
+                CollatzOutcome::Odd => OutcomeData::<Odd<D>>::with_name((), "CollatzOutcome::Odd".to_string()).into_outcome(),
+                _ => unreachable!()
             }
         }
     }
-
-    impl State for End {
-        type Income = isize;
-        type Transition = EndTransition;
-        type Data = Data;
-
-        fn init(&mut self, previous: Box<Self::Income>) {
-            assert_eq!(self.0, 0);
-            assert_eq!(*previous, 150);
-            self.0 = *previous as i32;
+
+    struct Even<D>(PhantomData<D>);
+
+    impl<D: LikeI32> Default for Even<D> {
+        fn default() -> Self {
+            Self(Default::default())
         }
+    }
+
+    impl<D: LikeI32> State for Even<D> {
+        type Income = ();
+        type Transition = CollatzOutcome<D>;
+        type Data = D;
 
         fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
-            self.0 -= 1;
-            match data {
-                Data::Counting(i) => *i += 1,
-                _ => panic!("Data should be set here"),
-            };
-            if self.0 == 0 {
-                return EndTransition(true);
-            } else {
-                EndTransition(false)
-            }
+            *data.get_mut() = *data.get() / 2;
+            CollatzOutcome::new(*data.get())
         }
 
         fn name(&self) -> String {
-            "End".to_string()
+            "Even".to_string()
         }
     }
 
-    #[derive(Default)]
-    struct MissingState;
-
-    impl State for MissingState {
-        type Income = ();
-        type Transition = ();
-        type Data = Data;
+    struct Odd<D>(PhantomData<D>);
 
-        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+    impl<D: LikeI32> Default for Odd<D> {
+        fn default() -> Self {
+            Self(Default::default())
+        }
     }
 
-    #[test]
-    fn missing_start() {
-        let mut machine = StateMachine::default();
-        machine.add_state::<Start>();
-        machine.add_state::<End>();
+    impl<D: LikeI32> State for Odd<D> {
+        type Income = ();
+        type Transition = CollatzOutcome<D>;
+        type Data = D;
 
-        let runner = machine.runner::<MissingState>(Data::Normal, ());
-        assert!(runner.is_none());
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data.get_mut() = *data.get() * 3 + 1;
+            CollatzOutcome::new(*data.get())
+        }
+
+        fn name(&self) -> String {
+            "Odd".to_string()
+        }
     }
 
+
     #[test]
-    fn missing_state() {
+    fn generic_states() {
         let mut machine = StateMachine::default();
-        machine.add_state::<Start>();
+        machine.add_state::<Even<_>>();
+        machine.add_state::<Odd<_>>();
 
-        let runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
+        let mut runner = machine.runner::<Odd<[i32; 1]>>([123], ()).unwrap();
         match runner.step() {
-            StepOutcome::StateNotFound {
+            StepOutcome::Transition {
+                machine,
                 start,
                 transition,
                 end,
             } => {
-                assert_eq!(start, "Start");
-                assert_eq!(transition, "Working");
-                assert_eq!(end, TypeId::of::<End>());
+                runner = machine;
+                assert_eq!(start, "Odd");
+                assert_eq!(transition, "CollatzOutcome::Even");
+                assert_eq!(end, "Even");
             }
             e @ _ => panic!("Unexpeced runner outcome {e:?}"),
         }
-    }
-
-    #[test]
-    fn wrong_transition_data() {
-        let mut machine = StateMachine::default();
-        machine.add_state::<Start>();
-        machine.add_state::<End>();
 
-        let runner = machine
-            .runner::<Start>(Data::IncorrectTransition, 0)
-            .unwrap();
         match runner.step() {
-            StepOutcome::IncorrectTransition {
+            StepOutcome::Transition {
+                machine,
                 start,
                 transition,
                 end,
-                expected_type,
-                received_data,
             } => {
-                assert_eq!(start, "Start");
-                assert_eq!(transition, "WrongData");
-                assert_eq!(end, "End");
-                assert_eq!(expected_type, TypeId::of::<<End as State>::Income>());
-                assert_eq!(
-                    received_data.downcast().expect("Given type should be .."),
-                    Box::new(..)
-                );
+                runner = machine;
+                assert_eq!(start, "Even");
+                assert_eq!(transition, "CollatzOutcome::Odd");
+                assert_eq!(end, "Odd");
             }
             e @ _ => panic!("Unexpeced runner outcome {e:?}"),
         }
+
+        assert_eq!(runner.data, [185]);
     }
-    #[test]
-    fn wrong_transition_data_2() {
-        let mut machine = StateMachine::default();
-        machine.add_state::<Start>();
-        machine.add_state::<End>();
 
-        let mut runner = machine
-            .runner::<Start>(Data::IncorrectTransition, 100)
-            .unwrap();
-        match runner.step() {
-            StepOutcome::Continue { machine } => {
-                runner = machine;
+    enum Command {
+        Arm,
+        Ignored,
+    }
+
+    struct LeakDetected(u8);
+
+    #[derive(Default)]
+    struct Idle;
+
+    impl State for Idle {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = Vec<u8>;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            panic!("Idle is only driven by events")
+        }
+
+        fn name(&self) -> String {
+            "Idle".to_string()
+        }
+    }
+
+    impl EventState<Command> for Idle {
+        fn on_event(&mut self, event: &Command, _data: &mut Self::Data) -> Self::Transition {
+            match event {
+                Command::Arm => OutcomeData::<Armed>::with_name((), "Arm".to_string()).into_outcome(),
+                Command::Ignored => ContinueOutcome::<Self>::default().into_outcome(),
             }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
         }
+    }
 
-        match runner.step() {
-            StepOutcome::IncorrectTransition {
+    #[derive(Default)]
+    struct Armed;
+
+    impl State for Armed {
+        type Income = ();
+        type Transition = ();
+        type Data = Vec<u8>;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            panic!("Armed is only driven by events")
+        }
+
+        fn name(&self) -> String {
+            "Armed".to_string()
+        }
+    }
+
+    impl EventState<LeakDetected> for Armed {
+        fn on_event(&mut self, event: &LeakDetected, data: &mut Self::Data) -> Self::Transition {
+            data.push(event.0);
+        }
+    }
+
+    #[test]
+    fn sent_events() {
+        let mut machine = StateMachine::default();
+        machine.add_event_state::<Idle, Command>();
+        machine.add_event_state::<Armed, LeakDetected>();
+
+        let mut runner = machine.runner::<Idle>(Vec::new(), ()).unwrap();
+        let sender = runner.event_sender();
+        let operator = sender.clone();
+        thread::spawn(move || assert!(operator.send(Command::Arm)))
+            .join()
+            .unwrap();
+        thread::spawn(move || assert!(sender.send(LeakDetected(3))))
+            .join()
+            .unwrap();
+        // Sent events are only received once the runner processes an event or steps
+        assert_eq!(runner.pending_events(), 0);
+        match runner.process_event() {
+            StepOutcome::Transition {
+                machine,
                 start,
-                transition,
                 end,
-                expected_type,
-                received_data,
+                ..
             } => {
-                assert_eq!(start, "Start");
-                assert_eq!(transition, "WrongData");
-                assert_eq!(end, "End");
-                assert_eq!(expected_type, TypeId::of::<<End as State>::Income>());
-                assert_eq!(
-                    received_data.downcast().expect("Given type should be .."),
-                    Box::new(..)
-                );
+                runner = machine;
+                assert_eq!(start, "Idle");
+                assert_eq!(end, "Armed");
             }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+            e => panic!("Unexpeced runner outcome {e:?}"),
         }
+        assert_eq!(runner.pending_events(), 1);
+        let sender = runner.event_sender();
+        match runner.process_event() {
+            StepOutcome::Complete { data, start, .. } => {
+                assert_eq!(start, "Armed");
+                assert_eq!(data, [3]);
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+        assert!(!sender.send(LeakDetected(4)));
     }
 
     #[test]
-    fn working() {
+    fn events() {
         let mut machine = StateMachine::default();
-        machine.add_state::<Start>();
-        machine.add_state::<End>();
+        machine.add_event_state::<Idle, Command>();
+        machine.add_event_state::<Armed, LeakDetected>();
 
-        let mut runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
-        match runner.step() {
+        let mut runner = machine.runner::<Idle>(Vec::new(), ()).unwrap();
+        match runner.process_event() {
+            StepOutcome::Continue { machine } => runner = machine,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+
+        // Armed does not receive Commands, and Idle does not receive LeakDetected
+        runner.dispatch(LeakDetected(1));
+        runner.dispatch(Command::Ignored);
+        runner.dispatch(Command::Arm);
+        runner.dispatch(Command::Arm);
+        runner.dispatch(LeakDetected(2));
+        assert_eq!(runner.pending_events(), 5);
+        match runner.process_event() {
+            StepOutcome::Unhandled {
+                machine,
+                state,
+                event,
+            } => {
+                runner = machine;
+                assert_eq!(state, "Idle");
+                assert_eq!(event, std::any::type_name::<LeakDetected>());
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+        match runner.process_event() {
+            StepOutcome::Continue { machine } => runner = machine,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+        match runner.process_event() {
             StepOutcome::Transition {
                 machine,
                 start,
@@ -782,264 +3588,523 @@ mod tests {
                 end,
             } => {
                 runner = machine;
-                assert_eq!(start, "Start");
-                assert_eq!(transition, "Working");
-                assert_eq!(end, "End");
+                assert_eq!(start, "Idle");
+                assert_eq!(transition, "Arm");
+                assert_eq!(end, "Armed");
             }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+            e => panic!("Unexpeced runner outcome {e:?}"),
         }
-
-        for _ in 0..149 {
-            match runner.step() {
-                StepOutcome::Continue { machine } => {
-                    runner = machine;
-                }
-                e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        match runner.process_event() {
+            StepOutcome::Unhandled { machine, state, .. } => {
+                runner = machine;
+                assert_eq!(state, "Armed");
             }
+            e => panic!("Unexpeced runner outcome {e:?}"),
         }
-
-        match runner.step() {
+        match runner.process_event() {
             StepOutcome::Complete {
                 data,
                 start,
                 transition,
             } => {
-                assert_eq!(data, Data::Counting(160));
-                assert_eq!(start, "End");
+                assert_eq!(data, vec![2]);
+                assert_eq!(start, "Armed");
                 assert_eq!(transition, "(Complete)");
             }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+            e => panic!("Unexpeced runner outcome {e:?}"),
         }
     }
 
     #[test]
-    fn working_2() {
+    fn events_past_deadline() {
         let mut machine = StateMachine::default();
-        machine.add_state::<Start>();
-        machine.add_state::<End>();
+        machine.add_event_state::<Idle, Command>();
+        machine.add_event_state::<Armed, LeakDetected>();
+
+        let mut runner = machine.runner::<Idle>(Vec::new(), ()).unwrap();
+        let deadline = Instant::now();
+        runner.set_deadline(deadline);
+        runner.dispatch(Command::Arm);
+        match runner.process_event() {
+            StepOutcome::LimitReached { data, state, limit } => {
+                assert!(data.is_empty());
+                assert_eq!(state, "Idle");
+                assert_eq!(limit, Limit::Deadline(deadline));
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    struct ExitLog {
+        next: fn() -> BoxedOutcome,
+        exits: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl ExitLog {
+        fn new(next: fn() -> BoxedOutcome) -> (Self, Rc<RefCell<Vec<&'static str>>>) {
+            let exits = Rc::new(RefCell::new(Vec::new()));
+            (Self { next, exits: exits.clone() }, exits)
+        }
+    }
+
+    #[derive(Default)]
+    struct Thrusters;
+
+    impl State for Thrusters {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = ExitLog;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            (data.next)()
+        }
+
+        fn exit(&mut self, data: &mut Self::Data) {
+            data.exits.borrow_mut().push("Thrusters");
+        }
+    }
+
+    #[derive(Default)]
+    struct Surface;
+
+    impl State for Surface {
+        type Income = ();
+        type Transition = ();
+        type Data = ExitLog;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+
+        fn exit(&mut self, data: &mut Self::Data) {
+            data.exits.borrow_mut().push("Surface");
+        }
+    }
+
+    struct WrongIncome;
+
+    impl Outcome for WrongIncome {
+        fn state_type(&self) -> TypeId {
+            TypeId::of::<Surface>()
+        }
+
+        fn data(self: Box<Self>) -> Box<dyn std::any::Any> {
+            Box::new(0u8)
+        }
+    }
+
+    fn exit_machine() -> StateMachine<ExitLog> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Thrusters>();
+        machine.add_state::<Surface>();
+        machine
+    }
+
+    #[test]
+    fn exit_on_transition_and_completion() {
+        let machine = exit_machine();
+        let (data, exits) = ExitLog::new(|| OutcomeData::<Surface>::new(()).into_outcome());
+        let runner = machine.runner::<Thrusters>(data, ()).unwrap();
+        assert!(runner.run_to_completion().is_ok());
+        assert_eq!(*exits.borrow(), vec!["Thrusters", "Surface"]);
+    }
+
+    #[test]
+    fn exit_not_run_on_continue() {
+        let machine = exit_machine();
+        let (data, exits) = ExitLog::new(|| ContinueOutcome::<Thrusters>::default().into_outcome());
+        let runner = machine.runner::<Thrusters>(data, ()).unwrap();
+        assert!(matches!(runner.step(), StepOutcome::Continue { .. }));
+        assert!(exits.borrow().is_empty());
+    }
+
+    #[test]
+    fn exit_on_errors() {
+        let mut machine = exit_machine();
+        machine.remove_state::<Surface>();
+        let (data, exits) = ExitLog::new(|| OutcomeData::<Surface>::new(()).into_outcome());
+        let runner = machine.runner::<Thrusters>(data, ()).unwrap();
+        assert!(matches!(runner.step(), StepOutcome::StateNotFound { .. }));
+        assert_eq!(*exits.borrow(), vec!["Thrusters"]);
+
+        let machine = exit_machine();
+        let (data, exits) = ExitLog::new(|| WrongIncome.into_outcome());
+        let runner = machine.runner::<Thrusters>(data, ()).unwrap();
+        assert!(matches!(runner.step(), StepOutcome::IncorrectTransition { .. }));
+        assert_eq!(*exits.borrow(), vec!["Thrusters"]);
+    }
+
+    #[derive(Default)]
+    struct Search {
+        progress: u32,
+        resumed: bool,
+    }
+
+    impl State for Search {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = Vec<(u32, bool)>;
 
-        let mut runner = machine.runner::<Start>(Data::Normal, 1000).unwrap();
-        match runner.step() {
-            StepOutcome::Continue { machine } => {
-                runner = machine;
-            }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+        fn resume(&mut self, _previous: Box<Self::Income>) {
+            self.resumed = true;
         }
 
-        match runner.step() {
-            StepOutcome::Transition {
-                machine,
-                start,
-                transition,
-                end,
-            } => {
-                runner = machine;
-                assert_eq!(start, "Start");
-                assert_eq!(transition, "Working");
-                assert_eq!(end, "End");
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            self.progress += 1;
+            data.push((self.progress, self.resumed));
+            match self.progress {
+                2 => OutcomeData::<Interrupt>::new(()).into_outcome(),
+                4 => ().into_outcome(),
+                _ => ContinueOutcome::<Self>::default().into_outcome(),
             }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
         }
+    }
 
-        for _ in 0..149 {
-            match runner.step() {
-                StepOutcome::Continue { machine } => {
-                    runner = machine;
-                }
-                e @ _ => panic!("Unexpeced runner outcome {e:?}"),
-            }
-        }
+    #[derive(Default)]
+    struct Interrupt;
 
-        match runner.step() {
-            StepOutcome::Complete {
-                data,
-                start,
-                transition,
-            } => {
-                assert_eq!(data, Data::Counting(160));
-                assert_eq!(start, "End");
-                assert_eq!(transition, "(Complete)");
-            }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+    impl State for Interrupt {
+        type Income = ();
+        type Transition = OutcomeData<Search>;
+        type Data = Vec<(u32, bool)>;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::new(())
         }
     }
 
     #[test]
-    fn working_3() {
+    fn persistent_state_resumes() {
         let mut machine = StateMachine::default();
-        machine.add_state::<Start>();
-        machine.add_state::<End>();
-
-        let runner = machine.runner::<Start>(Data::Normal, 1000).unwrap();
+        machine.add_state::<Search>();
+        machine.add_state::<Interrupt>();
+        assert!(machine.set_retention::<Search>(Retention::Persistent));
 
-        let data = runner
-            .run_to_completion()
-            .expect("State machine should work successfully");
-        assert_eq!(data, Data::Counting(160));
+        let runner = machine.runner::<Search>(Vec::new(), ()).unwrap();
+        let data = runner.run_to_completion().expect("Should not error");
+        assert_eq!(data, vec![(1, false), (2, false), (3, true), (4, true)]);
     }
 
-    // #[test]
-    // fn unknown() {
-    //     let mut machine = StateMachine::default();
-    //     machine.add_state::<Start>();
-    //     machine.add_state::<End>();
+    #[derive(Default)]
+    struct Sonar(u8);
 
-    //     let runner = machine.runner::<Start>(Data::Normal, 0).unwrap();
-    //     match runner.step() {
-    //         StepOutcome::IncorrectTransition {
-    //             start,
-    //             transition,
-    //             end,
-    //             expected_type,
-    //             received_data,
-    //         } => {
-    //             assert_eq!(start, "Start");
-    //             assert_eq!(transition, "ContinueWrong");
-    //             assert_eq!(end, "End");
-    //             assert_eq!(expected_type, TypeId::of::<usize>());
-    //             assert_eq!(received_data.downcast().expect("Returned box should be of type .."), Box::new(..));
-    //         }
-    //         e @ _ => panic!("Unexpeced runner outcome {e:?}"),
-    //     }
-    // }
+    impl State for Sonar {
+        type Income = u8;
+        type Transition = BoxedOutcome;
+        type Data = Vec<u8>;
 
-    trait LikeI32: 'static {
-        fn get(&self) -> &i32;
-        fn get_mut(&mut self) -> &mut i32;
+        fn init(&mut self, previous: Box<Self::Income>) {
+            self.0 += *previous;
+        }
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.push(self.0);
+            if self.0 < 2 {
+                OutcomeData::<Jam>::new(()).into_outcome()
+            } else {
+                ().into_outcome()
+            }
+        }
     }
 
-    impl LikeI32 for i32 {
-        fn get(&self) -> &i32 {
-            self
+    #[derive(Default)]
+    struct Jam;
+
+    /// A transition to Sonar with the wrong income
+    struct Garbled;
+
+    impl Outcome for Garbled {
+        fn state_type(&self) -> TypeId {
+            TypeId::of::<Sonar>()
         }
 
-        fn get_mut(&mut self) -> &mut i32 {
-            self
+        fn data(self: Box<Self>) -> Box<dyn std::any::Any> {
+            Box::new(())
         }
     }
 
-    impl LikeI32 for [i32; 1] {
-        fn get(&self) -> &i32 {
-            &self[0]
+    impl State for Jam {
+        type Income = ();
+        type Transition = Garbled;
+        type Data = Vec<u8>;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            Garbled
         }
+    }
 
-        fn get_mut(&mut self) -> &mut i32 {
-            &mut self[0]
+    #[derive(Default)]
+    struct Unjam;
+
+    impl State for Unjam {
+        type Income = RunErrorKind;
+        type Transition = OutcomeData<Sonar>;
+        type Data = Vec<u8>;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::new(2)
         }
     }
 
-    enum Void {}
+    #[test]
+    fn recovery_keeps_persistent_state() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Sonar>();
+        machine.add_state::<Jam>();
+        machine.add_state::<Unjam>();
+        assert!(machine.set_retention::<Sonar>(Retention::Persistent));
+        assert!(machine.set_error_state::<Unjam>());
 
-    enum CollatzOutcome<D> {
-        Even,
-        Odd,
-        _Unused(D, Void),
+        let runner = machine.runner::<Sonar>(Vec::new(), 1).unwrap();
+        let data = runner.run_to_completion().expect("Should not error");
+        assert_eq!(data, vec![1, 3]);
     }
 
-    impl<D> CollatzOutcome<D> {
-        pub fn new(v: i32) -> Self {
-            if v % 2 == 0 {
-                Self::Even
-            } else {
-                Self::Odd
-            }
+    #[derive(Default)]
+    struct Leg1;
+
+    impl State for Leg1 {
+        type Income = ();
+        type Transition = OutcomeData<Leg2>;
+        type Data = Vec<&'static str>;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.push("Leg1");
+            OutcomeData::new(())
         }
     }
 
-    impl<D: LikeI32> IntoOutcome for CollatzOutcome<D> {
-        fn into_outcome(self) -> BoxedOutcome {
-            match self {
-                CollatzOutcome::Even => OutcomeData::<Even<D>>::with_name((), "CollatzOutcome::Even".to_string()).into_outcome(),
-                CollatzOutcome::Odd => OutcomeData::<Odd<D>>::with_name((), "CollatzOutcome::Odd".to_string()).into_outcome(),
-                _ => unreachable!()
-            }
+    #[derive(Default)]
+    struct Leg2;
+
+    impl State for Leg2 {
+        type Income = ();
+        type Transition = ();
+        type Data = Vec<&'static str>;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.push("Leg2");
+        }
+
+        fn exit(&mut self, data: &mut Self::Data) {
+            data.push("Leg2 exit");
         }
     }
 
-    struct Even<D>(PhantomData<D>);
+    #[derive(Default)]
+    struct RouteInner;
 
-    impl<D: LikeI32> Default for Even<D> {
-        fn default() -> Self {
-            Self(Default::default())
+    impl SubMachineState for RouteInner {
+        type Income = ();
+        type Transition = ();
+        type Data = Vec<&'static str>;
+        type Start = Leg1;
+
+        fn build(machine: &mut StateMachine<Self::Data>) {
+            machine.add_state::<Leg1>();
+            machine.add_state::<Leg2>();
+        }
+
+        fn start(&mut self, _previous: Box<Self::Income>) {}
+
+        fn handle_complete(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.push("Route complete");
+        }
+
+        fn handle_error(&mut self, error: StepReport, _data: &mut Self::Data) -> Self::Transition {
+            panic!("{error}")
+        }
+
+        fn exit(&mut self, data: &mut Self::Data) {
+            data.push("Route exit");
         }
     }
 
-    impl<D: LikeI32> State for Even<D> {
+    type Route = SubMachine<RouteInner>;
+
+    #[derive(Default)]
+    struct Detour;
+
+    impl State for Detour {
         type Income = ();
-        type Transition = CollatzOutcome<D>;
-        type Data = D;
+        type Transition = OutcomeData<Route>;
+        type Data = Vec<&'static str>;
 
         fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
-            *data.get_mut() = *data.get() / 2;
-            CollatzOutcome::new(*data.get())
+            data.push("Detour");
+            OutcomeData::new(())
         }
+    }
 
-        fn name(&self) -> String {
-            "Even".to_string()
+    #[test]
+    fn persistent_sub_machine_resumes() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Route>();
+        machine.add_state::<Detour>();
+        assert!(machine.set_retention::<Route>(Retention::Persistent));
+        assert!(machine.add_global_transition(GlobalTransition::to::<Detour>(
+            "Detour",
+            |data: &Vec<&str>| data.last() == Some(&"Leg1"),
+            |_| (),
+        )));
+
+        let runner = machine.runner::<Route>(Vec::new(), ()).unwrap();
+        let data = runner.run_to_completion().expect("Should not error");
+        assert_eq!(
+            data,
+            vec![
+                "Leg1",
+                "Route exit",
+                "Detour",
+                "Leg2",
+                "Leg2 exit",
+                "Route complete",
+                "Route exit"
+            ]
+        );
+    }
+
+    #[test]
+    fn retention_of_missing_state() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Interrupt>();
+        assert!(!machine.set_retention::<Search>(Retention::Persistent));
+    }
+
+    #[test]
+    fn fresh_state_restarts() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Search>();
+        machine.add_state::<Interrupt>();
+
+        let mut runner = machine.runner::<Search>(Vec::new(), ()).unwrap();
+        for _ in 0..4 {
+            runner = match runner.step().into() {
+                Ok(machine) => machine,
+                Err(_) => panic!("Search should never complete without retention"),
+            };
         }
+        assert_eq!(runner.data, vec![(1, false), (2, false), (1, false)]);
     }
 
-    struct Odd<D>(PhantomData<D>);
+    #[test]
+    fn caught_panic_in_handle() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Start>();
+        machine.add_state::<End>();
 
-    impl<D: LikeI32> Default for Odd<D> {
-        fn default() -> Self {
-            Self(Default::default())
+        let mut runner = machine.runner::<Start>(Data::Counting(3), 0).unwrap();
+        runner.set_catch_panics(true);
+        match runner.step() {
+            StepOutcome::Panicked {
+                state,
+                message,
+                data,
+            } => {
+                assert_eq!(state, "Start");
+                assert_eq!(message, "Data should not be initially set to unused");
+                assert_eq!(data, Data::Counting(3));
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
         }
     }
 
-    impl<D: LikeI32> State for Odd<D> {
+    #[derive(Default)]
+    struct Arm;
+
+    impl State for Arm {
         type Income = ();
-        type Transition = CollatzOutcome<D>;
-        type Data = D;
+        type Transition = OutcomeData<Detonate>;
+        type Data = u32;
 
         fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
-            *data.get_mut() = *data.get() * 3 + 1;
-            CollatzOutcome::new(*data.get())
+            *data += 1;
+            OutcomeData::new(*data)
+        }
+    }
+
+    #[derive(Default)]
+    struct Detonate;
+
+    impl State for Detonate {
+        type Income = u32;
+        type Transition = ();
+        type Data = u32;
+
+        fn init(&mut self, previous: Box<Self::Income>) {
+            panic!("Fuse {previous} is faulty");
         }
 
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+
         fn name(&self) -> String {
-            "Odd".to_string()
+            "Detonate".to_string()
         }
     }
 
-
     #[test]
-    fn generic_states() {
+    fn caught_panic_in_init() {
         let mut machine = StateMachine::default();
-        machine.add_state::<Even<_>>();
-        machine.add_state::<Odd<_>>();
+        machine.add_state::<Arm>();
+        machine.add_state::<Detonate>();
 
-        let mut runner = machine.runner::<Odd<[i32; 1]>>([123], ()).unwrap();
+        let mut runner = machine.runner::<Arm>(4, ()).unwrap();
+        runner.set_catch_panics(true);
         match runner.step() {
-            StepOutcome::Transition {
-                machine,
-                start,
-                transition,
-                end,
+            StepOutcome::Panicked {
+                state,
+                message,
+                data,
             } => {
-                runner = machine;
-                assert_eq!(start, "Odd");
-                assert_eq!(transition, "CollatzOutcome::Even");
-                assert_eq!(end, "Even");
+                assert_eq!(state, "Detonate");
+                assert_eq!(message, "Fuse 5 is faulty");
+                assert_eq!(data, 5);
             }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn caught_panic_in_start_init() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Detonate>();
+
+        match machine.runner_catching_panics::<Detonate>(2, 7) {
+            Some(Err(RunError {
+                kind: RunErrorKind::Panicked { state, message },
+                data,
+            })) => {
+                assert_eq!(state, "Detonate");
+                assert_eq!(message, "Fuse 7 is faulty");
+                assert_eq!(data, 2);
+            }
+            Some(Ok(runner)) => panic!("Unexpeced runner {runner:?}"),
+            Some(Err(e)) => panic!("Unexpeced runner error {e:?}"),
+            None => panic!("Detonate should be present"),
         }
+    }
+
+    #[test]
+    fn catching_runner_catches_step_panics() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Arm>();
+        machine.add_state::<Detonate>();
 
+        let runner = machine.runner_catching_panics::<Arm>(0, ()).unwrap().unwrap();
         match runner.step() {
-            StepOutcome::Transition {
-                machine,
-                start,
-                transition,
-                end,
-            } => {
-                runner = machine;
-                assert_eq!(start, "Even");
-                assert_eq!(transition, "CollatzOutcome::Odd");
-                assert_eq!(end, "Odd");
+            StepOutcome::Panicked { state, data, .. } => {
+                assert_eq!(state, "Detonate");
+                assert_eq!(data, 1);
             }
-            e @ _ => panic!("Unexpeced runner outcome {e:?}"),
+            e => panic!("Unexpeced runner outcome {e:?}"),
         }
+    }
 
-        assert_eq!(runner.data, [185]);
+    #[test]
+    #[should_panic(expected = "Fuse 1 is faulty")]
+    fn uncaught_panic() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Arm>();
+        machine.add_state::<Detonate>();
+
+        let _ = machine.runner::<Arm>(0, ()).unwrap().step();
     }
 }
@@ -0,0 +1,393 @@
+use core::fmt;
+use std::{
+    any::type_name,
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::Instant,
+};
+
+use crate::{
+    sm::{
+        BoxedFuture, IntoOutcome, Limit, RunError, State, StateMachine, StateMachineRunner,
+        StepOutcome,
+    },
+    sm_observer::RunnerObserver,
+};
+
+/// Type useful for States which wait on I/O
+///
+/// handle returns a future instead of busy-polling through a ContinueOutcome,
+/// and may be implemented with an `async fn`
+pub trait AsyncState: Default + 'static {
+    type Income: 'static;
+    type Transition: IntoOutcome;
+    type Data;
+
+    #[allow(unused, clippy::boxed_local)]
+    fn init(&mut self, previous: Box<Self::Income>) {}
+    fn resume(&mut self, previous: Box<Self::Income>) {
+        self.init(previous);
+    }
+    fn handle(&mut self, data: &mut Self::Data) -> impl Future<Output = Self::Transition>;
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
+
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+}
+
+/// This struct wraps AsyncState types and provides a functional State implementation
+/// for all AsyncState types
+///
+/// Asynchronous and synchronous states may be added to the same StateMachine.
+/// An AsyncStateMachineRunner awaits the handle of the wrapped state,
+/// while a StateMachineRunner blocks the current thread on it using block_on
+///
+/// ```
+/// use umrsm::{sm::{ContinueOutcome, IntoOutcome, BoxedOutcome, StateMachine}, sm_async::{block_on, AsyncState, AsyncStateMachineRunner, AsyncStateStruct}};
+///
+/// #[derive(Default)]
+/// struct ReadSensorInner;
+///
+/// impl AsyncState for ReadSensorInner {
+///     type Income = ();
+///     type Transition = BoxedOutcome;
+///     type Data = Vec<u8>;
+///
+///     async fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+///         data.push(std::future::ready(data.len() as u8).await);
+///         if data.len() < 3 {
+///             ContinueOutcome::<ReadSensor>::default().into_outcome()
+///         } else {
+///             ().into_outcome()
+///         }
+///     }
+/// }
+///
+/// type ReadSensor = AsyncStateStruct<ReadSensorInner>;
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<ReadSensor>();
+///
+/// let runner = AsyncStateMachineRunner::new::<ReadSensor>(&machine, Vec::new(), ()).expect("ReadSensor exists in the machine");
+/// assert_eq!(block_on(runner.run_to_completion()).expect("Should not error"), vec![0, 1, 2]);
+/// ```
+#[derive(Default)]
+pub struct AsyncStateStruct<S: AsyncState> {
+    state: S,
+}
+
+impl<S: AsyncState> State for AsyncStateStruct<S> {
+    type Income = S::Income;
+    type Transition = S::Transition;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        self.state.init(previous);
+    }
+
+    fn resume(&mut self, previous: Box<Self::Income>) {
+        self.state.resume(previous);
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        block_on(self.state.handle(data))
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(self.state.handle(data))
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        self.state.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.state.name()
+    }
+}
+
+/// The async counterpart of StateMachineRunner
+///
+/// The runner does not depend on any particular executor;
+/// the futures it returns may be driven by any executor, including block_on
+pub struct AsyncStateMachineRunner<'a, Data: 'static> {
+    runner: StateMachineRunner<'a, Data>,
+}
+
+impl<'a, D: fmt::Debug + 'static> fmt::Debug for AsyncStateMachineRunner<'a, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncStateMachineRunner")
+            .field("runner", &self.runner)
+            .finish()
+    }
+}
+
+impl<'a, D> From<StateMachineRunner<'a, D>> for AsyncStateMachineRunner<'a, D> {
+    fn from(runner: StateMachineRunner<'a, D>) -> Self {
+        Self { runner }
+    }
+}
+
+impl<'a, D> AsyncStateMachineRunner<'a, D> {
+    /// Create an async state machine runner from the provided start state
+    /// Returns None if the provided start state is not present in the given state machine
+    pub fn new<Start: State>(
+        machine: &'a StateMachine<D>,
+        data: D,
+        start: Start::Income,
+    ) -> Option<Self> {
+        StateMachineRunner::new::<Start>(machine, data, start).map(Self::from)
+    }
+
+    /// Returns the wrapped synchronous runner
+    pub fn into_inner(self) -> StateMachineRunner<'a, D> {
+        self.runner
+    }
+
+    /// Sets whether panics in the methods of states are caught by the runner,
+    /// including panics while polling the handle of an AsyncState
+    /// 
+    /// See StateMachineRunner::set_catch_panics
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.runner.set_catch_panics(catch_panics);
+    }
+
+    /// See StateMachineRunner::set_deadline
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.runner.set_deadline(deadline);
+    }
+
+    /// See StateMachineRunner::set_step_budget
+    pub fn set_step_budget(&mut self, steps: u64) {
+        self.runner.set_step_budget(steps);
+    }
+
+    /// See StateMachineRunner::set_bail_out_state
+    pub fn set_bail_out_state<T: State<Data = D>>(&mut self) -> bool
+    where
+        T::Income: From<Limit>,
+    {
+        self.runner.set_bail_out_state::<T>()
+    }
+
+    /// Attaches an observer which is notified of every following step of the runner
+    /// 
+    /// See StateMachineRunner::add_observer
+    pub fn add_observer(&mut self, observer: impl RunnerObserver<D> + 'static) {
+        self.runner.add_observer(observer);
+    }
+
+    /// Perform one step of the state machine, awaiting the handle of the current state
+    /// Returns an outcome representing all possible outcomes of the step
+    ///
+    /// The runner returned in the outcome can be converted back into an
+    /// AsyncStateMachineRunner with `into`
+    pub async fn step(self) -> StepOutcome<StateMachineRunner<'a, D>> {
+        self.runner.step_async().await
+    }
+
+    /// Run the state machine until it either errors or completes
+    pub async fn run_to_completion(mut self) -> Result<D, RunError<D>> {
+        loop {
+            self = match self.step().await.into_runner() {
+                Ok(machine) => Self::from(machine),
+                Err(result) => return result,
+            }
+        }
+    }
+
+    /// Run to completion but print all notable steps
+    pub async fn run_to_completion_verbose(mut self) -> Result<D, RunError<D>> {
+        loop {
+            let result = self.step().await;
+            result.print_if_notable();
+            self = match result.into_runner() {
+                Ok(machine) => Self::from(machine),
+                Err(result) => return result,
+            }
+        }
+    }
+}
+
+/// Wakes a thread parked in block_on
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion on the current thread
+///
+/// This is a minimal executor, used by StateMachineRunner when it steps an AsyncStateStruct.
+/// It cannot drive futures which depend on the reactor of a specific runtime
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use super::{block_on, AsyncState, AsyncStateMachineRunner, AsyncStateStruct};
+    use crate::sm::{OutcomeData, State, StateMachine, StepOutcome};
+
+    /// A future which is pending once before completing
+    #[derive(Default)]
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct WaitForFrameInner;
+
+    impl AsyncState for WaitForFrameInner {
+        type Income = ();
+        type Transition = OutcomeData<Process>;
+        type Data = Vec<&'static str>;
+
+        async fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            YieldOnce::default().await;
+            data.push("frame");
+            OutcomeData::new(data.len())
+        }
+
+        fn name(&self) -> String {
+            "WaitForFrame".to_string()
+        }
+    }
+
+    type WaitForFrame = AsyncStateStruct<WaitForFrameInner>;
+
+    #[derive(Default)]
+    struct Process;
+
+    impl State for Process {
+        type Income = usize;
+        type Transition = ();
+        type Data = Vec<&'static str>;
+
+        fn init(&mut self, previous: Box<Self::Income>) {
+            assert_eq!(*previous, 1);
+        }
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.push("process");
+        }
+
+        fn name(&self) -> String {
+            "Process".to_string()
+        }
+    }
+
+    fn machine() -> StateMachine<Vec<&'static str>> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<WaitForFrame>();
+        machine.add_state::<Process>();
+        machine
+    }
+
+    #[test]
+    fn async_runner() {
+        let machine = machine();
+        let runner = AsyncStateMachineRunner::new::<WaitForFrame>(&machine, Vec::new(), ()).unwrap();
+        let runner = match block_on(runner.step()) {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(start, "WaitForFrame");
+                assert_eq!(transition, std::any::type_name::<Process>());
+                assert_eq!(end, "Process");
+                AsyncStateMachineRunner::from(machine)
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        let data = block_on(runner.run_to_completion()).expect("Should not error");
+        assert_eq!(data, vec!["frame", "process"]);
+    }
+
+    #[test]
+    fn sync_runner_blocks_on_async_state() {
+        let machine = machine();
+        let runner = machine.runner::<WaitForFrame>(Vec::new(), ()).unwrap();
+        let data = runner.run_to_completion().expect("Should not error");
+        assert_eq!(data, vec!["frame", "process"]);
+    }
+
+    #[derive(Default)]
+    struct DropFrameInner;
+
+    impl AsyncState for DropFrameInner {
+        type Income = ();
+        type Transition = ();
+        type Data = Vec<&'static str>;
+
+        async fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.push("frame");
+            YieldOnce::default().await;
+            panic!("Camera disconnected");
+        }
+
+        fn name(&self) -> String {
+            "DropFrame".to_string()
+        }
+    }
+
+    #[test]
+    fn caught_panic_while_polling() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<AsyncStateStruct<DropFrameInner>>();
+        let mut runner = AsyncStateMachineRunner::new::<AsyncStateStruct<DropFrameInner>>(
+            &machine,
+            Vec::new(),
+            (),
+        )
+        .unwrap();
+        runner.set_catch_panics(true);
+        match block_on(runner.step()) {
+            StepOutcome::Panicked {
+                state,
+                message,
+                data,
+            } => {
+                assert_eq!(state, "DropFrame");
+                assert_eq!(message, "Camera disconnected");
+                assert_eq!(data, vec!["frame"]);
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+}
@@ -1,9 +1,17 @@
+use core::fmt;
 use std::{
-    any::type_name,
+    any::{type_name, Any, TypeId},
+    collections::VecDeque,
+    fmt::Display,
+    marker::PhantomData,
     time::{Duration, Instant},
 };
 
-use crate::sm::{IntoOutcome, State};
+use crate::sm::{
+    BoxedFuture, BoxedOutcome, ContinueOutcome, DeclaredTransition, History, IncomeAdapters,
+    IntoOutcome, Outcome, OutcomeData, State, StateFactory, StateInternal, StateMachine,
+    StepReport,
+};
 
 /// Type useful for States which may loop endlessly
 /// 
@@ -14,12 +22,18 @@ pub trait TimedState: Default + 'static {
     type Transition: IntoOutcome;
     type Data;
 
-    #[allow(unused)]
+    #[allow(unused, clippy::boxed_local)]
     fn init(&mut self, previous: Box<Self::Income>) -> Option<Duration> {
         None
     }
+    /// Returning None from resume keeps both the previous timeout and the previous start time
+    fn resume(&mut self, previous: Box<Self::Income>) -> Option<Duration> {
+        self.init(previous)
+    }
     fn handle_if_not_timeout(&mut self, data: &mut Self::Data) -> Self::Transition;
     fn handle_once_timeout(&mut self, data: &mut Self::Data) -> Self::Transition;
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
 
     fn name(&self) -> String {
         type_name::<Self>().to_string()
@@ -92,6 +106,13 @@ impl<S: TimedState> State for TimedStateStruct<S> {
         }
     }
 
+    fn resume(&mut self, previous: Box<Self::Income>) {
+        if let Some(timeout) = self.state.resume(previous) {
+            self.start_time = Instant::now();
+            self.timeout = timeout;
+        }
+    }
+
     fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
         if (Instant::now() - self.start_time) > self.timeout {
             self.state.handle_once_timeout(data)
@@ -100,7 +121,1967 @@ impl<S: TimedState> State for TimedStateStruct<S> {
         }
     }
 
+    fn exit(&mut self, data: &mut Self::Data) {
+        self.state.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.state.name()
+    }
+}
+
+/// Type useful for States which run an entire nested state machine
+///
+/// The nested machine is stepped once each time the enclosing state is handled
+/// and shares the data of the enclosing machine, allowing a machine of task states
+/// to be reused as a single state in several missions
+pub trait SubMachineState: Default + 'static {
+    type Income: 'static;
+    type Transition: IntoOutcome;
+    type Data: 'static;
+    /// The state the nested machine starts in each time this state is entered
+    ///
+    /// The nested machine also restarts from this state with the same income
+    /// if this state continues after the nested machine completed or errored
+    type Start: State<Data = Self::Data, Income: Clone>;
+
+    /// Adds the states of the nested machine
+    ///
+    /// This method is run once whenever a new instance of the state is created
+    fn build(machine: &mut StateMachine<Self::Data>);
+    /// Converts the data sent by the previous state into the income of the nested start state
+    fn start(&mut self, previous: Box<Self::Income>) -> <Self::Start as State>::Income;
+    /// The same as start, but run when this state is resumed because of Retention::Persistent
+    /// 
+    /// The nested machine continues from the nested state it was in when this state was left,
+    /// so the returned income is only used if the nested machine had already finished,
+    /// in which case it restarts from the start state
+    fn resume(&mut self, previous: Box<Self::Income>) -> <Self::Start as State>::Income {
+        self.start(previous)
+    }
+    /// This method is run once the nested machine transitions to `()`
+    fn handle_complete(&mut self, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run if the nested machine cannot continue
+    ///
+    /// error is always a StateNotFound or an IncorrectTransition
+    fn handle_error(&mut self, error: StepReport, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run once when transitioning away from this state,
+    /// after the exit of any state still running in the nested machine.
+    /// If this state is kept by Retention::Persistent, the nested state is kept without exiting it
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
+
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+}
+
+/// This struct wraps SubMachineState types and provides a functional State implementation
+/// for all SubMachineState types
+///
+/// ```
+/// use umrsm::{sm::{ContinueOutcome, IntoOutcome, OutcomeData, State, StateMachine, StepReport}, sm_ext::{SubMachine, SubMachineState}};
+///
+/// #[derive(Default)]
+/// struct Count;
+///
+/// impl State for Count {
+///     type Income = usize;
+///     type Transition = ();
+///     type Data = usize;
+///
+///     fn init(&mut self, previous: Box<Self::Income>) {
+///         assert_eq!(*previous, 10);
+///     }
+///
+///     fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+///         *data += 1;
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct CountTwiceInner;
+///
+/// impl SubMachineState for CountTwiceInner {
+///     type Income = ();
+///     type Transition = ();
+///     type Data = usize;
+///     type Start = Count;
+///
+///     fn build(machine: &mut StateMachine<usize>) {
+///         machine.add_state::<Count>();
+///     }
+///
+///     fn start(&mut self, _previous: Box<Self::Income>) -> usize {
+///         10
+///     }
+///
+///     fn handle_complete(&mut self, data: &mut Self::Data) -> Self::Transition {
+///         *data *= 2;
+///     }
+///
+///     fn handle_error(&mut self, error: StepReport, _data: &mut Self::Data) -> Self::Transition {
+///         panic!("{error}")
+///     }
+/// }
+///
+/// type CountTwice = SubMachine<CountTwiceInner>;
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<CountTwice>();
+///
+/// let runner = machine.runner::<CountTwice>(1, ()).expect("CountTwice exists in the machine");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), 4);
+/// ```
+pub struct SubMachine<S: SubMachineState> {
+    machine: StateMachine<S::Data>,
+    /// The income the nested machine was last started with
+    start: Option<<S::Start as State>::Income>,
+    state: Option<Box<dyn StateInternal<S::Data>>>,
+    history: History<S::Data>,
+    inner: S,
+}
+
+impl<S: SubMachineState> Default for SubMachine<S> {
+    fn default() -> Self {
+        let mut machine = StateMachine::default();
+        S::build(&mut machine);
+        Self {
+            machine,
+            start: None,
+            state: None,
+            history: Default::default(),
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<S: SubMachineState> SubMachine<S> {
+    /// Starts the nested machine from its start state with `start`
+    fn restart(&mut self, start: <S::Start as State>::Income) {
+        self.start = Some(start.clone());
+        self.state = self.machine.start_state::<S::Start>(&mut self.history, start);
+    }
+
+    /// Restarts the nested machine if it already completed or errored
+    fn restart_if_finished(&mut self) {
+        if let (None, Some(start)) = (&self.state, self.start.clone()) {
+            self.restart(start);
+        }
+    }
+
+    /// Reports that the nested start state was not present in the nested machine
+    fn missing_start(&mut self, data: &mut S::Data) -> BoxedOutcome {
+        let error = StepReport::StateNotFound {
+            start: self.inner.name(),
+            transition: "(Start)".to_string(),
+            end: TypeId::of::<S::Start>(),
+        };
+        self.inner.handle_error(error, data).into_outcome()
+    }
+
+    /// Maps the report of a step of the nested machine into a transition of this state
+    fn finish_step(&mut self, report: StepReport, data: &mut S::Data) -> BoxedOutcome {
+        match report {
+            StepReport::Continue
+            | StepReport::Transition { .. }
+            | StepReport::Recovered { .. }
+            | StepReport::Vetoed { .. } => ContinueOutcome::<Self>::default().into_outcome(),
+            StepReport::Complete { .. } => {
+                self.state = None;
+                self.inner.handle_complete(data).into_outcome()
+            }
+            error => {
+                self.state = None;
+                self.inner.handle_error(error, data).into_outcome()
+            }
+        }
+    }
+}
+
+impl<S: SubMachineState> State for SubMachine<S> {
+    type Income = S::Income;
+    type Transition = BoxedOutcome;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        let start = self.inner.start(previous);
+        self.restart(start);
+    }
+
+    fn resume(&mut self, previous: Box<Self::Income>) {
+        let start = self.inner.resume(previous);
+        if self.state.is_none() {
+            self.restart(start);
+        }
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        self.restart_if_finished();
+        let Some(state) = &mut self.state else {
+            return self.missing_start(data);
+        };
+        let report = self.machine.step_state(state, &mut self.history, data);
+        self.finish_step(report, data)
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(async move {
+            self.restart_if_finished();
+            let Some(state) = &mut self.state else {
+                return self.missing_start(data);
+            };
+            let report = self.machine.step_state_async(state, &mut self.history, data).await;
+            self.finish_step(report, data)
+        })
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        if let Some(mut state) = self.state.take() {
+            state.exit(data);
+            self.machine.retain_state(state, &mut self.history);
+        }
+        self.inner.exit(data);
+    }
+
+    /// Keeps the nested state without exiting it, so that the nested machine continues from it
+    /// once this state is resumed
+    fn suspend(&mut self, data: &mut Self::Data) {
+        self.inner.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        S::Transition::declared_transitions()
+    }
+}
+
+/// A child state of a combinator state which has not been entered yet
+struct Child<Data: 'static> {
+    factory: StateFactory<Data>,
+    income: Box<dyn Any>,
+}
+
+/// The child states of a combinator state, along with the Income each child is entered with
+///
+/// Children are entered with their init method, in the order they were added
+pub struct Children<Data: 'static> {
+    children: VecDeque<Child<Data>>,
+}
+
+// Manually implemented because derive macro requires D: Default
+impl<D> Default for Children<D> {
+    fn default() -> Self {
+        Self {
+            children: VecDeque::new(),
+        }
+    }
+}
+
+impl<D: 'static> Children<D> {
+    /// Adds T as the next child, to be entered with `income`
+    pub fn add<T: State<Data = D>>(&mut self, income: T::Income) -> &mut Self {
+        self.children.push_back(Child {
+            factory: || Box::<T>::default() as _,
+            income: Box::new(income),
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Enters the next child, which is at `index` in the order the children were added
+    fn start_next(&mut self, index: usize) -> Option<RunningChild<D>> {
+        let child = self.children.pop_front()?;
+        let mut state = (child.factory)();
+        state
+            .enter(child.income, &IncomeAdapters::default())
+            .expect("Children::add ensures the income matches the child");
+        Some(RunningChild { index, state })
+    }
+
+    /// Enters every child, in the order they were added
+    fn start_all(&mut self) -> Vec<RunningChild<D>> {
+        let mut running = Vec::with_capacity(self.len());
+        while let Some(child) = self.start_next(running.len()) {
+            running.push(child);
+        }
+        running
+    }
+}
+
+/// A child state of a combinator state which has been entered
+struct RunningChild<Data> {
+    index: usize,
+    state: Box<dyn StateInternal<Data>>,
+}
+
+impl<D: 'static> RunningChild<D> {
+    /// Returns None if `outcome` continues in the child,
+    /// otherwise runs the exit of the child and returns its outcome
+    fn finish(&mut self, outcome: BoxedOutcome, data: &mut D) -> Option<ChildOutcome> {
+        if outcome.state_type() == <dyn StateInternal<D> as Any>::type_id(&*self.state) {
+            return None;
+        }
+        self.state.exit(data);
+        Some(ChildOutcome {
+            index: self.index,
+            child: self.state.name(),
+            outcome,
+        })
+    }
+}
+
+/// The outcome of a child state of a combinator state which did not continue in the child
+pub struct ChildOutcome {
+    /// The position of the child, in the order the children were added
+    pub index: usize,
+    /// The name of the child state
+    pub child: String,
+    /// The outcome produced by the child, which may be returned as a transition
+    /// of the combinator state to take the transition the child would have taken
+    pub outcome: BoxedOutcome,
+}
+
+impl ChildOutcome {
+    /// Returns true if the child transitioned to `()`
+    pub fn is_complete(&self) -> bool {
+        self.outcome.state_type() == TypeId::of::<()>()
+    }
+
+    /// The name of the outcome produced by the child
+    pub fn transition(&self) -> String {
+        self.outcome.name()
+    }
+}
+
+impl fmt::Debug for ChildOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChildOutcome")
+            .field("index", &self.index)
+            .field("child", &self.child)
+            .field("transition", &self.transition())
+            .finish()
+    }
+}
+
+impl Display for ChildOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} --[{}]-->", self.child, self.transition())
+    }
+}
+
+/// Type useful for States which run several states one after another
+///
+/// Each child is entered once the previous child transitions to `()`,
+/// and is handled in place of the enclosing state until it does the same.
+/// A child transitioning to any state other than itself ends the sequence early
+pub trait SequenceState: Default + 'static {
+    type Income: 'static;
+    type Transition: IntoOutcome;
+    type Data: 'static;
+
+    /// Adds the children of the sequence, in the order they run
+    ///
+    /// This method is run each time this state is entered, restarting the sequence
+    fn children(&mut self, previous: Box<Self::Income>, children: &mut Children<Self::Data>);
+    /// This method is run once the last child transitions to `()`
+    fn handle_complete(&mut self, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run once a child transitions to a state other than itself or `()`
+    fn handle_failure(&mut self, failure: ChildOutcome, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run once when transitioning away from this state,
+    /// after the exit of the running child if the sequence was left early
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
+
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+}
+
+/// This struct wraps SequenceState types and provides a functional State implementation
+/// for all SequenceState types
+///
+/// ```
+/// use umrsm::{sm::{OutcomeData, State, StateMachine}, sm_ext::{ChildOutcome, Children, Sequence, SequenceState}};
+///
+/// #[derive(Default)]
+/// struct Turn;
+///
+/// impl State for Turn {
+///     type Income = u32;
+///     type Transition = ();
+///     type Data = Vec<u32>;
+///
+///     fn init(&mut self, previous: Box<Self::Income>) {
+///         assert!(*previous > 0);
+///     }
+///
+///     fn handle(&mut self, headings: &mut Self::Data) -> Self::Transition {
+///         headings.push(headings.len() as u32 * 90);
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Surface;
+///
+/// impl State for Surface {
+///     type Income = ();
+///     type Transition = ();
+///     type Data = Vec<u32>;
+///
+///     fn handle(&mut self, _headings: &mut Self::Data) -> Self::Transition {}
+/// }
+///
+/// #[derive(Default)]
+/// struct SquareInner;
+///
+/// impl SequenceState for SquareInner {
+///     type Income = ();
+///     type Transition = OutcomeData<Surface>;
+///     type Data = Vec<u32>;
+///
+///     fn children(&mut self, _previous: Box<Self::Income>, children: &mut Children<Vec<u32>>) {
+///         for side in 1..=4 {
+///             children.add::<Turn>(side);
+///         }
+///     }
+///
+///     fn handle_complete(&mut self, _headings: &mut Self::Data) -> Self::Transition {
+///         OutcomeData::new(())
+///     }
+///
+///     fn handle_failure(&mut self, failure: ChildOutcome, _headings: &mut Self::Data) -> Self::Transition {
+///         panic!("{failure}")
+///     }
+/// }
+///
+/// type Square = Sequence<SquareInner>;
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<Square>();
+/// machine.add_state::<Surface>();
+///
+/// let runner = machine.runner::<Square>(Vec::new(), ()).expect("Square exists in the machine");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), [0, 90, 180, 270]);
+/// ```
+pub struct Sequence<S: SequenceState> {
+    children: Children<S::Data>,
+    current: Option<RunningChild<S::Data>>,
+    inner: S,
+}
+
+impl<S: SequenceState> Default for Sequence<S> {
+    fn default() -> Self {
+        Self {
+            children: Default::default(),
+            current: None,
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<S: SequenceState> Sequence<S> {
+    /// Maps the outcome of the running child into a transition of this state
+    fn finish_step(&mut self, outcome: BoxedOutcome, data: &mut S::Data) -> BoxedOutcome {
+        let Some(child) = &mut self.current else {
+            return self.inner.handle_complete(data).into_outcome();
+        };
+        let Some(finished) = child.finish(outcome, data) else {
+            return ContinueOutcome::<Self>::default().into_outcome();
+        };
+        if !finished.is_complete() {
+            self.current = None;
+            return self.inner.handle_failure(finished, data).into_outcome();
+        }
+        self.current = self.children.start_next(finished.index + 1);
+        match self.current {
+            Some(_) => ContinueOutcome::<Self>::default().into_outcome(),
+            None => self.inner.handle_complete(data).into_outcome(),
+        }
+    }
+}
+
+impl<S: SequenceState> State for Sequence<S> {
+    type Income = S::Income;
+    type Transition = BoxedOutcome;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        self.children = Children::default();
+        self.inner.children(previous, &mut self.children);
+        self.current = self.children.start_next(0);
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        let Some(child) = &mut self.current else {
+            return self.inner.handle_complete(data).into_outcome();
+        };
+        let outcome = child.state.handle(data);
+        self.finish_step(outcome, data)
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(async move {
+            let Some(child) = &mut self.current else {
+                return self.inner.handle_complete(data).into_outcome();
+            };
+            let outcome = child.state.handle_async(data).await;
+            self.finish_step(outcome, data)
+        })
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        if let Some(mut child) = self.current.take() {
+            child.state.exit(data);
+        }
+        self.inner.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        S::Transition::declared_transitions()
+    }
+}
+
+/// Type useful for States which may fail and should be attempted again before giving up
+///
+/// A failed attempt is an outcome for which is_failure returns true.
+/// After a failed attempt the state is exited and, once the backoff has elapsed,
+/// initialised again with the Income it was first entered with
+pub trait Retryable: State
+where
+    Self::Income: Clone,
+{
+    /// The most attempts made before giving up, including the first attempt
+    const ATTEMPTS: u32;
+
+    /// Returns true if `outcome` is a failed attempt
+    fn is_failure(outcome: &dyn Outcome) -> bool;
+    /// The time to wait after the failed attempt `attempt` before starting the next one
+    ///
+    /// Attempts count from 1. The enclosing state machine continues to be stepped while waiting
+    #[allow(unused)]
+    fn backoff(attempt: u32) -> Duration {
+        Duration::ZERO
+    }
+    /// This method is run instead of init at the start of every attempt, counting from 1
+    fn init_attempt(&mut self, previous: Box<Self::Income>, attempt: u32) {
+        let _ = attempt;
+        self.init(previous)
+    }
+    /// This method is run once the last attempt fails and returns the transition taken instead
+    ///
+    /// The default implementation takes the transition of the last failed attempt.
+    /// Continuing in the Retry state instead starts over from the first attempt
+    #[allow(unused)]
+    fn give_up(&mut self, failure: BoxedOutcome, data: &mut Self::Data) -> BoxedOutcome {
+        failure
+    }
+}
+
+/// This struct wraps Retryable types and provides a State implementation
+/// which retries them until they succeed or run out of attempts
+///
+/// ```
+/// use std::time::Duration;
+/// use umrsm::{sm::{BoxedOutcome, IntoOutcome, Outcome, OutcomeData, State, StateMachine}, sm_ext::{Retry, Retryable}};
+///
+/// #[derive(Default)]
+/// struct Grab {
+///     attempt: u32,
+/// }
+///
+/// impl State for Grab {
+///     type Income = u8;
+///     type Transition = BoxedOutcome;
+///     type Data = Vec<u32>;
+///
+///     fn handle(&mut self, attempts: &mut Self::Data) -> Self::Transition {
+///         attempts.push(self.attempt);
+///         if self.attempt < 3 {
+///             OutcomeData::<Search>::with_name((), "slipped".to_string()).into_outcome()
+///         } else {
+///             ().into_outcome()
+///         }
+///     }
+/// }
+///
+/// impl Retryable for Grab {
+///     const ATTEMPTS: u32 = 5;
+///
+///     fn is_failure(outcome: &dyn Outcome) -> bool {
+///         outcome.name() == "slipped"
+///     }
+///
+///     fn backoff(attempt: u32) -> Duration {
+///         Duration::from_millis(1 << attempt)
+///     }
+///
+///     fn init_attempt(&mut self, marker: Box<u8>, attempt: u32) {
+///         assert_eq!(*marker, 7);
+///         self.attempt = attempt;
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Search;
+///
+/// impl State for Search {
+///     type Income = ();
+///     type Transition = ();
+///     type Data = Vec<u32>;
+///
+///     fn handle(&mut self, _attempts: &mut Self::Data) -> Self::Transition {}
+/// }
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<Retry<Grab>>();
+/// machine.add_state::<Search>();
+///
+/// let runner = machine.runner::<Retry<Grab>>(Vec::new(), 7).expect("Retry<Grab> exists in the machine");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), [1, 2, 3]);
+/// ```
+pub struct Retry<S: Retryable>
+where
+    S::Income: Clone,
+{
+    state: S,
+    /// The Income each attempt is initialised with
+    income: Option<S::Income>,
+    attempt: u32,
+    /// Set while waiting for the backoff of a failed attempt to elapse
+    retry_at: Option<Instant>,
+}
+
+impl<S: Retryable> Default for Retry<S>
+where
+    S::Income: Clone,
+{
+    fn default() -> Self {
+        Self {
+            state: Default::default(),
+            income: None,
+            attempt: 0,
+            retry_at: None,
+        }
+    }
+}
+
+impl<S: Retryable> Retry<S>
+where
+    S::Income: Clone,
+{
+    /// Starts the next attempt once the backoff has elapsed,
+    /// or the first attempt if the state machine continued in this state after giving up
+    /// Returns false if the backoff has not elapsed yet
+    fn ready(&mut self) -> bool {
+        if let Some(retry_at) = self.retry_at {
+            if Instant::now() < retry_at {
+                return false;
+            }
+            self.retry_at = None;
+        } else if self.attempt != 0 {
+            return true;
+        }
+        self.attempt += 1;
+        let income = self.income.clone().expect("Set when the state is entered");
+        self.state.init_attempt(Box::new(income), self.attempt);
+        true
+    }
+
+    /// Maps the outcome of an attempt into a transition of this state
+    fn finish_step(&mut self, outcome: BoxedOutcome, data: &mut S::Data) -> BoxedOutcome {
+        if outcome.state_type() == TypeId::of::<S>() {
+            return ContinueOutcome::<Self>::default().into_outcome();
+        }
+        if !S::is_failure(&*outcome) {
+            return outcome;
+        }
+        self.state.exit(data);
+        if self.attempt >= S::ATTEMPTS {
+            self.attempt = 0;
+            return self.state.give_up(outcome, data);
+        }
+        self.retry_at = Some(Instant::now() + S::backoff(self.attempt));
+        ContinueOutcome::<Self>::default().into_outcome()
+    }
+
+    fn start(&mut self, previous: Box<S::Income>) {
+        self.income = Some((*previous).clone());
+        self.attempt = 1;
+        self.retry_at = None;
+        self.state.init_attempt(previous, 1);
+    }
+}
+
+impl<S: Retryable> State for Retry<S>
+where
+    S::Income: Clone,
+{
+    type Income = S::Income;
+    type Transition = BoxedOutcome;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        self.start(previous);
+    }
+
+    fn resume(&mut self, previous: Box<Self::Income>) {
+        self.start(previous);
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        if !self.ready() {
+            return ContinueOutcome::<Self>::default().into_outcome();
+        }
+        let outcome = self.state.handle(data).into_outcome();
+        self.finish_step(outcome, data)
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(async move {
+            if !self.ready() {
+                return ContinueOutcome::<Self>::default().into_outcome();
+            }
+            let outcome = self.state.handle_async(data).await.into_outcome();
+            self.finish_step(outcome, data)
+        })
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        // The state was already exited after a failed attempt or giving up
+        if self.retry_at.is_none() && self.attempt != 0 {
+            self.state.exit(data);
+        }
+        self.retry_at = None;
+        self.attempt = 0;
+    }
+
     fn name(&self) -> String {
         self.state.name()
     }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        let mut declared = S::declared_transitions()?;
+        declared.retain(|transition| transition.target != TypeId::of::<S>());
+        Some(declared)
+    }
+}
+
+/// The Income of the fallback of a Timeout, describing the state which ran out of time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expired {
+    /// The time since the wrapped state was entered
+    pub elapsed: Duration,
+    /// The name of the last outcome of the wrapped state, None if it was never handled
+    pub last_outcome: Option<String>,
+}
+
+/// Type useful for States which should be left for a fallback state once they run for too long,
+/// by wrapping them in a Timeout
+pub trait TimeoutState: State {
+    /// The time this state may run for before the fallback is taken
+    ///
+    /// This method is run each time this state is entered, after init or resume
+    fn timeout(&self) -> Duration;
+}
+
+/// Wraps any TimeoutState, transitioning to Fallback once the state has run for its timeout
+///
+/// Unlike TimedState, the wrapped state does not handle its own timeout.
+/// Once the timeout has elapsed the wrapped state is no longer handled,
+/// and the enclosing state machine takes a transition named `(Timeout)` to Fallback
+///
+/// ```
+/// use std::{thread, time::Duration};
+/// use umrsm::{sm::{ContinueOutcome, State, StateMachine}, sm_ext::{Expired, Timeout, TimeoutState}};
+///
+/// #[derive(Default)]
+/// struct Hover {
+///     millis: u64,
+/// }
+///
+/// impl State for Hover {
+///     type Income = u64;
+///     type Transition = ContinueOutcome<Self>;
+///     type Data = u32;
+///
+///     fn init(&mut self, millis: Box<Self::Income>) {
+///         self.millis = *millis;
+///     }
+///
+///     fn handle(&mut self, steps: &mut Self::Data) -> Self::Transition {
+///         *steps += 1;
+///         thread::sleep(Duration::from_millis(5));
+///         ContinueOutcome::default()
+///     }
+///
+///     fn name(&self) -> String {
+///         "Hover".to_string()
+///     }
+/// }
+///
+/// impl TimeoutState for Hover {
+///     fn timeout(&self) -> Duration {
+///         Duration::from_millis(self.millis)
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Surface;
+///
+/// impl State for Surface {
+///     type Income = Expired;
+///     type Transition = ();
+///     type Data = u32;
+///
+///     fn init(&mut self, expired: Box<Self::Income>) {
+///         assert!(expired.elapsed > Duration::from_millis(20));
+///         assert!(expired.last_outcome.is_some());
+///     }
+///
+///     fn handle(&mut self, _steps: &mut Self::Data) -> Self::Transition {}
+/// }
+///
+/// type HoverBriefly = Timeout<Hover, Surface>;
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<HoverBriefly>();
+/// machine.add_state::<Surface>();
+/// assert!(machine.to_dot().contains("[label=\"(Timeout)\"]"));
+///
+/// let runner = machine.runner::<HoverBriefly>(0, 20).expect("HoverBriefly exists in the machine");
+/// assert!(runner.run_to_completion().expect("Should not error") > 0);
+/// ```
+pub struct Timeout<S: TimeoutState, Fallback: State>
+where
+    Fallback::Income: From<Expired>,
+{
+    state: S,
+    start_time: Instant,
+    timeout: Duration,
+    last_outcome: Option<String>,
+    fallback: PhantomData<Fallback>,
+}
+
+impl<S: TimeoutState, F: State> Default for Timeout<S, F>
+where
+    F::Income: From<Expired>,
+{
+    fn default() -> Self {
+        Self {
+            state: Default::default(),
+            start_time: Instant::now(),
+            timeout: Duration::ZERO,
+            last_outcome: None,
+            fallback: PhantomData,
+        }
+    }
+}
+
+impl<S: TimeoutState, F: State> Timeout<S, F>
+where
+    F::Income: From<Expired>,
+{
+    /// Returns the transition to F if the timeout has elapsed
+    fn expired(&mut self) -> Option<BoxedOutcome> {
+        let elapsed = self.start_time.elapsed();
+        if elapsed <= self.timeout {
+            return None;
+        }
+        let expired = Expired {
+            elapsed,
+            last_outcome: self.last_outcome.take(),
+        };
+        Some(OutcomeData::<F>::with_name(expired.into(), "(Timeout)".to_string()).into_outcome())
+    }
+
+    /// Maps the outcome of the wrapped state into a transition of this state
+    fn finish_step(&mut self, outcome: BoxedOutcome) -> BoxedOutcome {
+        self.last_outcome = Some(outcome.name());
+        if outcome.state_type() == TypeId::of::<S>() {
+            ContinueOutcome::<Self>::default().into_outcome()
+        } else {
+            outcome
+        }
+    }
+}
+
+impl<S: TimeoutState, F: State> State for Timeout<S, F>
+where
+    F::Income: From<Expired>,
+{
+    type Income = S::Income;
+    type Transition = BoxedOutcome;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        self.start_time = Instant::now();
+        self.last_outcome = None;
+        self.state.init(previous);
+        self.timeout = self.state.timeout();
+    }
+
+    fn resume(&mut self, previous: Box<Self::Income>) {
+        self.start_time = Instant::now();
+        self.last_outcome = None;
+        self.state.resume(previous);
+        self.timeout = self.state.timeout();
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        if let Some(fallback) = self.expired() {
+            return fallback;
+        }
+        let outcome = self.state.handle(data).into_outcome();
+        self.finish_step(outcome)
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(async move {
+            if let Some(fallback) = self.expired() {
+                return fallback;
+            }
+            let outcome = self.state.handle_async(data).await.into_outcome();
+            self.finish_step(outcome)
+        })
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        self.state.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.state.name()
+    }
+
+    /// Declares the transitions of the wrapped state along with the `(Timeout)` transition,
+    /// which is declared even if the wrapped state does not declare its transitions
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        let mut declared = S::declared_transitions().unwrap_or_default();
+        declared.retain(|transition| transition.target != TypeId::of::<S>());
+        declared.push(DeclaredTransition::to::<F>("(Timeout)"));
+        Some(declared)
+    }
+}
+
+/// Type useful for States which run several states at once until one of them finishes
+///
+/// Every child is handled each time the enclosing state is handled, in the order they were added,
+/// against the same data. The first child to produce an outcome which does not continue
+/// in that child wins, and every other child is exited.
+/// A race without children could never be won, so it is handled by handle_empty instead
+pub trait RaceState: Default + 'static {
+    type Income: 'static;
+    type Transition: IntoOutcome;
+    type Data: 'static;
+
+    /// Adds the children of the race
+    ///
+    /// This method is run each time this state is entered, restarting the race
+    fn children(&mut self, previous: Box<Self::Income>, children: &mut Children<Self::Data>);
+    /// This method is run once a child wins the race, after every other child has exited
+    fn handle_winner(&mut self, winner: ChildOutcome, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run instead of handling the children when no child is running,
+    /// either because children added none or because handle_winner continued in this state
+    fn handle_empty(&mut self, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run once when transitioning away from this state,
+    /// after the exit of any child still running
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
+
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+}
+
+/// This struct wraps RaceState types and provides a functional State implementation
+/// for all RaceState types
+///
+/// ```
+/// use umrsm::{sm::{ContinueOutcome, IntoOutcome, BoxedOutcome, OutcomeData, State, StateMachine}, sm_ext::{ChildOutcome, Children, Race, RaceState}};
+///
+/// #[derive(Default)]
+/// struct CameraSearch;
+///
+/// impl State for CameraSearch {
+///     type Income = ();
+///     type Transition = ContinueOutcome<Self>;
+///     type Data = u32;
+///
+///     fn handle(&mut self, _ticks: &mut Self::Data) -> Self::Transition {
+///         ContinueOutcome::default()
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct SonarSearch;
+///
+/// impl State for SonarSearch {
+///     type Income = ();
+///     type Transition = BoxedOutcome;
+///     type Data = u32;
+///
+///     fn handle(&mut self, ticks: &mut Self::Data) -> Self::Transition {
+///         *ticks += 1;
+///         if *ticks == 3 {
+///             OutcomeData::<Approach>::with_name(40, "pinged".to_string()).into_outcome()
+///         } else {
+///             ContinueOutcome::<Self>::default().into_outcome()
+///         }
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Approach;
+///
+/// impl State for Approach {
+///     type Income = u32;
+///     type Transition = ();
+///     type Data = u32;
+///
+///     fn init(&mut self, range: Box<Self::Income>) {
+///         assert_eq!(*range, 40);
+///     }
+///
+///     fn handle(&mut self, _ticks: &mut Self::Data) -> Self::Transition {}
+/// }
+///
+/// #[derive(Default)]
+/// struct SearchInner;
+///
+/// impl RaceState for SearchInner {
+///     type Income = ();
+///     type Transition = BoxedOutcome;
+///     type Data = u32;
+///
+///     fn children(&mut self, _previous: Box<Self::Income>, children: &mut Children<u32>) {
+///         children.add::<CameraSearch>(()).add::<SonarSearch>(());
+///     }
+///
+///     fn handle_winner(&mut self, winner: ChildOutcome, _ticks: &mut Self::Data) -> Self::Transition {
+///         assert_eq!(winner.index, 1);
+///         assert_eq!(winner.transition(), "pinged");
+///         winner.outcome
+///     }
+///
+///     fn handle_empty(&mut self, _ticks: &mut Self::Data) -> Self::Transition {
+///         unreachable!("The search always has children")
+///     }
+/// }
+///
+/// type Search = Race<SearchInner>;
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<Search>();
+/// machine.add_state::<Approach>();
+///
+/// let runner = machine.runner::<Search>(0, ()).expect("Search exists in the machine");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), 3);
+/// ```
+pub struct Race<S: RaceState> {
+    running: Vec<RunningChild<S::Data>>,
+    inner: S,
+}
+
+impl<S: RaceState> Default for Race<S> {
+    fn default() -> Self {
+        Self {
+            running: Vec::new(),
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<S: RaceState> Race<S> {
+    /// Maps the outcome of the child at `position` into a transition of this state,
+    /// returning None if the child continues
+    fn finish_child(
+        &mut self,
+        position: usize,
+        outcome: BoxedOutcome,
+        data: &mut S::Data,
+    ) -> Option<BoxedOutcome> {
+        let winner = self.running[position].finish(outcome, data)?;
+        self.running.remove(position);
+        for mut child in self.running.drain(..) {
+            child.state.exit(data);
+        }
+        Some(self.inner.handle_winner(winner, data).into_outcome())
+    }
+}
+
+impl<S: RaceState> State for Race<S> {
+    type Income = S::Income;
+    type Transition = BoxedOutcome;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        let mut children = Children::default();
+        self.inner.children(previous, &mut children);
+        self.running = children.start_all();
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        if self.running.is_empty() {
+            return self.inner.handle_empty(data).into_outcome();
+        }
+        for position in 0..self.running.len() {
+            let outcome = self.running[position].state.handle(data);
+            if let Some(transition) = self.finish_child(position, outcome, data) {
+                return transition;
+            }
+        }
+        ContinueOutcome::<Self>::default().into_outcome()
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(async move {
+            if self.running.is_empty() {
+                return self.inner.handle_empty(data).into_outcome();
+            }
+            for position in 0..self.running.len() {
+                let outcome = self.running[position].state.handle_async(data).await;
+                if let Some(transition) = self.finish_child(position, outcome, data) {
+                    return transition;
+                }
+            }
+            ContinueOutcome::<Self>::default().into_outcome()
+        })
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        for mut child in self.running.drain(..) {
+            child.state.exit(data);
+        }
+        self.inner.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        S::Transition::declared_transitions()
+    }
+}
+
+/// Type useful for States which run several states at once until all of them finish
+///
+/// Every child is handled each time the enclosing state is handled, in the order they were added,
+/// against the same data. A child finishes once it produces an outcome which does not continue
+/// in that child, after which it is exited and no longer handled.
+/// A join without children finishes the first time it is handled
+pub trait JoinState: Default + 'static {
+    type Income: 'static;
+    type Transition: IntoOutcome;
+    type Data: 'static;
+
+    /// Adds the children of the join
+    ///
+    /// This method is run each time this state is entered, restarting the join
+    fn children(&mut self, previous: Box<Self::Income>, children: &mut Children<Self::Data>);
+    /// This method is run once every child has finished,
+    /// with the outcome of every child in the order the children were added
+    fn handle_all(&mut self, outcomes: Vec<ChildOutcome>, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run once when transitioning away from this state,
+    /// after the exit of any child still running
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
+
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+}
+
+/// This struct wraps JoinState types and provides a functional State implementation
+/// for all JoinState types
+///
+/// ```
+/// use umrsm::{sm::{ContinueOutcome, IntoOutcome, BoxedOutcome, State, StateMachine}, sm_ext::{ChildOutcome, Children, Join, JoinState}};
+///
+/// #[derive(Default)]
+/// struct HomeArm {
+///     remaining: u32,
+/// }
+///
+/// impl State for HomeArm {
+///     type Income = u32;
+///     type Transition = BoxedOutcome;
+///     type Data = Vec<u32>;
+///
+///     fn init(&mut self, distance: Box<Self::Income>) {
+///         self.remaining = *distance;
+///     }
+///
+///     fn handle(&mut self, homed: &mut Self::Data) -> Self::Transition {
+///         self.remaining -= 1;
+///         if self.remaining == 0 {
+///             homed.push(homed.len() as u32);
+///             ().into_outcome()
+///         } else {
+///             ContinueOutcome::<Self>::default().into_outcome()
+///         }
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct HomeArmsInner;
+///
+/// impl JoinState for HomeArmsInner {
+///     type Income = ();
+///     type Transition = ();
+///     type Data = Vec<u32>;
+///
+///     fn children(&mut self, _previous: Box<Self::Income>, children: &mut Children<Vec<u32>>) {
+///         children.add::<HomeArm>(3).add::<HomeArm>(1);
+///     }
+///
+///     fn handle_all(&mut self, outcomes: Vec<ChildOutcome>, homed: &mut Self::Data) -> Self::Transition {
+///         assert!(outcomes.iter().all(ChildOutcome::is_complete));
+///         assert_eq!(outcomes[0].index, 0);
+///         homed.push(99);
+///     }
+/// }
+///
+/// type HomeArms = Join<HomeArmsInner>;
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<HomeArms>();
+///
+/// let runner = machine.runner::<HomeArms>(Vec::new(), ()).expect("HomeArms exists in the machine");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), [0, 1, 99]);
+/// ```
+pub struct Join<S: JoinState> {
+    running: Vec<RunningChild<S::Data>>,
+    finished: Vec<ChildOutcome>,
+    inner: S,
+}
+
+impl<S: JoinState> Default for Join<S> {
+    fn default() -> Self {
+        Self {
+            running: Vec::new(),
+            finished: Vec::new(),
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<S: JoinState> Join<S> {
+    /// Moves the child at `position` to finished if `outcome` does not continue in it
+    /// Returns true if the child is still running
+    fn finish_child(&mut self, position: usize, outcome: BoxedOutcome, data: &mut S::Data) -> bool {
+        let Some(finished) = self.running[position].finish(outcome, data) else {
+            return true;
+        };
+        self.running.remove(position);
+        self.finished.push(finished);
+        false
+    }
+
+    /// Maps the state of the children after a step into a transition of this state
+    fn finish_step(&mut self, data: &mut S::Data) -> BoxedOutcome {
+        if !self.running.is_empty() {
+            return ContinueOutcome::<Self>::default().into_outcome();
+        }
+        let mut outcomes = std::mem::take(&mut self.finished);
+        outcomes.sort_by_key(|outcome| outcome.index);
+        self.inner.handle_all(outcomes, data).into_outcome()
+    }
+}
+
+impl<S: JoinState> State for Join<S> {
+    type Income = S::Income;
+    type Transition = BoxedOutcome;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        let mut children = Children::default();
+        self.inner.children(previous, &mut children);
+        self.running = children.start_all();
+        self.finished.clear();
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        let mut position = 0;
+        while position < self.running.len() {
+            let outcome = self.running[position].state.handle(data);
+            if self.finish_child(position, outcome, data) {
+                position += 1;
+            }
+        }
+        self.finish_step(data)
+    }
+
+    fn handle_async<'s>(&'s mut self, data: &'s mut Self::Data) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(async move {
+            let mut position = 0;
+            while position < self.running.len() {
+                let outcome = self.running[position].state.handle_async(data).await;
+                if self.finish_child(position, outcome, data) {
+                    position += 1;
+                }
+            }
+            self.finish_step(data)
+        })
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        for mut child in self.running.drain(..) {
+            child.state.exit(data);
+        }
+        self.finished.clear();
+        self.inner.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        S::Transition::declared_transitions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{
+        ChildOutcome, Children, Expired, Join, JoinState, Race, RaceState, Retry, Retryable,
+        Sequence, SequenceState, SubMachine, SubMachineState, Timeout, TimeoutState,
+    };
+    use crate::{
+        sm::{
+            BoxedOutcome, ContinueOutcome, IntoOutcome, Outcome, OutcomeData, State, StateMachine,
+            StepOutcome, StepReport,
+        },
+        sm_global::GlobalTransition,
+    };
+
+    #[derive(Default)]
+    struct Leg {
+        left: u8,
+    }
+
+    impl State for Leg {
+        type Income = u8;
+        type Transition = BoxedOutcome;
+        type Data = Vec<String>;
+
+        fn init(&mut self, previous: Box<Self::Income>) {
+            self.left = *previous;
+        }
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("Leg".to_string());
+            if self.left == 0 {
+                return ().into_outcome();
+            }
+            self.left -= 1;
+            ContinueOutcome::<Self>::default().into_outcome()
+        }
+
+        fn exit(&mut self, log: &mut Self::Data) {
+            log.push("exit Leg".to_string());
+        }
+
+        fn name(&self) -> String {
+            "Leg".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Snag;
+
+    impl State for Snag {
+        type Income = ();
+        type Transition = OutcomeData<Surface>;
+        type Data = Vec<String>;
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("Snag".to_string());
+            OutcomeData::with_name((), "snagged".to_string())
+        }
+
+        fn exit(&mut self, log: &mut Self::Data) {
+            log.push("exit Snag".to_string());
+        }
+
+        fn name(&self) -> String {
+            "Snag".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Surface;
+
+    impl State for Surface {
+        type Income = ();
+        type Transition = ();
+        type Data = Vec<String>;
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("Surface".to_string());
+        }
+
+        fn name(&self) -> String {
+            "Surface".to_string()
+        }
+    }
+
+    /// Runs two legs, with a Snag between them if entered with true
+    #[derive(Default)]
+    struct PatrolInner;
+
+    impl SequenceState for PatrolInner {
+        type Income = bool;
+        type Transition = BoxedOutcome;
+        type Data = Vec<String>;
+
+        fn children(&mut self, snag: Box<Self::Income>, children: &mut Children<Vec<String>>) {
+            children.add::<Leg>(1);
+            if *snag {
+                children.add::<Snag>(());
+            }
+            children.add::<Leg>(0);
+        }
+
+        fn handle_complete(&mut self, _log: &mut Self::Data) -> Self::Transition {
+            OutcomeData::<Surface>::with_name((), "patrolled".to_string()).into_outcome()
+        }
+
+        fn handle_failure(
+            &mut self,
+            failure: ChildOutcome,
+            log: &mut Self::Data,
+        ) -> Self::Transition {
+            log.push(format!(
+                "failure {} {}",
+                failure.index,
+                failure.transition()
+            ));
+            failure.outcome
+        }
+
+        fn exit(&mut self, log: &mut Self::Data) {
+            log.push("exit Patrol".to_string());
+        }
+
+        fn name(&self) -> String {
+            "Patrol".to_string()
+        }
+    }
+
+    type Patrol = Sequence<PatrolInner>;
+
+    fn machine() -> StateMachine<Vec<String>> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Patrol>();
+        machine.add_state::<Surface>();
+        machine
+    }
+
+    #[test]
+    fn sequence_advances_through_children() {
+        let machine = machine();
+        let mut runner = machine.runner::<Patrol>(Vec::new(), false).unwrap();
+        for log in [vec!["Leg"], vec!["Leg", "Leg", "exit Leg"]] {
+            runner = match runner.step() {
+                StepOutcome::Continue { machine } => machine,
+                e => panic!("Unexpeced runner outcome {e:?}"),
+            };
+            assert_eq!(runner.data, log);
+        }
+        let runner = match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(start, "Patrol");
+                assert_eq!(transition, "patrolled");
+                assert_eq!(end, "Surface");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(
+            log,
+            [
+                "Leg",
+                "Leg",
+                "exit Leg",
+                "Leg",
+                "exit Leg",
+                "exit Patrol",
+                "Surface"
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_handles_failure() {
+        let machine = machine();
+        let mut runner = machine.runner::<Patrol>(Vec::new(), true).unwrap();
+        for _ in 0..2 {
+            runner = match runner.step() {
+                StepOutcome::Continue { machine } => machine,
+                e => panic!("Unexpeced runner outcome {e:?}"),
+            };
+        }
+        let runner = match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(start, "Patrol");
+                assert_eq!(transition, "snagged");
+                assert_eq!(end, "Surface");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(
+            log,
+            [
+                "Leg",
+                "Leg",
+                "exit Leg",
+                "Snag",
+                "exit Snag",
+                "failure 1 snagged",
+                "exit Patrol",
+                "Surface"
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_exits_interrupted_child() {
+        let mut machine = machine();
+        machine.add_global_transition(GlobalTransition::to::<Surface>(
+            "recalled",
+            |log: &Vec<String>| !log.is_empty(),
+            |_| (),
+        ));
+        let runner = machine.runner::<Patrol>(Vec::new(), false).unwrap();
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(log, ["Leg", "exit Leg", "exit Patrol", "Surface"]);
+    }
+
+    /// Succeeds on the attempt it is entered with, or never if entered with 0
+    #[derive(Default)]
+    struct Grab {
+        attempt: u32,
+        succeeds: u32,
+    }
+
+    impl State for Grab {
+        type Income = u32;
+        type Transition = BoxedOutcome;
+        type Data = Vec<String>;
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push(format!("Grab {}", self.attempt));
+            if self.attempt == self.succeeds {
+                return ().into_outcome();
+            }
+            OutcomeData::<Surface>::with_name((), "slipped".to_string()).into_outcome()
+        }
+
+        fn exit(&mut self, log: &mut Self::Data) {
+            log.push("exit Grab".to_string());
+        }
+
+        fn name(&self) -> String {
+            "Grab".to_string()
+        }
+    }
+
+    impl Retryable for Grab {
+        const ATTEMPTS: u32 = 3;
+
+        fn is_failure(outcome: &dyn Outcome) -> bool {
+            outcome.name() == "slipped"
+        }
+
+        fn backoff(attempt: u32) -> Duration {
+            Duration::from_millis(20 * attempt as u64)
+        }
+
+        fn init_attempt(&mut self, succeeds: Box<Self::Income>, attempt: u32) {
+            self.attempt = attempt;
+            self.succeeds = *succeeds;
+        }
+
+        fn give_up(&mut self, failure: BoxedOutcome, log: &mut Self::Data) -> BoxedOutcome {
+            log.push(format!("give up {}", self.attempt));
+            // Starts over once, then takes the transition of the last attempt
+            if log
+                .iter()
+                .filter(|entry| entry.starts_with("give up"))
+                .count()
+                == 1
+            {
+                return ContinueOutcome::<Retry<Grab>>::default().into_outcome();
+            }
+            failure
+        }
+    }
+
+    fn retry_machine() -> StateMachine<Vec<String>> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Retry<Grab>>();
+        machine.add_state::<Surface>();
+        machine
+    }
+
+    #[test]
+    fn retry_exits_each_failed_attempt() {
+        let machine = retry_machine();
+        let runner = machine.runner::<Retry<Grab>>(Vec::new(), 2).unwrap();
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(log, ["Grab 1", "exit Grab", "Grab 2", "exit Grab"]);
+    }
+
+    #[test]
+    fn retry_waits_for_backoff() {
+        let machine = retry_machine();
+        let mut runner = machine.runner::<Retry<Grab>>(Vec::new(), 0).unwrap();
+        for _ in 0..2 {
+            runner = match runner.step() {
+                StepOutcome::Continue { machine } => machine,
+                e => panic!("Unexpeced runner outcome {e:?}"),
+            };
+        }
+        assert_eq!(runner.data, ["Grab 1", "exit Grab"]);
+        thread::sleep(Duration::from_millis(20));
+        let runner = match runner.step() {
+            StepOutcome::Continue { machine } => machine,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        assert_eq!(runner.data, ["Grab 1", "exit Grab", "Grab 2", "exit Grab"]);
+    }
+
+    #[test]
+    fn retry_gives_up_after_attempts() {
+        let machine = retry_machine();
+        let runner = machine.runner::<Retry<Grab>>(Vec::new(), 0).unwrap();
+        let log = runner.run_to_completion().unwrap();
+        let round = [
+            "Grab 1",
+            "exit Grab",
+            "Grab 2",
+            "exit Grab",
+            "Grab 3",
+            "exit Grab",
+        ];
+        // Continuing after giving up starts over from the first attempt,
+        // and the last attempt is not exited again when leaving
+        let expected: Vec<_> = round
+            .iter()
+            .chain(&["give up 3"])
+            .chain(&round)
+            .chain(&["give up 3", "Surface"])
+            .collect();
+        assert_eq!(log.iter().collect::<Vec<_>>(), expected);
+    }
+
+    /// Races a leg of `Income` steps against a leg of one step, or runs no race if `Income` is None
+    #[derive(Default)]
+    struct ChaseInner;
+
+    impl RaceState for ChaseInner {
+        type Income = Option<u8>;
+        type Transition = OutcomeData<Surface>;
+        type Data = Vec<String>;
+
+        fn children(&mut self, steps: Box<Self::Income>, children: &mut Children<Vec<String>>) {
+            if let Some(steps) = *steps {
+                children.add::<Leg>(steps).add::<Leg>(0);
+            }
+        }
+
+        fn handle_winner(
+            &mut self,
+            winner: ChildOutcome,
+            log: &mut Self::Data,
+        ) -> Self::Transition {
+            log.push(format!("winner {}", winner.index));
+            OutcomeData::with_name((), "caught".to_string())
+        }
+
+        fn handle_empty(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("empty".to_string());
+            OutcomeData::with_name((), "no chase".to_string())
+        }
+
+        fn name(&self) -> String {
+            "Chase".to_string()
+        }
+    }
+
+    type Chase = Race<ChaseInner>;
+
+    fn race(steps: Option<u8>) -> (String, Vec<String>) {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Chase>();
+        machine.add_state::<Surface>();
+        let runner = machine.runner::<Chase>(Vec::new(), steps).unwrap();
+        let (runner, transition) = match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(start, "Chase");
+                assert_eq!(end, "Surface");
+                (machine, transition)
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        (transition, runner.run_to_completion().unwrap())
+    }
+
+    #[test]
+    fn race_exits_losers() {
+        let (transition, log) = race(Some(1));
+        assert_eq!(transition, "caught");
+        assert_eq!(
+            log,
+            ["Leg", "Leg", "exit Leg", "exit Leg", "winner 1", "Surface"]
+        );
+    }
+
+    #[test]
+    fn race_without_children() {
+        let (transition, log) = race(None);
+        assert_eq!(transition, "no chase");
+        assert_eq!(log, ["empty", "Surface"]);
+    }
+
+    #[derive(Default)]
+    struct SweepInner;
+
+    impl JoinState for SweepInner {
+        type Income = ();
+        type Transition = OutcomeData<Surface>;
+        type Data = Vec<String>;
+
+        fn children(&mut self, _previous: Box<Self::Income>, children: &mut Children<Vec<String>>) {
+            children.add::<Leg>(2).add::<Snag>(()).add::<Leg>(0);
+        }
+
+        fn handle_all(
+            &mut self,
+            outcomes: Vec<ChildOutcome>,
+            log: &mut Self::Data,
+        ) -> Self::Transition {
+            for outcome in outcomes {
+                log.push(format!("{} {}", outcome.index, outcome.is_complete()));
+            }
+            OutcomeData::new(())
+        }
+    }
+
+    #[test]
+    fn join_waits_for_every_child() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Join<SweepInner>>();
+        machine.add_state::<Surface>();
+        let runner = machine.runner::<Join<SweepInner>>(Vec::new(), ()).unwrap();
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(
+            log,
+            [
+                "Leg",
+                "Snag",
+                "exit Snag",
+                "Leg",
+                "exit Leg",
+                "Leg",
+                "Leg",
+                "exit Leg",
+                "0 true",
+                "1 false",
+                "2 true",
+                "Surface"
+            ]
+        );
+    }
+
+    /// Hovers for as many milliseconds as it is entered with before timing out
+    #[derive(Default)]
+    struct Hover {
+        millis: u64,
+    }
+
+    impl State for Hover {
+        type Income = u64;
+        type Transition = ContinueOutcome<Self>;
+        type Data = Vec<String>;
+
+        fn init(&mut self, millis: Box<Self::Income>) {
+            self.millis = *millis;
+        }
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("Hover".to_string());
+            ContinueOutcome::default()
+        }
+
+        fn exit(&mut self, log: &mut Self::Data) {
+            log.push("exit Hover".to_string());
+        }
+
+        fn name(&self) -> String {
+            "Hover".to_string()
+        }
+    }
+
+    impl TimeoutState for Hover {
+        fn timeout(&self) -> Duration {
+            Duration::from_millis(self.millis)
+        }
+    }
+
+    /// Logs whether the state which timed out was handled and ran for at least 10 milliseconds
+    #[derive(Default)]
+    struct Abort {
+        expired: Option<Expired>,
+    }
+
+    impl State for Abort {
+        type Income = Expired;
+        type Transition = ();
+        type Data = Vec<String>;
+
+        fn init(&mut self, expired: Box<Self::Income>) {
+            self.expired = Some(*expired);
+        }
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            let expired = self.expired.take().unwrap();
+            log.push(format!(
+                "Abort {} {}",
+                expired.last_outcome.is_some(),
+                expired.elapsed >= Duration::from_millis(10)
+            ));
+        }
+
+        fn name(&self) -> String {
+            "Abort".to_string()
+        }
+    }
+
+    type HoverBriefly = Timeout<Hover, Abort>;
+
+    fn timeout_machine() -> StateMachine<Vec<String>> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<HoverBriefly>();
+        machine.add_state::<Abort>();
+        machine
+    }
+
+    #[test]
+    fn timeout_exits_expired_state() {
+        let machine = timeout_machine();
+        let runner = machine.runner::<HoverBriefly>(Vec::new(), 10).unwrap();
+        let runner = match runner.step() {
+            StepOutcome::Continue { machine } => machine,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        thread::sleep(Duration::from_millis(10));
+        let runner = match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(start, "Hover");
+                assert_eq!(transition, "(Timeout)");
+                assert_eq!(end, "Abort");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(log, ["Hover", "exit Hover", "Abort true true"]);
+    }
+
+    #[test]
+    fn timeout_is_read_on_entry() {
+        let machine = timeout_machine();
+        let runner = machine.runner::<HoverBriefly>(Vec::new(), 0).unwrap();
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(log, ["exit Hover", "Abort false false"]);
+    }
+
+    /// Runs a single leg as a nested machine, twice in a row
+    #[derive(Default)]
+    struct LapsInner;
+
+    impl SubMachineState for LapsInner {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = Vec<String>;
+        type Start = Leg;
+
+        fn build(machine: &mut StateMachine<Self::Data>) {
+            machine.add_state::<Leg>();
+        }
+
+        fn start(&mut self, _previous: Box<Self::Income>) -> u8 {
+            0
+        }
+
+        fn handle_complete(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("lap".to_string());
+            if log.iter().filter(|entry| *entry == "lap").count() < 2 {
+                return ContinueOutcome::<Laps>::default().into_outcome();
+            }
+            OutcomeData::<Surface>::new(()).into_outcome()
+        }
+
+        fn handle_error(&mut self, error: StepReport, _log: &mut Self::Data) -> Self::Transition {
+            panic!("{error}")
+        }
+    }
+
+    type Laps = SubMachine<LapsInner>;
+
+    #[test]
+    fn sub_machine_restarts_when_continued() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Laps>();
+        machine.add_state::<Surface>();
+        let runner = machine.runner::<Laps>(Vec::new(), ()).unwrap();
+        let log = runner.run_to_completion().unwrap();
+        assert_eq!(
+            log,
+            ["Leg", "exit Leg", "lap", "Leg", "exit Leg", "lap", "Surface"]
+        );
+    }
 }
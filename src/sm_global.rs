@@ -0,0 +1,444 @@
+use core::fmt;
+use std::{
+    any::{type_name, Any, TypeId},
+    cmp::Reverse,
+};
+
+use crate::sm::{BoxedOutcome, GlobalHook, Outcome, State, StateMachine, Threading};
+
+/// Predicate deciding whether a global transition fires
+type GlobalPredicate<Data> = Box<dyn Fn(&Data) -> bool + Send + Sync>;
+
+/// Builds the Income of the target of a global transition
+type GlobalIncome<Data> = Box<dyn Fn(&Data) -> Box<dyn Any> + Send + Sync>;
+
+/// Determines when the predicate of a global transition is evaluated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalTiming {
+    /// Evaluated before the handle of the current state, which is not run if the transition fires
+    #[default]
+    BeforeHandle,
+    /// Evaluated after the handle of the current state,
+    /// replacing the outcome of the handle if the transition fires
+    AfterHandle,
+}
+
+/// A transition which may be taken from every state of a state machine,
+/// added with StateMachine::add_global_transition
+///
+/// Global transitions are evaluated by every step of the state machine,
+/// in order of decreasing priority, and at most one fires per step.
+/// Because BeforeHandle transitions are evaluated before the handle of the current state,
+/// an AfterHandle transition may not have a higher priority than a BeforeHandle transition
+/// of the same state machine, and BeforeHandle transitions win ties.
+/// A global transition never fires while the state machine is in its target state
+/// or in one of its exempt states
+///
+/// ```
+/// use umrsm::{sm::{BoxedOutcome, ContinueOutcome, IntoOutcome, State, StateMachine}, sm_global::GlobalTransition};
+///
+/// #[derive(Default)]
+/// struct Patrol;
+///
+/// impl State for Patrol {
+///     type Income = ();
+///     type Transition = BoxedOutcome;
+///     type Data = u32;
+///
+///     fn handle(&mut self, battery: &mut Self::Data) -> Self::Transition {
+///         *battery -= 10;
+///         ContinueOutcome::<Self>::default().into_outcome()
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Surface;
+///
+/// impl State for Surface {
+///     type Income = u32;
+///     type Transition = ();
+///     type Data = u32;
+///
+///     fn handle(&mut self, _battery: &mut Self::Data) -> Self::Transition {}
+/// }
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<Patrol>();
+/// machine.add_state::<Surface>();
+/// machine.add_global_transition(GlobalTransition::to::<Surface>(
+///     "battery low",
+///     |battery| *battery < 20,
+///     |battery| *battery,
+/// ));
+///
+/// let runner = machine.runner::<Patrol>(50, ()).expect("Patrol exists in the machine");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), 10);
+/// ```
+pub struct GlobalTransition<Data> {
+    name: String,
+    priority: i32,
+    timing: GlobalTiming,
+    predicate: GlobalPredicate<Data>,
+    target: TypeId,
+    target_name: &'static str,
+    income: GlobalIncome<Data>,
+    exempt: Vec<TypeId>,
+}
+
+impl<D> GlobalTransition<D> {
+    /// A global transition to T which fires once `predicate` returns true,
+    /// entering T with the Income built by `income`
+    ///
+    /// The transition has priority 0 and is evaluated before the handle of the current state
+    pub fn to<T: State<Data = D>>(
+        name: impl Into<String>,
+        predicate: impl Fn(&D) -> bool + Send + Sync + 'static,
+        income: impl Fn(&D) -> T::Income + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            priority: 0,
+            timing: GlobalTiming::default(),
+            predicate: Box::new(predicate),
+            target: TypeId::of::<T>(),
+            target_name: type_name::<T>(),
+            income: Box::new(move |data| Box::new(income(data))),
+            exempt: Vec::new(),
+        }
+    }
+
+    /// Global transitions with a higher priority are evaluated first
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_timing(mut self, timing: GlobalTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Prevents the transition from firing while the state machine is in T
+    pub fn exempt<T: State<Data = D>>(mut self) -> Self {
+        self.exempt.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+/// The outcome of a global transition which fired
+struct GlobalOutcome {
+    target: TypeId,
+    income: Box<dyn Any>,
+    name: String,
+}
+
+impl Outcome for GlobalOutcome {
+    fn state_type(&self) -> TypeId {
+        self.target
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        self.income
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// The global transitions of a state machine, sorted by decreasing priority
+/// and in the order they were added for equal priorities
+struct GlobalTransitions<D>(Vec<GlobalTransition<D>>);
+
+impl<D: 'static> GlobalHook<D> for GlobalTransitions<D> {
+    fn fire(&self, before_handle: bool, state: TypeId, data: &D) -> Option<BoxedOutcome> {
+        let timing = if before_handle {
+            GlobalTiming::BeforeHandle
+        } else {
+            GlobalTiming::AfterHandle
+        };
+        let transition = self.0.iter().find(|transition| {
+            transition.timing == timing
+                && transition.target != state
+                && !transition.exempt.contains(&state)
+                && (transition.predicate)(data)
+        })?;
+        Some(Box::new(GlobalOutcome {
+            target: transition.target,
+            income: (transition.income)(data),
+            name: transition.name.clone(),
+        }))
+    }
+
+    fn targets(&self) -> Vec<(String, TypeId, &'static str)> {
+        self.0
+            .iter()
+            .map(|transition| {
+                (
+                    transition.name.clone(),
+                    transition.target,
+                    transition.target_name,
+                )
+            })
+            .collect()
+    }
+}
+
+impl<D, K: Threading> StateMachine<D, K> {
+    /// Adds a transition which may be taken from every state of the state machine
+    ///
+    /// See GlobalTransition.
+    /// Returns false if the transition would give an AfterHandle transition a higher priority
+    /// than a BeforeHandle transition, in which case it is not added
+    pub fn add_global_transition(&mut self, transition: GlobalTransition<D>) -> bool {
+        let hook = self
+            .global_hook_mut()
+            .get_or_insert_with(|| Box::new(GlobalTransitions::<D>(Vec::new())));
+        let hook: &mut dyn Any = &mut **hook;
+        let transitions = &mut hook
+            .downcast_mut::<GlobalTransitions<D>>()
+            .expect("Only sm_global sets the global hook of a state machine")
+            .0;
+        let outranked = |before: &GlobalTransition<D>, after: &GlobalTransition<D>| {
+            before.timing == GlobalTiming::BeforeHandle
+                && after.timing == GlobalTiming::AfterHandle
+                && after.priority > before.priority
+        };
+        if transitions
+            .iter()
+            .any(|other| outranked(other, &transition) || outranked(&transition, other))
+        {
+            return false;
+        }
+        transitions.push(transition);
+        transitions.sort_by_key(|transition| Reverse(transition.priority));
+        true
+    }
+}
+
+impl<D> fmt::Debug for GlobalTransition<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlobalTransition")
+            .field("name", &self.name)
+            .field("priority", &self.priority)
+            .field("timing", &self.timing)
+            .field("target", &self.target)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GlobalTiming, GlobalTransition};
+    use crate::sm::{BoxedOutcome, ContinueOutcome, IntoOutcome, State, StateMachine, StepOutcome};
+
+    #[derive(Debug, Default, PartialEq, Eq)]
+    struct Vehicle {
+        battery: u32,
+        kill_switch: bool,
+        handled: Vec<&'static str>,
+    }
+
+    #[derive(Default)]
+    struct Patrol;
+
+    impl State for Patrol {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = Vehicle;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.battery -= 10;
+            data.handled.push("Patrol");
+            ContinueOutcome::<Self>::default().into_outcome()
+        }
+
+        fn name(&self) -> String {
+            "Patrol".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Surface;
+
+    impl State for Surface {
+        type Income = u32;
+        type Transition = BoxedOutcome;
+        type Data = Vehicle;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.handled.push("Surface");
+            if data.kill_switch {
+                ().into_outcome()
+            } else {
+                ContinueOutcome::<Self>::default().into_outcome()
+            }
+        }
+
+        fn name(&self) -> String {
+            "Surface".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Kill;
+
+    impl State for Kill {
+        type Income = ();
+        type Transition = ();
+        type Data = Vehicle;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.handled.push("Kill");
+        }
+
+        fn name(&self) -> String {
+            "Kill".to_string()
+        }
+    }
+
+    fn battery_low() -> GlobalTransition<Vehicle> {
+        GlobalTransition::to::<Surface>(
+            "battery low",
+            |data: &Vehicle| data.battery < 20,
+            |data: &Vehicle| data.battery,
+        )
+        .exempt::<Kill>()
+    }
+
+    fn kill_switch() -> GlobalTransition<Vehicle> {
+        GlobalTransition::to::<Kill>("kill switch", |data: &Vehicle| data.kill_switch, |_| ())
+            .with_priority(10)
+            .exempt::<Surface>()
+    }
+
+    fn machine(timing: GlobalTiming) -> StateMachine<Vehicle> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Patrol>();
+        machine.add_state::<Surface>();
+        machine.add_state::<Kill>();
+        assert!(machine.add_global_transition(battery_low().with_timing(timing)));
+        assert!(machine.add_global_transition(kill_switch().with_timing(timing)));
+        machine
+    }
+
+    fn vehicle(battery: u32, kill_switch: bool) -> Vehicle {
+        Vehicle {
+            battery,
+            kill_switch,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn before_handle() {
+        let machine = machine(GlobalTiming::BeforeHandle);
+        let mut runner = machine.runner::<Patrol>(vehicle(30, false), ()).unwrap();
+        for _ in 0..2 {
+            runner = match runner.step() {
+                StepOutcome::Continue { machine } => machine,
+                e => panic!("Unexpeced runner outcome {e:?}"),
+            };
+        }
+        match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(start, "Patrol");
+                assert_eq!(transition, "battery low");
+                assert_eq!(end, "Surface");
+                assert_eq!(machine.data.handled, vec!["Patrol", "Patrol"]);
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn priority_and_exemption() {
+        let machine = machine(GlobalTiming::BeforeHandle);
+        let runner = machine.runner::<Patrol>(vehicle(10, true), ()).unwrap();
+        let runner = match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                transition,
+                end,
+                ..
+            } => {
+                assert_eq!(transition, "kill switch");
+                assert_eq!(end, "Kill");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        let data = runner.run_to_completion().unwrap();
+        assert_eq!(data.handled, vec!["Kill"]);
+
+        // Surface is exempt from the kill switch, so it completes through its own handle
+        let runner = machine.runner::<Surface>(vehicle(10, true), 10).unwrap();
+        let data = runner.run_to_completion().unwrap();
+        assert_eq!(data.handled, vec!["Surface"]);
+    }
+
+    #[test]
+    fn after_handle() {
+        let machine = machine(GlobalTiming::AfterHandle);
+        let runner = machine.runner::<Patrol>(vehicle(20, false), ()).unwrap();
+        match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                transition,
+                end,
+                ..
+            } => {
+                assert_eq!(transition, "battery low");
+                assert_eq!(end, "Surface");
+                assert_eq!(machine.data.handled, vec!["Patrol"]);
+                assert_eq!(machine.data.battery, 10);
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+
+        let runner = machine.runner::<Patrol>(vehicle(20, true), ()).unwrap();
+        match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                transition,
+                end,
+                ..
+            } => {
+                assert_eq!(transition, "kill switch");
+                assert_eq!(end, "Kill");
+                assert_eq!(machine.data.handled, vec!["Patrol"]);
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn after_handle_may_not_outrank_before_handle() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Patrol>();
+        assert!(machine.add_global_transition(battery_low()));
+        assert!(
+            !machine.add_global_transition(kill_switch().with_timing(GlobalTiming::AfterHandle))
+        );
+        assert!(machine.add_global_transition(
+            kill_switch()
+                .with_priority(0)
+                .with_timing(GlobalTiming::AfterHandle)
+        ));
+        assert!(!machine.add_global_transition(battery_low().with_priority(-1)));
+        assert!(machine.add_global_transition(kill_switch()));
+    }
+}
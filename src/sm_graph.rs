@@ -0,0 +1,376 @@
+use std::{any::TypeId, collections::HashMap, fmt::Write};
+
+use crate::{
+    sm::{DeclaredTransition, StateMachine, Threading},
+    sm_mission,
+};
+
+/// A node of the graph of a state machine
+struct Node {
+    id: String,
+    label: String,
+    /// False for targets of declared transitions which are not in the state machine
+    registered: bool,
+}
+
+/// The end of an edge of the graph of a state machine
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// An index into the nodes of the graph
+    Node(usize),
+    /// The transition to `()`
+    End,
+    /// A Wired outcome which is not wired to any state
+    Unwired,
+}
+
+/// The states and declared transitions of a state machine, in a stable order
+struct Graph {
+    nodes: Vec<Node>,
+    /// Index into nodes of the start of each edge
+    edges: Vec<(usize, Target, String)>,
+}
+
+impl Graph {
+    fn new<D, K: Threading>(machine: &StateMachine<D, K>) -> Self {
+        let mut states: Vec<(String, TypeId, Option<Vec<DeclaredTransition>>)> = machine
+            .registrations()
+            .map(|(state_id, registration)| {
+                (
+                    registration.name.clone(),
+                    *state_id,
+                    (registration.declared_transitions)(),
+                )
+            })
+            .collect();
+        states.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut indices: HashMap<TypeId, usize> = HashMap::new();
+        for (name, state_id, _) in &states {
+            indices.insert(*state_id, nodes.len());
+            nodes.push(Node {
+                id: format!("s{}", nodes.len()),
+                label: name.clone(),
+                registered: true,
+            });
+        }
+
+        let mut edges = Vec::new();
+        for (_, state_id, declared) in states {
+            let from = indices[&state_id];
+            for transition in declared.into_iter().flatten() {
+                let to = match sm_mission::declared_target(machine, state_id, &transition) {
+                    None => Target::Unwired,
+                    Some(target) if target == TypeId::of::<()>() => Target::End,
+                    Some(target) => {
+                        let index = *indices.entry(target).or_insert_with(|| {
+                            nodes.push(Node {
+                                id: format!("s{}", nodes.len()),
+                                label: transition.target_name.to_string(),
+                                registered: false,
+                            });
+                            nodes.len() - 1
+                        });
+                        Target::Node(index)
+                    }
+                };
+                edges.push((from, to, transition.name));
+            }
+        }
+        Self { nodes, edges }
+    }
+
+    fn has_edge_to(&self, target: Target) -> bool {
+        self.edges.iter().any(|(_, to, _)| *to == target)
+    }
+}
+
+/// Escapes a label for use inside of a quoted Graphviz string
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes a label for use inside of Mermaid text
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
+
+impl<D, K: Threading> StateMachine<D, K> {
+    /// Renders the states of the state machine and their declared transitions
+    /// as a Graphviz DOT digraph
+    ///
+    /// States are labelled with State::name and transitions with the declared outcome name.
+    /// Transitions to `()` lead to a final END node,
+    /// and targets which are not in the state machine are drawn dashed.
+    /// Wired outcomes which are not wired lead to a dashed UNWIRED node.
+    /// States which do not declare their transitions have no outgoing edges
+    pub fn to_dot(&self) -> String {
+        let graph = Graph::new(self);
+        let mut dot = String::from("digraph StateMachine {\n");
+        for node in &graph.nodes {
+            let style = if node.registered { "" } else { ", style=dashed" };
+            let _ = writeln!(
+                dot,
+                "    {} [label=\"{}\"{style}];",
+                node.id,
+                escape_dot(&node.label)
+            );
+        }
+        if graph.has_edge_to(Target::End) {
+            dot.push_str("    end [label=\"END\", shape=doublecircle];\n");
+        }
+        if graph.has_edge_to(Target::Unwired) {
+            dot.push_str("    unwired [label=\"UNWIRED\", shape=octagon, style=dashed];\n");
+        }
+        for (from, to, name) in &graph.edges {
+            let to = match to {
+                Target::Node(to) => &graph.nodes[*to].id,
+                Target::End => "end",
+                Target::Unwired => "unwired",
+            };
+            let _ = writeln!(
+                dot,
+                "    {} -> {to} [label=\"{}\"];",
+                graph.nodes[*from].id,
+                escape_dot(name)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the states of the state machine and their declared transitions
+    /// as a Mermaid stateDiagram
+    ///
+    /// Follows the same conventions as to_dot, with transitions to `()` leading to `[*]`
+    pub fn to_mermaid(&self) -> String {
+        let graph = Graph::new(self);
+        let mut mermaid = String::from("stateDiagram-v2\n");
+        for node in &graph.nodes {
+            let _ = writeln!(
+                mermaid,
+                "    state \"{}\" as {}",
+                escape_mermaid(&node.label),
+                node.id
+            );
+        }
+        let unwired = graph.has_edge_to(Target::Unwired);
+        if unwired {
+            mermaid.push_str("    state \"UNWIRED\" as unwired\n");
+        }
+        for (from, to, name) in &graph.edges {
+            let to = match to {
+                Target::Node(to) => &graph.nodes[*to].id,
+                Target::End => "[*]",
+                Target::Unwired => "unwired",
+            };
+            let _ = writeln!(
+                mermaid,
+                "    {} --> {to} : {}",
+                graph.nodes[*from].id,
+                escape_mermaid(name)
+            );
+        }
+        let missing: Vec<&str> = graph
+            .nodes
+            .iter()
+            .filter(|node| !node.registered)
+            .map(|node| node.id.as_str())
+            .chain(unwired.then_some("unwired"))
+            .collect();
+        if !missing.is_empty() {
+            mermaid.push_str("    classDef missing stroke-dasharray: 5 5\n");
+            let _ = writeln!(mermaid, "    class {} missing", missing.join(","));
+        }
+        mermaid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::{type_name, Any, TypeId};
+
+    use crate::{
+        sm::{DeclaredTransition, Outcome, OutcomeData, State, StateMachine},
+        sm_mission::Wired,
+    };
+
+    enum SearchTransition {
+        Found,
+        Lost,
+    }
+
+    impl Outcome for SearchTransition {
+        fn state_type(&self) -> TypeId {
+            match self {
+                SearchTransition::Found => TypeId::of::<Align>(),
+                SearchTransition::Lost => TypeId::of::<Resurface>(),
+            }
+        }
+
+        fn data(self: Box<Self>) -> Box<dyn Any> {
+            Box::new(())
+        }
+
+        fn name(&self) -> String {
+            match self {
+                SearchTransition::Found => "found".to_string(),
+                SearchTransition::Lost => "lost".to_string(),
+            }
+        }
+
+        fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+            Some(vec![
+                DeclaredTransition::to::<Align>("found"),
+                DeclaredTransition::to::<Resurface>("lost"),
+            ])
+        }
+    }
+
+    #[derive(Default)]
+    struct Search {
+        found: bool,
+    }
+
+    impl State for Search {
+        type Income = ();
+        type Transition = SearchTransition;
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            if self.found {
+                SearchTransition::Found
+            } else {
+                SearchTransition::Lost
+            }
+        }
+
+        fn name(&self) -> String {
+            "Search".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Align;
+
+    impl State for Align {
+        type Income = ();
+        type Transition = ();
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+
+        fn name(&self) -> String {
+            "Align \"gate\"".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Resurface;
+
+    impl State for Resurface {
+        type Income = ();
+        type Transition = OutcomeData<Search>;
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::with_name((), "retry".to_string())
+        }
+    }
+
+    #[derive(Default)]
+    struct Hover;
+
+    impl State for Hover {
+        type Income = ();
+        type Transition = Wired;
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            Wired::new("timeout", ())
+        }
+
+        fn name(&self) -> String {
+            "Hover".to_string()
+        }
+
+        fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+            Some(vec![DeclaredTransition::wired::<()>("timeout")])
+        }
+    }
+
+    fn machine() -> StateMachine<()> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Search>();
+        machine.add_state::<Align>();
+        machine
+    }
+
+    #[test]
+    fn dot() {
+        let resurface = type_name::<Resurface>();
+        assert_eq!(
+            machine().to_dot(),
+            format!(
+                "digraph StateMachine {{
+    s0 [label=\"Align \\\"gate\\\"\"];
+    s1 [label=\"Search\"];
+    s2 [label=\"{resurface}\", style=dashed];
+    end [label=\"END\", shape=doublecircle];
+    s0 -> end [label=\"(Complete)\"];
+    s1 -> s0 [label=\"found\"];
+    s1 -> s2 [label=\"lost\"];
+}}
+"
+            )
+        );
+    }
+
+    #[test]
+    fn mermaid() {
+        let mut machine = machine();
+        machine.add_state::<Resurface>();
+        let resurface = type_name::<Resurface>();
+        let search = type_name::<Search>();
+        assert_eq!(
+            machine.to_mermaid(),
+            format!(
+                "stateDiagram-v2
+    state \"Align #quot;gate#quot;\" as s0
+    state \"Search\" as s1
+    state \"{resurface}\" as s2
+    s0 --> [*] : (Complete)
+    s1 --> s0 : found
+    s1 --> s2 : lost
+    s2 --> s1 : {search}
+"
+            )
+        );
+    }
+
+    #[test]
+    fn unwired() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Hover>();
+        assert_eq!(
+            machine.to_dot(),
+            "digraph StateMachine {
+    s0 [label=\"Hover\"];
+    unwired [label=\"UNWIRED\", shape=octagon, style=dashed];
+    s0 -> unwired [label=\"timeout\"];
+}
+"
+        );
+        assert_eq!(
+            machine.to_mermaid(),
+            "stateDiagram-v2
+    state \"Hover\" as s0
+    state \"UNWIRED\" as unwired
+    s0 --> unwired : timeout
+    classDef missing stroke-dasharray: 5 5
+    class unwired missing
+"
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use std::any::{Any, TypeId};
+
+use crate::sm::{
+    BoxedOutcome, InterceptHook, IntoOutcome, Outcome, OutcomeData, State, StateMachine, Threading,
+};
+
+/// Decides what happens to a transition, added with StateMachine::add_interceptor
+type Interceptor<Data> = Box<dyn Fn(&TransitionRequest, &Data) -> Interception + Send + Sync>;
+
+/// A transition which a state is about to take, as seen by an interceptor
+///
+/// Only transitions which leave the current state are intercepted,
+/// including transitions to `()`, global transitions, redirects made by other interceptors,
+/// and the transitions to the error state and the bail out state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionRequest {
+    start: TypeId,
+    target: TypeId,
+    transition: String,
+}
+
+impl TransitionRequest {
+    /// The TypeId of the state which is being left
+    pub fn start(&self) -> TypeId {
+        self.start
+    }
+
+    /// The TypeId of the state which is being transitioned to
+    pub fn target(&self) -> TypeId {
+        self.target
+    }
+
+    /// The name of the outcome of the transition
+    pub fn transition(&self) -> &str {
+        &self.transition
+    }
+
+    pub fn is_from<T: State>(&self) -> bool {
+        self.start == TypeId::of::<T>()
+    }
+
+    pub fn is_to<T: State>(&self) -> bool {
+        self.target == TypeId::of::<T>()
+    }
+
+    /// Returns true if the transition completes the state machine
+    pub fn is_complete(&self) -> bool {
+        self.target == TypeId::of::<()>()
+    }
+}
+
+/// The decision of an interceptor about a transition
+pub enum Interception {
+    /// Lets the transition, or the next interceptor, proceed
+    Allow,
+    /// Cancels the transition, keeping the state machine in the current state
+    Veto,
+    /// Replaces the transition with a transition to another state
+    Redirect(BoxedOutcome),
+}
+
+impl Interception {
+    /// Redirects the transition to T, entering T with the provided income
+    pub fn redirect<T: State>(income: T::Income) -> Self {
+        Interception::Redirect(OutcomeData::<T>::new(income).into_outcome())
+    }
+}
+
+/// Asks every interceptor about `request` in the order they were added
+/// Returns the first decision which is not Interception::Allow
+fn intercept<D>(
+    interceptors: &[Interceptor<D>],
+    request: &TransitionRequest,
+    data: &D,
+) -> Interception {
+    interceptors
+        .iter()
+        .map(|interceptor| interceptor(request, data))
+        .find(|decision| !matches!(decision, Interception::Allow))
+        .unwrap_or(Interception::Allow)
+}
+
+/// The number of times a transition may be redirected in a row before it is vetoed
+pub const MAX_REDIRECTS: usize = 8;
+
+/// Asks the interceptors about `outcome` leaving the state of type `start`,
+/// then asks them again about every redirect
+/// Returns the outcome to take, or the request which was vetoed
+///
+/// A redirect back to `start` is returned without asking the interceptors again,
+/// since it does not leave the state. Redirecting more than MAX_REDIRECTS times in a row
+/// vetoes the last redirect
+fn intercept_outcome<D>(
+    interceptors: &[Interceptor<D>],
+    start: TypeId,
+    mut outcome: BoxedOutcome,
+    data: &D,
+) -> Result<BoxedOutcome, TransitionRequest> {
+    if interceptors.is_empty() {
+        return Ok(outcome);
+    }
+    let name = format!("{} (redirected)", outcome.name());
+    let mut redirects = 0;
+    loop {
+        let request = request(start, &outcome);
+        match intercept(interceptors, &request, data) {
+            Interception::Allow => return Ok(outcome),
+            Interception::Veto => return Err(request),
+            Interception::Redirect(_) if redirects == MAX_REDIRECTS => return Err(request),
+            Interception::Redirect(redirect) => {
+                let redirect_start = redirect.state_type() == start;
+                outcome = Box::new(Redirected {
+                    outcome: redirect,
+                    name: name.clone(),
+                });
+                if redirect_start {
+                    return Ok(outcome);
+                }
+                redirects += 1;
+            }
+        }
+    }
+}
+
+/// The interceptors of a state machine, in the order they were added
+struct Interceptors<D>(Vec<Interceptor<D>>);
+
+impl<D: 'static> InterceptHook<D> for Interceptors<D> {
+    fn intercept(
+        &self,
+        start: TypeId,
+        outcome: BoxedOutcome,
+        data: &D,
+    ) -> Result<BoxedOutcome, String> {
+        intercept_outcome(&self.0, start, outcome, data).map_err(|request| request.transition)
+    }
+}
+
+impl<D, K: Threading> StateMachine<D, K> {
+    /// Adds an interceptor which decides whether each transition leaving a state
+    /// is allowed, vetoed or redirected to another state
+    ///
+    /// Interceptors are asked in the order they were added, and the first decision other than
+    /// Interception::Allow is used.
+    /// Redirects are intercepted again, up to MAX_REDIRECTS times in a row.
+    /// Entering the error state or the bail out state of a runner is intercepted as well
+    pub fn add_interceptor(
+        &mut self,
+        interceptor: impl Fn(&TransitionRequest, &D) -> Interception + Send + Sync + 'static,
+    ) {
+        let hook = self
+            .intercept_hook_mut()
+            .get_or_insert_with(|| Box::new(Interceptors::<D>(Vec::new())));
+        let hook: &mut dyn Any = &mut **hook;
+        hook.downcast_mut::<Interceptors<D>>()
+            .expect("Only sm_intercept sets the intercept hook of a state machine")
+            .0
+            .push(Box::new(interceptor));
+    }
+}
+
+/// Builds the request describing `outcome` leaving the state of type `start`
+fn request(start: TypeId, outcome: &BoxedOutcome) -> TransitionRequest {
+    TransitionRequest {
+        start,
+        target: outcome.state_type(),
+        transition: outcome.name(),
+    }
+}
+
+/// The outcome of a transition which was redirected by an interceptor
+struct Redirected {
+    outcome: BoxedOutcome,
+    name: String,
+}
+
+impl Outcome for Redirected {
+    fn state_type(&self) -> TypeId {
+        self.outcome.state_type()
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        self.outcome.data()
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interception, TransitionRequest};
+    use crate::sm::{
+        BoxedOutcome, IntoOutcome, Limit, OutcomeData, RunErrorKind, State, StateMachine,
+        StepOutcome,
+    };
+
+    #[derive(Debug, Default, PartialEq, Eq)]
+    struct Sub {
+        armed: bool,
+        log: Vec<&'static str>,
+    }
+
+    #[derive(Default)]
+    struct Aim;
+
+    impl State for Aim {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = Sub;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.log.push("Aim");
+            OutcomeData::<Torpedo>::with_name(3, "fire".to_string()).into_outcome()
+        }
+
+        fn name(&self) -> String {
+            "Aim".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Torpedo;
+
+    impl State for Torpedo {
+        type Income = u8;
+        type Transition = ();
+        type Data = Sub;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.log.push("Torpedo");
+        }
+
+        fn name(&self) -> String {
+            "Torpedo".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Arm;
+
+    impl State for Arm {
+        type Income = ();
+        type Transition = OutcomeData<Aim>;
+        type Data = Sub;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.log.push("Arm");
+            data.armed = true;
+            OutcomeData::new(())
+        }
+
+        fn name(&self) -> String {
+            "Arm".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Reload;
+
+    impl State for Reload {
+        type Income = ();
+        type Transition = OutcomeData<Aim>;
+        type Data = Sub;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.log.push("Reload");
+            OutcomeData::with_name((), "reloaded".to_string())
+        }
+
+        fn name(&self) -> String {
+            "Reload".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Surface;
+
+    impl State for Surface {
+        type Income = RunErrorKind;
+        type Transition = ();
+        type Data = Sub;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.log.push("Surface");
+        }
+
+        fn name(&self) -> String {
+            "Surface".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Dive;
+
+    impl State for Dive {
+        type Income = Limit;
+        type Transition = ();
+        type Data = Sub;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.log.push("Dive");
+        }
+
+        fn name(&self) -> String {
+            "Dive".to_string()
+        }
+    }
+
+    fn machine(redirect: bool) -> StateMachine<Sub> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Aim>();
+        machine.add_state::<Torpedo>();
+        machine.add_state::<Arm>();
+        machine.add_interceptor(|request: &TransitionRequest, _data: &Sub| {
+            assert!(!request.is_complete() || request.is_from::<Torpedo>());
+            Interception::Allow
+        });
+        machine.add_interceptor(move |request: &TransitionRequest, data: &Sub| {
+            if !request.is_to::<Torpedo>() || data.armed {
+                Interception::Allow
+            } else if redirect {
+                Interception::redirect::<Arm>(())
+            } else {
+                Interception::Veto
+            }
+        });
+        machine
+    }
+
+    #[test]
+    fn veto() {
+        let machine = machine(false);
+        let runner = machine.runner::<Aim>(Sub::default(), ()).unwrap();
+        let mut runner = match runner.step() {
+            StepOutcome::Vetoed {
+                machine,
+                start,
+                transition,
+            } => {
+                assert_eq!(start, "Aim");
+                assert_eq!(transition, "fire");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        runner.data.armed = true;
+        let data = runner.run_to_completion().unwrap();
+        assert_eq!(data.log, vec!["Aim", "Aim", "Torpedo"]);
+    }
+
+    #[test]
+    fn redirect() {
+        let machine = machine(true);
+        let runner = machine.runner::<Aim>(Sub::default(), ()).unwrap();
+        let runner = match runner.step() {
+            StepOutcome::Transition {
+                machine,
+                start,
+                transition,
+                end,
+            } => {
+                assert_eq!(start, "Aim");
+                assert_eq!(transition, "fire (redirected)");
+                assert_eq!(end, "Arm");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        let data = runner.run_to_completion().unwrap();
+        assert_eq!(data.log, vec!["Aim", "Arm", "Aim", "Torpedo"]);
+    }
+
+    #[test]
+    fn redirect_is_intercepted_again() {
+        let mut machine = machine(false);
+        machine.add_state::<Reload>();
+        machine.add_interceptor(|request: &TransitionRequest, _data: &Sub| {
+            if request.is_to::<Aim>() {
+                Interception::redirect::<Torpedo>(4)
+            } else {
+                Interception::Allow
+            }
+        });
+        let runner = machine.runner::<Reload>(Sub::default(), ()).unwrap();
+        let runner = match runner.step() {
+            StepOutcome::Vetoed {
+                machine,
+                start,
+                transition,
+            } => {
+                assert_eq!(start, "Reload");
+                assert_eq!(transition, "reloaded (redirected)");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        assert_eq!(runner.data.log, vec!["Reload"]);
+    }
+
+    #[test]
+    fn redirect_limit() {
+        let mut machine = machine(true);
+        machine.add_interceptor(|request: &TransitionRequest, _data: &Sub| {
+            if request.is_to::<Arm>() {
+                Interception::redirect::<Torpedo>(2)
+            } else {
+                Interception::Allow
+            }
+        });
+        let runner = machine.runner::<Aim>(Sub::default(), ()).unwrap();
+        match runner.step() {
+            StepOutcome::Vetoed {
+                start, transition, ..
+            } => {
+                assert_eq!(start, "Aim");
+                assert_eq!(transition, "fire (redirected)");
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn error_state_is_intercepted() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Aim>();
+        machine.add_state::<Surface>();
+        assert!(machine.set_error_state::<Surface>());
+        machine.add_interceptor(|request: &TransitionRequest, _data: &Sub| {
+            if request.is_to::<Surface>() {
+                assert_eq!(request.transition(), "(Error)");
+                Interception::Veto
+            } else {
+                Interception::Allow
+            }
+        });
+        let runner = machine.runner::<Aim>(Sub::default(), ()).unwrap();
+        match runner.step() {
+            StepOutcome::StateNotFound {
+                start, transition, ..
+            } => {
+                assert_eq!(start, "Aim");
+                assert_eq!(transition, "fire");
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        }
+    }
+
+    #[test]
+    fn bail_out_is_intercepted() {
+        let mut machine = machine(true);
+        machine.add_state::<Dive>();
+        machine.add_interceptor(|request: &TransitionRequest, _data: &Sub| {
+            if request.is_to::<Dive>() {
+                assert_eq!(request.transition(), "(Step budget of 0)");
+                Interception::redirect::<Torpedo>(5)
+            } else {
+                Interception::Allow
+            }
+        });
+        let mut runner = machine.runner::<Aim>(Sub::default(), ()).unwrap();
+        runner.set_step_budget(0);
+        assert!(runner.set_bail_out_state::<Dive>());
+        let runner = match runner.step() {
+            StepOutcome::BailOut { machine, end, .. } => {
+                assert_eq!(end, "Arm");
+                machine
+            }
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        let data = runner.run_to_completion().unwrap();
+        assert_eq!(data.log, vec!["Arm", "Aim", "Torpedo"]);
+    }
+}
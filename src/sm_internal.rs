@@ -0,0 +1,99 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    time::Instant,
+};
+
+use crate::sm::{BoxedFuture, BoxedOutcome, Limit, RunErrorKind, StepReport};
+
+/// Function used to convert the data of an outcome into the Income of the state it transitions to
+type IncomeAdapter = Box<dyn Fn(Box<dyn Any>) -> Box<dyn Any> + Send + Sync>;
+
+/// The conversions added with StateMachine::add_income_adapter
+///
+/// Keyed by the TypeId of the received data and then the TypeId of the Income
+#[derive(Default)]
+pub struct IncomeAdapters(pub(crate) HashMap<(TypeId, TypeId), IncomeAdapter>);
+
+impl IncomeAdapters {
+    /// Converts `meta` into I if it is not already I and an adapter exists,
+    /// otherwise returns `meta` unchanged
+    pub(crate) fn adapt<I: 'static>(&self, meta: Box<dyn Any>) -> Box<dyn Any> {
+        let received = (*meta).type_id();
+        if received == TypeId::of::<I>() {
+            return meta;
+        }
+        match self.0.get(&(received, TypeId::of::<I>())) {
+            Some(adapter) => adapter(meta),
+            None => meta,
+        }
+    }
+
+    /// Returns true if data of type `received` is converted into the Income type `income`
+    pub(crate) fn contains(&self, received: TypeId, income: TypeId) -> bool {
+        self.0.contains_key(&(received, income))
+    }
+}
+
+/// Internal type used to represent possible missing state errors
+#[derive(Debug)]
+pub struct StateEntryError {
+    pub(crate) expected: TypeId,
+    pub(crate) received: Box<dyn Any>,
+}
+
+impl StateEntryError {
+    pub(crate) fn from_any<T: 'static>(any: Box<dyn Any>) -> Self {
+        Self {
+            expected: TypeId::of::<T>(),
+            received: any,
+        }
+    }
+}
+
+/// Internal representation of a state which is object safe without specifying the associated types
+pub trait StateInternal<Data>: Any {
+    /// Converts meta into the Income of the state with the adapters of the state machine if needed
+    fn enter(
+        &mut self,
+        meta: Box<dyn Any>,
+        adapters: &IncomeAdapters,
+    ) -> Result<(), StateEntryError>;
+    fn resume(
+        &mut self,
+        meta: Box<dyn Any>,
+        adapters: &IncomeAdapters,
+    ) -> Result<(), StateEntryError>;
+    fn handle(&mut self, data: &mut Data) -> BoxedOutcome;
+    fn handle_async<'s>(&'s mut self, data: &'s mut Data) -> BoxedFuture<'s, BoxedOutcome>;
+    fn exit(&mut self, data: &mut Data);
+    fn suspend(&mut self, data: &mut Data);
+    fn name(&self) -> String;
+}
+
+/// An observer of a runner, see sm_observer
+pub trait StepHook<Data> {
+    /// Called once a step described by `report` has finished,
+    /// with `state` the current state of the runner after the step
+    /// and `event` the type name of the event the step delivered through process_event, if any
+    fn step(
+        &mut self,
+        report: &StepReport,
+        state: &str,
+        event: Option<&str>,
+        at: Instant,
+        data: &Data,
+    );
+    /// Called when the runner left `start` after reaching `limit`,
+    /// with the name of the transition and the state it bailed out to if it did
+    fn limit(
+        &mut self,
+        start: &str,
+        limit: Limit,
+        bail_out: Option<(&str, &str)>,
+        at: Instant,
+        data: &Data,
+    );
+    /// Called when `error` ended the state machine outside of any step report
+    fn error(&mut self, error: &RunErrorKind, at: Instant, data: &Data);
+}
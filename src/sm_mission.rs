@@ -0,0 +1,803 @@
+use core::fmt;
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    fmt::Display,
+};
+
+use crate::sm::{
+    BoxedOutcome, DeclaredTransition, Outcome, State, StateMachine, StateMachineRunner, Threading,
+    WiringHook,
+};
+
+/// An outcome which does not know the state it leads to
+///
+/// The target is looked up by the name of the outcome in the wiring of the mission file
+/// which included the state. Transitions which are not wired end in StateNotFound.
+/// Wired outcomes are declared with DeclaredTransition::wired
+pub struct Wired {
+    name: String,
+    data: Box<dyn Any>,
+}
+
+impl Wired {
+    /// Creates a Wired outcome which enters its target with `data`
+    pub fn new<I: 'static>(name: impl Into<String>, data: I) -> Self {
+        Self {
+            name: name.into(),
+            data: Box::new(data),
+        }
+    }
+}
+
+impl Outcome for Wired {
+    fn state_type(&self) -> TypeId {
+        TypeId::of::<Wired>()
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        self.data
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// A Wired outcome along with the state it was wired to
+struct WiredTo {
+    outcome: BoxedOutcome,
+    target: TypeId,
+}
+
+impl Outcome for WiredTo {
+    fn state_type(&self) -> TypeId {
+        self.target
+    }
+
+    fn data(self: Box<Self>) -> Box<dyn Any> {
+        self.outcome.data()
+    }
+
+    fn name(&self) -> String {
+        self.outcome.name()
+    }
+}
+
+/// The targets of Wired outcomes, keyed by the TypeId of the state and then the outcome name
+#[derive(Default)]
+struct Wiring(HashMap<(TypeId, String), TypeId>);
+
+impl WiringHook for Wiring {
+    fn follow(&self, state: TypeId, outcome: BoxedOutcome) -> BoxedOutcome {
+        if outcome.state_type() != TypeId::of::<Wired>() {
+            return outcome;
+        }
+        match self.0.get(&(state, outcome.name())) {
+            Some(&target) => Box::new(WiredTo { outcome, target }),
+            None => outcome,
+        }
+    }
+}
+
+impl DeclaredTransition {
+    /// A Wired outcome which provides data of type I to whichever state it is wired to
+    pub fn wired<I: 'static>(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            target: TypeId::of::<Wired>(),
+            target_name: type_name::<Wired>(),
+            income: TypeId::of::<I>(),
+            income_name: type_name::<I>(),
+        }
+    }
+}
+
+/// Sends Wired outcomes named `outcome` of the state `state` to the state `target`,
+/// where a `target` of `()` completes the state machine
+fn wire<D, K: Threading>(
+    machine: &mut StateMachine<D, K>,
+    state: TypeId,
+    outcome: String,
+    target: TypeId,
+) {
+    let hook = machine
+        .wiring_hook_mut()
+        .get_or_insert_with(|| Box::new(Wiring::default()));
+    let hook: &mut dyn Any = &mut **hook;
+    hook.downcast_mut::<Wiring>()
+        .expect("Only sm_mission sets the wiring hook of a state machine")
+        .0
+        .insert((state, outcome), target);
+}
+
+/// Returns the target of the declared transition, following the wiring of Wired outcomes
+/// Returns None if the transition is a Wired outcome which is not wired
+pub(crate) fn declared_target<D, K: Threading>(
+    machine: &StateMachine<D, K>,
+    state: TypeId,
+    transition: &DeclaredTransition,
+) -> Option<TypeId> {
+    if transition.target != TypeId::of::<Wired>() {
+        return Some(transition.target);
+    }
+    let hook: &dyn Any = machine.wiring_hook()?;
+    let wiring = hook
+        .downcast_ref::<Wiring>()
+        .expect("Only sm_mission sets the wiring hook of a state machine");
+    wiring.0.get(&(state, transition.name.clone())).copied()
+}
+
+/// The target which completes the state machine in a mission file
+const END: &str = "END";
+
+/// An error in a mission file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissionError {
+    /// The line of the error, counting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for MissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for MissionError {}
+
+/// A state which mission files may include
+struct RegisteredState<Data: 'static> {
+    state: TypeId,
+    add: fn(&mut StateMachine<Data>),
+}
+
+/// The states which mission files may refer to, each under a stable name
+///
+/// A mission file lists the states of the mission, the state it starts in,
+/// and the state each Wired outcome leads to, so that missions can be reordered
+/// without recompiling. Mission files are a subset of TOML:
+///
+/// ```toml
+/// # Names are quoted if they are not made of letters, digits, `_` and `-`
+/// start = "dive"
+///
+/// [dive]
+/// gate = "pass-gate"
+/// lost = "surface"
+///
+/// [pass-gate]
+/// done = "END"
+///
+/// [surface]
+/// ```
+///
+/// Each `[section]` includes the state registered under its name,
+/// which may only be included once even if it is registered under several names,
+/// and each of its keys wires the Wired outcome of that name to another included state,
+/// or to `END` to complete the state machine.
+/// States which declare their transitions must have every Wired outcome wired,
+/// and must provide the Income of the states they are wired to
+///
+/// ```
+/// use umrsm::{sm::{DeclaredTransition, State}, sm_mission::{StateRegistry, Wired}};
+///
+/// #[derive(Default)]
+/// struct Dive;
+///
+/// impl State for Dive {
+///     type Income = ();
+///     type Transition = Wired;
+///     type Data = Vec<&'static str>;
+///
+///     fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+///         log.push("dive");
+///         Wired::new("depth reached", ())
+///     }
+///
+///     fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+///         Some(vec![DeclaredTransition::wired::<()>("depth reached")])
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Surface;
+///
+/// impl State for Surface {
+///     type Income = ();
+///     type Transition = Wired;
+///     type Data = Vec<&'static str>;
+///
+///     fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+///         log.push("surface");
+///         Wired::new("done", ())
+///     }
+/// }
+///
+/// let mut registry = StateRegistry::default();
+/// registry.register::<Dive>("dive");
+/// registry.register::<Surface>("surface");
+///
+/// let mission = registry
+///     .load(r#"
+///         start = "dive"
+///
+///         [dive]
+///         "depth reached" = "surface"
+///
+///         [surface]
+///         done = "END"
+///     "#)
+///     .expect("The mission is wired correctly");
+/// let runner = mission.runner(Vec::new(), ()).expect("dive starts with ()");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), ["dive", "surface"]);
+/// ```
+pub struct StateRegistry<Data: 'static> {
+    states: HashMap<String, RegisteredState<Data>>,
+}
+
+// Manually implemented because derive macro requires D: Default
+impl<D> Default for StateRegistry<D> {
+    fn default() -> Self {
+        Self {
+            states: HashMap::new(),
+        }
+    }
+}
+
+/// A state included by a mission file, along with its wiring
+struct Section<'r, D: 'static> {
+    line: usize,
+    name: String,
+    state: &'r RegisteredState<D>,
+    /// The line, outcome and target name of each wired outcome
+    wiring: Vec<(usize, String, String)>,
+}
+
+impl<D> StateRegistry<D> {
+    /// Registers T under `name`
+    /// Returns false if `name` is already registered or is `END`, in which case T is not registered
+    pub fn register<T: State<Data = D>>(&mut self, name: impl Into<String>) -> bool {
+        let name = name.into();
+        if name == END || self.states.contains_key(&name) {
+            return false;
+        }
+        self.states.insert(
+            name,
+            RegisteredState {
+                state: TypeId::of::<T>(),
+                add: StateMachine::<D>::add_state::<T>,
+            },
+        );
+        true
+    }
+
+    /// Builds the state machine described by `mission`
+    pub fn load(&self, mission: &str) -> Result<Mission<D>, MissionError> {
+        self.load_into(StateMachine::default(), mission)
+    }
+
+    /// The same as load, but adds the states of the mission to `machine`
+    ///
+    /// This allows the income adapters of `machine` to be taken into account
+    /// while the wiring is checked
+    pub fn load_into(
+        &self,
+        mut machine: StateMachine<D>,
+        mission: &str,
+    ) -> Result<Mission<D>, MissionError> {
+        let mut start: Option<(usize, String)> = None;
+        let mut sections: Vec<Section<D>> = Vec::new();
+        for (index, text) in mission.lines().enumerate() {
+            let line = index + 1;
+            let error = |message| MissionError { line, message };
+            match parse_line(text).map_err(error)? {
+                None => {}
+                Some(Entry::Section(name)) => {
+                    if let Some(previous) = sections.iter().find(|section| section.name == name) {
+                        return Err(error(format!(
+                            "`{name}` is already included on line {}",
+                            previous.line
+                        )));
+                    }
+                    let Some(state) = self.states.get(&name) else {
+                        return Err(error(format!("No state is registered as `{name}`")));
+                    };
+                    if let Some(previous) = sections
+                        .iter()
+                        .find(|section| section.state.state == state.state)
+                    {
+                        return Err(error(format!(
+                            "`{name}` is the same state as `{}`, which is already included on line {}",
+                            previous.name, previous.line
+                        )));
+                    }
+                    sections.push(Section {
+                        line,
+                        name,
+                        state,
+                        wiring: Vec::new(),
+                    });
+                }
+                Some(Entry::Pair { key, value }) => match sections.last_mut() {
+                    None if key == "start" => {
+                        if let Some((previous, _)) = start {
+                            return Err(error(format!(
+                                "The start state is already set on line {previous}"
+                            )));
+                        }
+                        start = Some((line, value));
+                    }
+                    None => {
+                        return Err(error(format!(
+                            "Unknown key `{key}`, only `start` may be set outside of a state"
+                        )))
+                    }
+                    Some(section) => {
+                        if let Some((previous, ..)) = section
+                            .wiring
+                            .iter()
+                            .find(|(_, outcome, _)| *outcome == key)
+                        {
+                            return Err(error(format!(
+                                "Outcome `{key}` of `{}` is already wired on line {previous}",
+                                section.name
+                            )));
+                        }
+                        section.wiring.push((line, key, value));
+                    }
+                },
+            }
+        }
+
+        let Some((start_line, start_name)) = start else {
+            return Err(MissionError {
+                line: 1,
+                message: "The mission does not set a start state".to_string(),
+            });
+        };
+        for section in &sections {
+            (section.state.add)(&mut machine);
+        }
+        let start = self
+            .included(&sections, &start_name)
+            .map_err(|message| MissionError {
+                line: start_line,
+                message,
+            })?;
+        for section in &sections {
+            self.wire_section(&mut machine, section, &sections)?;
+        }
+        Ok(Mission {
+            machine,
+            start,
+            start_name,
+        })
+    }
+
+    /// Returns the TypeId of the state included in the mission as `name`
+    fn included(&self, sections: &[Section<D>], name: &str) -> Result<TypeId, String> {
+        match sections.iter().find(|section| section.name == name) {
+            Some(section) => Ok(section.state.state),
+            None if self.states.contains_key(name) => {
+                Err(format!("`{name}` is not included in the mission"))
+            }
+            None => Err(format!("No state is registered as `{name}`")),
+        }
+    }
+
+    /// Wires the outcomes of `section` after checking them against its declared transitions
+    fn wire_section(
+        &self,
+        machine: &mut StateMachine<D>,
+        section: &Section<D>,
+        sections: &[Section<D>],
+    ) -> Result<(), MissionError> {
+        let state = section.state.state;
+        let declared = (machine
+            .registration(state)
+            .expect("Every section was added to the state machine")
+            .declared_transitions)();
+        for (line, outcome, target_name) in &section.wiring {
+            let error = |message| MissionError {
+                line: *line,
+                message,
+            };
+            let target = if target_name == END {
+                TypeId::of::<()>()
+            } else {
+                self.included(sections, target_name).map_err(error)?
+            };
+            if let Some(declared) = &declared {
+                let Some(transition) = declared.iter().find(|transition| {
+                    transition.target == TypeId::of::<Wired>() && transition.name == *outcome
+                }) else {
+                    return Err(error(format!(
+                        "`{}` has no Wired outcome named `{outcome}`",
+                        section.name
+                    )));
+                };
+                if let Some(expected) = machine.registration(target).filter(|_| target != state) {
+                    if expected.income != transition.income
+                        && !machine
+                            .income_adapters()
+                            .contains(transition.income, expected.income)
+                    {
+                        return Err(error(format!(
+                            "`{target_name}` expected incoming data of type {} but outcome `{outcome}` of `{}` provides data of type {}",
+                            expected.income_name,
+                            section.name,
+                            transition.income_name
+                        )));
+                    }
+                }
+            }
+            wire(machine, state, outcome.clone(), target);
+        }
+
+        let error = |message| MissionError {
+            line: section.line,
+            message,
+        };
+        for transition in declared.into_iter().flatten() {
+            match declared_target(machine, state, &transition) {
+                None => {
+                    return Err(error(format!(
+                        "Outcome `{}` of `{}` is not wired",
+                        transition.name, section.name
+                    )))
+                }
+                Some(target)
+                    if target != TypeId::of::<()>()
+                        && target != state
+                        && machine.registration(target).is_none() =>
+                {
+                    return Err(error(format!(
+                        "`{}` transitions to {} which is not in the mission",
+                        section.name, transition.target_name
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A state machine built from a mission file by StateRegistry::load
+pub struct Mission<Data: 'static> {
+    machine: StateMachine<Data>,
+    start: TypeId,
+    start_name: String,
+}
+
+impl<D> Mission<D> {
+    pub fn machine(&self) -> &StateMachine<D> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut StateMachine<D> {
+        &mut self.machine
+    }
+
+    /// The registered name of the start state
+    pub fn start(&self) -> &str {
+        &self.start_name
+    }
+
+    /// Create a state machine runner starting in the start state of the mission
+    /// Returns None if `start_transition_data` is not the Income of the start state
+    pub fn runner<I: 'static>(
+        &self,
+        initial_data: D,
+        start_transition_data: I,
+    ) -> Option<StateMachineRunner<'_, D>> {
+        StateMachineRunner::with_start(
+            &self.machine,
+            initial_data,
+            self.start,
+            Box::new(start_transition_data),
+        )
+    }
+
+    pub fn into_machine(self) -> StateMachine<D> {
+        self.machine
+    }
+}
+
+/// A line of a mission file which is not blank or a comment
+#[derive(Debug, PartialEq, Eq)]
+enum Entry {
+    Section(String),
+    Pair { key: String, value: String },
+}
+
+/// Parses a single line of a mission file
+/// Returns None for blank lines and comments
+fn parse_line(line: &str) -> Result<Option<Entry>, String> {
+    let mut parser = LineParser { rest: line };
+    if parser.at_end() {
+        return Ok(None);
+    }
+    let entry = if parser.eat('[') {
+        let name = parser.key()?;
+        if !parser.eat(']') {
+            return Err(format!("Expected `]` after `{name}`"));
+        }
+        Entry::Section(name)
+    } else {
+        let key = parser.key()?;
+        if !parser.eat('=') {
+            return Err(format!("Expected `=` after `{key}`"));
+        }
+        let value = parser.string()?;
+        Entry::Pair { key, value }
+    };
+    if !parser.at_end() {
+        return Err(format!("Unexpected `{}`", parser.rest.trim_end()));
+    }
+    Ok(Some(entry))
+}
+
+struct LineParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> LineParser<'a> {
+    /// Returns true if only whitespace or a comment is left
+    fn at_end(&mut self) -> bool {
+        self.rest = self.rest.trim_start();
+        self.rest.is_empty() || self.rest.starts_with('#')
+    }
+
+    /// Consumes `expected` after any whitespace, returning false if it is not next
+    fn eat(&mut self, expected: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(expected) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Parses a bare or quoted key
+    fn key(&mut self) -> Result<String, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('"') {
+            return self.string();
+        }
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err("Expected a name".to_string());
+        }
+        let (key, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Ok(key.to_string())
+    }
+
+    /// Parses a quoted string
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat('"') {
+            return Err("Expected a quoted string".to_string());
+        }
+        let mut value = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[index + 1..];
+                    return Ok(value);
+                }
+                '\\' => match chars.next() {
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, c)) => return Err(format!("Unknown escape `\\{c}`")),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err("Unterminated string".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, MissionError, StateRegistry, Wired};
+    use crate::sm::{DeclaredTransition, IntoOutcome, OutcomeData, State, StepOutcome};
+
+    #[derive(Default)]
+    struct Search;
+
+    impl State for Search {
+        type Income = ();
+        type Transition = Wired;
+        type Data = Vec<&'static str>;
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("Search");
+            if log.len() < 3 {
+                Wired::new("lost", 2u8)
+            } else {
+                Wired::new("found", ())
+            }
+        }
+
+        fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+            Some(vec![
+                DeclaredTransition::wired::<()>("found"),
+                DeclaredTransition::wired::<u8>("lost"),
+            ])
+        }
+    }
+
+    #[derive(Default)]
+    struct Spin;
+
+    impl State for Spin {
+        type Income = u8;
+        type Transition = OutcomeData<Search>;
+        type Data = Vec<&'static str>;
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("Spin");
+            OutcomeData::new(())
+        }
+
+        fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+            <Self::Transition as IntoOutcome>::declared_transitions()
+        }
+    }
+
+    #[derive(Default)]
+    struct Touch;
+
+    impl State for Touch {
+        type Income = ();
+        type Transition = ();
+        type Data = Vec<&'static str>;
+
+        fn handle(&mut self, log: &mut Self::Data) -> Self::Transition {
+            log.push("Touch");
+        }
+    }
+
+    fn registry() -> StateRegistry<Vec<&'static str>> {
+        let mut registry = StateRegistry::default();
+        assert!(registry.register::<Search>("search"));
+        assert!(registry.register::<Spin>("spin"));
+        assert!(registry.register::<Touch>("touch buoy"));
+        assert!(!registry.register::<Touch>("touch buoy"));
+        assert!(!registry.register::<Touch>("END"));
+        assert!(registry.register::<Touch>("buoy"));
+        registry
+    }
+
+    const MISSION: &str = r#"
+# Buoy task
+start = "search"
+
+[search]   # Looks for the buoy
+found = "touch buoy"
+lost = "spin"
+
+[spin]
+["touch buoy"]
+"#;
+
+    #[test]
+    fn run_mission() {
+        let mission = registry().load(MISSION).unwrap();
+        assert_eq!(mission.start(), "search");
+        assert!(mission.machine().validate().is_valid());
+        assert!(mission.runner(Vec::new(), 0u8).is_none());
+
+        let mut runner = mission.runner(Vec::new(), ()).unwrap();
+        let mut transitions = Vec::new();
+        loop {
+            runner = match runner.step() {
+                StepOutcome::Continue { machine } => machine,
+                StepOutcome::Transition {
+                    machine,
+                    transition,
+                    end,
+                    ..
+                } => {
+                    transitions.push(format!("{transition} {end}"));
+                    machine
+                }
+                StepOutcome::Complete { data, .. } => {
+                    assert_eq!(data, ["Search", "Spin", "Search", "Touch"]);
+                    break;
+                }
+                e => panic!("Unexpected runner outcome {e:?}"),
+            }
+        }
+        assert_eq!(
+            transitions[0],
+            format!("lost {}", std::any::type_name::<Spin>())
+        );
+        assert_eq!(
+            transitions[2],
+            format!("found {}", std::any::type_name::<Touch>())
+        );
+    }
+
+    #[test]
+    fn parse_lines() {
+        assert_eq!(super::parse_line("  # comment"), Ok(None));
+        assert_eq!(
+            super::parse_line(r#"[ "a \"b\"" ] # c"#),
+            Ok(Some(Entry::Section("a \"b\"".to_string())))
+        );
+        assert_eq!(
+            super::parse_line("a-b_1 = \"#\""),
+            Ok(Some(Entry::Pair {
+                key: "a-b_1".to_string(),
+                value: "#".to_string()
+            }))
+        );
+    }
+
+    #[test]
+    fn wiring_errors() {
+        let registry = registry();
+        let error = |line: usize, message: &str| {
+            Err::<(), _>(MissionError {
+                line,
+                message: message.to_string(),
+            })
+        };
+        let cases = [
+            ("[search]\nfound = END", error(2, "Expected a quoted string")),
+            ("[search\n", error(1, "Expected `]` after `search`")),
+            ("start = \"search\" x", error(1, "Unexpected `x`")),
+            ("mission = \"buoy\"", error(1, "Unknown key `mission`, only `start` may be set outside of a state")),
+            ("[search]\n[touch]", error(2, "No state is registered as `touch`")),
+            ("[search]\n\n[search]", error(3, "`search` is already included on line 1")),
+            (
+                "[\"touch buoy\"]\n[buoy]",
+                error(2, "`buoy` is the same state as `touch buoy`, which is already included on line 1"),
+            ),
+            ("[spin]\nfound = \"END\"", error(1, "The mission does not set a start state")),
+            ("start = \"touch buoy\"\n[search]", error(1, "`touch buoy` is not included in the mission")),
+            (
+                "start = \"search\"\n[search]\nfound = \"END\"\nfound = \"END\"",
+                error(4, "Outcome `found` of `search` is already wired on line 3"),
+            ),
+            (
+                "start = \"search\"\n[search]\nfound = \"END\"\nlost = \"END\"\nspun = \"END\"",
+                error(5, "`search` has no Wired outcome named `spun`"),
+            ),
+            (
+                "start = \"search\"\n[search]\nfound = \"END\"\nlost = \"touch buoy\"\n[\"touch buoy\"]",
+                error(4, "`touch buoy` expected incoming data of type () but outcome `lost` of `search` provides data of type u8"),
+            ),
+            (
+                "start = \"search\"\n\n[search]\nfound = \"END\"",
+                error(3, "Outcome `lost` of `search` is not wired"),
+            ),
+            (
+                "start = \"spin\"\n[spin]",
+                Err(MissionError {
+                    line: 2,
+                    message: format!(
+                        "`spin` transitions to {} which is not in the mission",
+                        std::any::type_name::<Search>()
+                    ),
+                }),
+            ),
+        ];
+        for (mission, expected) in cases {
+            assert_eq!(registry.load(mission).map(|_| ()), expected, "{mission}");
+        }
+    }
+}
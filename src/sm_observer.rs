@@ -0,0 +1,431 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+use crate::sm::{Limit, RunErrorKind, Sendable, StateMachineRunner, StepHook, StepReport};
+
+/// Receives the notable events of a StateMachineRunner
+///
+/// Observers are attached with StateMachineRunner::add_observer,
+/// and every observer of a runner is notified in the order it was attached.
+/// Observers are notified once a step has finished, with `at` set to the instant it finished
+/// and `data` as it was left by the step.
+/// Entering the start state happens before any observer can be attached, so it is not observed
+pub trait RunnerObserver<Data> {
+    /// Called after every step in which a method of `state` handled the step,
+    /// before any of the other methods for that step
+    #[allow(unused)]
+    fn on_step(&mut self, state: &str, at: Instant, data: &Data) {}
+    /// Called instead of on_step when `state` handled an event whose type is named `event`,
+    /// delivered by StateMachineRunner::process_event
+    ///
+    /// Reports the event as a step by default
+    #[allow(unused)]
+    fn on_event(&mut self, state: &str, event: &str, at: Instant, data: &Data) {
+        self.on_step(state, at, data);
+    }
+    /// Called when the state machine leaves `state`, including when it leaves because of an error
+    #[allow(unused)]
+    fn on_exit(&mut self, state: &str, at: Instant, data: &Data) {}
+    #[allow(unused)]
+    fn on_transition(
+        &mut self,
+        start: &str,
+        transition: &str,
+        end: &str,
+        at: Instant,
+        data: &Data,
+    ) {
+    }
+    /// Called instead of on_transition when the runner bails out after reaching `limit`
+    ///
+    /// Reports the bail out as a transition by default
+    #[allow(unused)]
+    fn on_bail_out(
+        &mut self,
+        start: &str,
+        transition: &str,
+        end: &str,
+        limit: Limit,
+        at: Instant,
+        data: &Data,
+    ) {
+        self.on_transition(start, transition, end, at, data);
+    }
+    /// Called after on_error when the state machine recovered from `error` by entering `end`,
+    /// before on_enter
+    #[allow(unused)]
+    fn on_recover(&mut self, error: &RunErrorKind, end: &str, at: Instant, data: &Data) {}
+    /// Called after on_transition, on_bail_out or on_recover
+    #[allow(unused)]
+    fn on_enter(&mut self, state: &str, at: Instant, data: &Data) {}
+    #[allow(unused)]
+    fn on_complete(&mut self, start: &str, transition: &str, at: Instant, data: &Data) {}
+    /// Called for every error, including errors which were recovered from through an error state
+    #[allow(unused)]
+    fn on_error(&mut self, error: &RunErrorKind, at: Instant, data: &Data) {}
+}
+
+// Shared observers can be attached to a runner while still being read elsewhere
+impl<D, O: RunnerObserver<D>> RunnerObserver<D> for Rc<RefCell<O>> {
+    fn on_step(&mut self, state: &str, at: Instant, data: &D) {
+        self.borrow_mut().on_step(state, at, data);
+    }
+
+    fn on_event(&mut self, state: &str, event: &str, at: Instant, data: &D) {
+        self.borrow_mut().on_event(state, event, at, data);
+    }
+
+    fn on_exit(&mut self, state: &str, at: Instant, data: &D) {
+        self.borrow_mut().on_exit(state, at, data);
+    }
+
+    fn on_transition(&mut self, start: &str, transition: &str, end: &str, at: Instant, data: &D) {
+        self.borrow_mut()
+            .on_transition(start, transition, end, at, data);
+    }
+
+    fn on_bail_out(
+        &mut self,
+        start: &str,
+        transition: &str,
+        end: &str,
+        limit: Limit,
+        at: Instant,
+        data: &D,
+    ) {
+        self.borrow_mut()
+            .on_bail_out(start, transition, end, limit, at, data);
+    }
+
+    fn on_recover(&mut self, error: &RunErrorKind, end: &str, at: Instant, data: &D) {
+        self.borrow_mut().on_recover(error, end, at, data);
+    }
+
+    fn on_enter(&mut self, state: &str, at: Instant, data: &D) {
+        self.borrow_mut().on_enter(state, at, data);
+    }
+
+    fn on_complete(&mut self, start: &str, transition: &str, at: Instant, data: &D) {
+        self.borrow_mut().on_complete(start, transition, at, data);
+    }
+
+    fn on_error(&mut self, error: &RunErrorKind, at: Instant, data: &D) {
+        self.borrow_mut().on_error(error, at, data);
+    }
+}
+
+impl<D, O: RunnerObserver<D>> RunnerObserver<D> for Arc<Mutex<O>> {
+    fn on_step(&mut self, state: &str, at: Instant, data: &D) {
+        lock(self).on_step(state, at, data);
+    }
+
+    fn on_event(&mut self, state: &str, event: &str, at: Instant, data: &D) {
+        lock(self).on_event(state, event, at, data);
+    }
+
+    fn on_exit(&mut self, state: &str, at: Instant, data: &D) {
+        lock(self).on_exit(state, at, data);
+    }
+
+    fn on_transition(&mut self, start: &str, transition: &str, end: &str, at: Instant, data: &D) {
+        lock(self).on_transition(start, transition, end, at, data);
+    }
+
+    fn on_bail_out(
+        &mut self,
+        start: &str,
+        transition: &str,
+        end: &str,
+        limit: Limit,
+        at: Instant,
+        data: &D,
+    ) {
+        lock(self).on_bail_out(start, transition, end, limit, at, data);
+    }
+
+    fn on_recover(&mut self, error: &RunErrorKind, end: &str, at: Instant, data: &D) {
+        lock(self).on_recover(error, end, at, data);
+    }
+
+    fn on_enter(&mut self, state: &str, at: Instant, data: &D) {
+        lock(self).on_enter(state, at, data);
+    }
+
+    fn on_complete(&mut self, start: &str, transition: &str, at: Instant, data: &D) {
+        lock(self).on_complete(start, transition, at, data);
+    }
+
+    fn on_error(&mut self, error: &RunErrorKind, at: Instant, data: &D) {
+        lock(self).on_error(error, at, data);
+    }
+}
+
+/// Locks a shared observer, ignoring poisoning since observers only record what they are told
+fn lock<O>(observer: &Mutex<O>) -> std::sync::MutexGuard<'_, O> {
+    observer.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Notifies `observer` that `state` handled a step, or the event named `event` if there is one
+fn handled<D, O: RunnerObserver<D>>(
+    observer: &mut O,
+    state: &str,
+    event: Option<&str>,
+    at: Instant,
+    data: &D,
+) {
+    match event {
+        Some(event) => observer.on_event(state, event, at, data),
+        None => observer.on_step(state, at, data),
+    }
+}
+
+impl<D, O: RunnerObserver<D>> StepHook<D> for O {
+    fn step(
+        &mut self,
+        report: &StepReport,
+        state: &str,
+        event: Option<&str>,
+        at: Instant,
+        data: &D,
+    ) {
+        match report {
+            StepReport::Continue => handled(self, state, event, at, data),
+            StepReport::Vetoed { start, .. } => handled(self, start, event, at, data),
+            StepReport::Transition {
+                start,
+                transition,
+                end,
+            } => {
+                handled(self, start, event, at, data);
+                self.on_exit(start, at, data);
+                self.on_transition(start, transition, end, at, data);
+                self.on_enter(end, at, data);
+            }
+            StepReport::Complete { start, transition } => {
+                handled(self, start, event, at, data);
+                self.on_exit(start, at, data);
+                self.on_complete(start, transition, at, data);
+            }
+            StepReport::StateNotFound { start, .. }
+            | StepReport::IncorrectTransition { start, .. } => {
+                handled(self, start, event, at, data);
+                self.on_exit(start, at, data);
+                if let Some(error) = report.error_kind() {
+                    self.on_error(&error, at, data);
+                }
+            }
+            StepReport::Recovered { error, end } => {
+                if let RunErrorKind::StateNotFound { start, .. }
+                | RunErrorKind::IncorrectTransition { start, .. } = error
+                {
+                    handled(self, start, event, at, data);
+                    self.on_exit(start, at, data);
+                }
+                self.on_error(error, at, data);
+                self.on_recover(error, end, at, data);
+                self.on_enter(end, at, data);
+            }
+        }
+    }
+
+    fn limit(
+        &mut self,
+        start: &str,
+        limit: Limit,
+        bail_out: Option<(&str, &str)>,
+        at: Instant,
+        data: &D,
+    ) {
+        self.on_exit(start, at, data);
+        match bail_out {
+            Some((transition, end)) => {
+                self.on_bail_out(start, transition, end, limit, at, data);
+                self.on_enter(end, at, data);
+            }
+            None => {
+                let error = RunErrorKind::LimitReached {
+                    state: start.to_string(),
+                    limit,
+                };
+                self.on_error(&error, at, data);
+            }
+        }
+    }
+
+    fn error(&mut self, error: &RunErrorKind, at: Instant, data: &D) {
+        self.on_error(error, at, data);
+    }
+}
+
+impl<'a, D> StateMachineRunner<'a, D> {
+    /// Attaches an observer which is notified of every following step of the runner
+    ///
+    /// Any number of observers may be attached to the same runner
+    pub fn add_observer(&mut self, observer: impl RunnerObserver<D> + 'static) {
+        self.add_step_hook(Box::new(observer));
+    }
+}
+
+impl<'a, D> StateMachineRunner<'a, D, Sendable> {
+    /// Attaches an observer to the runner, which must be Send since the runner may be sent
+    /// to another thread
+    pub fn add_observer(&mut self, observer: impl RunnerObserver<D> + Send + 'static) {
+        self.add_step_hook(Box::new(observer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Instant};
+
+    use super::RunnerObserver;
+    use crate::sm::{
+        BoxedOutcome, ContinueOutcome, IntoOutcome, OutcomeData, RunErrorKind, State, StateMachine,
+    };
+
+    #[derive(Default)]
+    struct Descend;
+
+    impl State for Descend {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+            if *data < 2 {
+                ContinueOutcome::<Self>::default().into_outcome()
+            } else {
+                OutcomeData::<Hover>::with_name((), "deep enough".to_string()).into_outcome()
+            }
+        }
+
+        fn name(&self) -> String {
+            "Descend".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Hover;
+
+    impl State for Hover {
+        type Income = ();
+        type Transition = ();
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+        }
+
+        fn name(&self) -> String {
+            "Hover".to_string()
+        }
+    }
+
+    /// Records every notification it receives, prefixed with its tag
+    struct Recorder {
+        tag: &'static str,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn record(&self, event: String) {
+            self.log.borrow_mut().push(format!("{}: {event}", self.tag));
+        }
+    }
+
+    impl RunnerObserver<u32> for Recorder {
+        fn on_step(&mut self, state: &str, _at: Instant, data: &u32) {
+            self.record(format!("step {state} {data}"));
+        }
+
+        fn on_exit(&mut self, state: &str, _at: Instant, _data: &u32) {
+            self.record(format!("exit {state}"));
+        }
+
+        fn on_transition(
+            &mut self,
+            start: &str,
+            transition: &str,
+            end: &str,
+            _at: Instant,
+            _data: &u32,
+        ) {
+            self.record(format!("{start} --[{transition}]--> {end}"));
+        }
+
+        fn on_enter(&mut self, state: &str, _at: Instant, _data: &u32) {
+            self.record(format!("enter {state}"));
+        }
+
+        fn on_complete(&mut self, start: &str, transition: &str, _at: Instant, data: &u32) {
+            self.record(format!("{start} --[{transition}]--> END {data}"));
+        }
+
+        fn on_error(&mut self, error: &RunErrorKind, _at: Instant, _data: &u32) {
+            self.record(format!("error {error}"));
+        }
+    }
+
+    #[test]
+    fn observers() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Descend>();
+        machine.add_state::<Hover>();
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut runner = machine.runner::<Descend>(0, ()).unwrap();
+        runner.add_observer(Recorder {
+            tag: "a",
+            log: log.clone(),
+        });
+        runner.add_observer(Recorder {
+            tag: "b",
+            log: log.clone(),
+        });
+        assert_eq!(runner.run_to_completion().unwrap(), 3);
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "a: step Descend 1",
+                "b: step Descend 1",
+                "a: step Descend 2",
+                "a: exit Descend",
+                "a: Descend --[deep enough]--> Hover",
+                "a: enter Hover",
+                "b: step Descend 2",
+                "b: exit Descend",
+                "b: Descend --[deep enough]--> Hover",
+                "b: enter Hover",
+                "a: step Hover 3",
+                "a: exit Hover",
+                "a: Hover --[(Complete)]--> END 3",
+                "b: step Hover 3",
+                "b: exit Hover",
+                "b: Hover --[(Complete)]--> END 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn observed_error() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Descend>();
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut runner = machine.runner::<Descend>(1, ()).unwrap();
+        runner.add_observer(Recorder {
+            tag: "a",
+            log: log.clone(),
+        });
+        assert!(runner.run_to_completion().is_err());
+        let log = log.borrow();
+        assert_eq!(log[..2], ["a: step Descend 2", "a: exit Descend"]);
+        assert!(log[2].starts_with("a: error Descend --[deep enough]-->"));
+        assert_eq!(log.len(), 3);
+    }
+}
@@ -0,0 +1,569 @@
+use std::any::{type_name, TypeId};
+
+use crate::sm::{
+    BoxedFuture, BoxedOutcome, ContinueOutcome, DeclaredTransition, History, IntoOutcome, State,
+    StateInternal, StateMachine, StepReport,
+};
+
+/// Determines when a Parallel state completes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum JoinPolicy {
+    /// Complete once every region has transitioned to `()`
+    #[default]
+    All,
+    /// Complete as soon as any region transitions to `()`,
+    /// or once no region is active, such as when there are no regions
+    Any,
+}
+
+/// A single region of a Parallel state
+///
+/// state is None once the region has completed or errored,
+/// or if its start state is not present in its state machine
+struct Region<Data: 'static> {
+    machine: StateMachine<Data>,
+    state: Option<Box<dyn StateInternal<Data>>>,
+    history: History<Data>,
+    /// Set if the start state of the region is not present in its state machine
+    missing_start: Option<TypeId>,
+}
+
+/// The regions of a Parallel state, along with the policy which decides when they complete
+///
+/// Each region runs its own instance of a state machine, with its own current state
+pub struct Regions<Data: 'static> {
+    regions: Vec<Region<Data>>,
+    join: JoinPolicy,
+}
+
+// Manually implemented because derive macro requires D: Default
+impl<D> Default for Regions<D> {
+    fn default() -> Self {
+        Self {
+            regions: Vec::new(),
+            join: JoinPolicy::default(),
+        }
+    }
+}
+
+impl<D: 'static> Regions<D> {
+    /// Adds a region which runs the state machine built by `build`,
+    /// starting in Start with `income`
+    pub fn add<Start: State<Data = D>>(
+        &mut self,
+        build: fn(&mut StateMachine<D>),
+        income: Start::Income,
+    ) -> &mut Self {
+        let mut machine = StateMachine::default();
+        build(&mut machine);
+        let mut history = History::default();
+        let state = machine.start_state::<Start>(&mut history, income);
+        let missing_start = state.is_none().then(TypeId::of::<Start>);
+        self.regions.push(Region {
+            machine,
+            state,
+            history,
+            missing_start,
+        });
+        self
+    }
+
+    /// Sets when the regions complete, JoinPolicy::All unless set
+    pub fn set_join_policy(&mut self, join: JoinPolicy) -> &mut Self {
+        self.join = join;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Returns true if no region is active
+    fn all_inactive(&self) -> bool {
+        self.regions.iter().all(|region| region.state.is_none())
+    }
+
+    /// Runs the exit method of the current state of every region which is still active
+    fn exit_all(&mut self, data: &mut D) {
+        for region in &mut self.regions {
+            if let Some(mut state) = region.state.take() {
+                state.exit(data);
+            }
+        }
+    }
+}
+
+/// Type useful for States which run several independent state machines at once
+///
+/// Every active region is stepped each time the enclosing state is handled,
+/// in the order the regions were added, against the same data.
+/// A region stops being stepped once its state machine transitions to `()`
+pub trait ParallelState: Default + 'static {
+    type Income: 'static;
+    type Transition: IntoOutcome;
+    type Data: 'static;
+
+    /// Adds the regions and sets their join policy
+    ///
+    /// This method is run each time this state is entered, restarting every region
+    fn regions(&mut self, previous: Box<Self::Income>, regions: &mut Regions<Self::Data>);
+    /// This method is run once the regions complete according to their join policy,
+    /// after the exit of any region still active
+    fn handle_complete(&mut self, data: &mut Self::Data) -> Self::Transition;
+    /// This method is run once the region at `region` cannot continue,
+    /// after the exit of every other region
+    ///
+    /// error is always a StateNotFound or an IncorrectTransition
+    fn handle_error(
+        &mut self,
+        region: usize,
+        error: StepReport,
+        data: &mut Self::Data,
+    ) -> Self::Transition;
+    /// This method is run for every step of a region which is not StepReport::Continue,
+    /// before handle_complete or handle_error
+    #[allow(unused)]
+    fn on_region_step(&mut self, region: usize, report: &StepReport, data: &mut Self::Data) {}
+    /// This method is run once when transitioning away from this state,
+    /// after the exit of any region still active
+    #[allow(unused)]
+    fn exit(&mut self, data: &mut Self::Data) {}
+
+    fn name(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+}
+
+/// This struct wraps ParallelState types and provides a functional State implementation
+/// for all ParallelState types
+///
+/// ```
+/// use umrsm::{sm::{BoxedOutcome, ContinueOutcome, IntoOutcome, State, StateMachine, StepReport}, sm_parallel::{JoinPolicy, Parallel, ParallelState, Regions}};
+///
+/// #[derive(Default)]
+/// struct Navigate;
+///
+/// impl State for Navigate {
+///     type Income = ();
+///     type Transition = BoxedOutcome;
+///     type Data = (u32, u32);
+///
+///     fn handle(&mut self, (waypoints, _): &mut Self::Data) -> Self::Transition {
+///         *waypoints += 1;
+///         if *waypoints == 3 {
+///             ().into_outcome()
+///         } else {
+///             ContinueOutcome::<Self>::default().into_outcome()
+///         }
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct MonitorLeaks;
+///
+/// impl State for MonitorLeaks {
+///     type Income = ();
+///     type Transition = ContinueOutcome<Self>;
+///     type Data = (u32, u32);
+///
+///     fn handle(&mut self, (_, checks): &mut Self::Data) -> Self::Transition {
+///         *checks += 1;
+///         ContinueOutcome::default()
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct TransitInner;
+///
+/// impl ParallelState for TransitInner {
+///     type Income = ();
+///     type Transition = ();
+///     type Data = (u32, u32);
+///
+///     fn regions(&mut self, _previous: Box<Self::Income>, regions: &mut Regions<(u32, u32)>) {
+///         regions
+///             .add::<Navigate>(|machine| machine.add_state::<Navigate>(), ())
+///             .add::<MonitorLeaks>(|machine| machine.add_state::<MonitorLeaks>(), ())
+///             .set_join_policy(JoinPolicy::Any);
+///     }
+///
+///     fn handle_complete(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+///
+///     fn handle_error(&mut self, _region: usize, error: StepReport, _data: &mut Self::Data) -> Self::Transition {
+///         panic!("{error}")
+///     }
+/// }
+///
+/// type Transit = Parallel<TransitInner>;
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<Transit>();
+///
+/// let runner = machine.runner::<Transit>((0, 0), ()).expect("Transit exists in the machine");
+/// assert_eq!(runner.run_to_completion().expect("Should not error"), (3, 3));
+/// ```
+pub struct Parallel<S: ParallelState> {
+    regions: Regions<S::Data>,
+    inner: S,
+}
+
+impl<S: ParallelState> Default for Parallel<S> {
+    fn default() -> Self {
+        Self {
+            regions: Default::default(),
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<S: ParallelState> Parallel<S> {
+    /// Reports the first region whose start state is not present in its state machine
+    /// Returns None if every region was started
+    fn missing_start(&mut self, data: &mut S::Data) -> Option<BoxedOutcome> {
+        let (region, end) = self
+            .regions
+            .regions
+            .iter()
+            .enumerate()
+            .find_map(|(region, entry)| Some((region, entry.missing_start?)))?;
+        let error = StepReport::StateNotFound {
+            start: self.inner.name(),
+            transition: "(Start)".to_string(),
+            end,
+        };
+        self.regions.exit_all(data);
+        Some(self.inner.handle_error(region, error, data).into_outcome())
+    }
+
+    /// Maps the report of a step of the region at `region` into the state of that region
+    /// Returns true if the region completed
+    fn finish_region(&mut self, region: usize, report: &StepReport, data: &mut S::Data) -> bool {
+        if report.is_notable() {
+            self.inner.on_region_step(region, report, data);
+        }
+        let complete = matches!(report, StepReport::Complete { .. });
+        if complete || report.is_error() {
+            self.regions.regions[region].state = None;
+        }
+        complete
+    }
+
+    /// Maps the reports of a step of every active region into a transition of this state
+    fn finish_step(
+        &mut self,
+        reports: Vec<(usize, StepReport)>,
+        data: &mut S::Data,
+    ) -> BoxedOutcome {
+        let mut completed = false;
+        let mut error = None;
+        for (region, report) in reports {
+            completed |= self.finish_region(region, &report, data);
+            if report.is_error() && error.is_none() {
+                error = Some((region, report));
+            }
+        }
+        if let Some((region, error)) = error {
+            self.regions.exit_all(data);
+            return self.inner.handle_error(region, error, data).into_outcome();
+        }
+        let complete = match self.regions.join {
+            JoinPolicy::All => self.regions.all_inactive(),
+            JoinPolicy::Any => completed || self.regions.all_inactive(),
+        };
+        if !complete {
+            return ContinueOutcome::<Self>::default().into_outcome();
+        }
+        self.regions.exit_all(data);
+        self.inner.handle_complete(data).into_outcome()
+    }
+}
+
+impl<S: ParallelState> State for Parallel<S> {
+    type Income = S::Income;
+    type Transition = BoxedOutcome;
+    type Data = S::Data;
+
+    fn init(&mut self, previous: Box<Self::Income>) {
+        self.regions = Regions::default();
+        self.inner.regions(previous, &mut self.regions);
+    }
+
+    fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+        if let Some(error) = self.missing_start(data) {
+            return error;
+        }
+        let mut reports = Vec::with_capacity(self.regions.len());
+        for (index, region) in self.regions.regions.iter_mut().enumerate() {
+            let Some(state) = &mut region.state else {
+                continue;
+            };
+            let report = region.machine.step_state(state, &mut region.history, data);
+            reports.push((index, report));
+        }
+        self.finish_step(reports, data)
+    }
+
+    fn handle_async<'s>(
+        &'s mut self,
+        data: &'s mut Self::Data,
+    ) -> BoxedFuture<'s, Self::Transition> {
+        Box::pin(async move {
+            if let Some(error) = self.missing_start(data) {
+                return error;
+            }
+            let mut reports = Vec::with_capacity(self.regions.len());
+            for (index, region) in self.regions.regions.iter_mut().enumerate() {
+                let Some(state) = &mut region.state else {
+                    continue;
+                };
+                let report = region
+                    .machine
+                    .step_state_async(state, &mut region.history, data)
+                    .await;
+                reports.push((index, report));
+            }
+            self.finish_step(reports, data)
+        })
+    }
+
+    fn exit(&mut self, data: &mut Self::Data) {
+        self.regions.exit_all(data);
+        self.inner.exit(data);
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+        S::Transition::declared_transitions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::TypeId;
+
+    use super::{JoinPolicy, Parallel, ParallelState, Regions};
+    use crate::sm::{
+        BoxedOutcome, ContinueOutcome, IntoOutcome, OutcomeData, State, StateMachine, StepOutcome,
+        StepReport,
+    };
+
+    #[derive(Debug, Default, PartialEq, Eq)]
+    struct Data {
+        navigated: u32,
+        monitored: u32,
+        log: Vec<String>,
+    }
+
+    #[derive(Default)]
+    struct Navigate;
+
+    impl State for Navigate {
+        type Income = u32;
+        type Transition = BoxedOutcome;
+        type Data = Data;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.navigated += 1;
+            if data.navigated == 3 {
+                ().into_outcome()
+            } else {
+                ContinueOutcome::<Self>::default().into_outcome()
+            }
+        }
+
+        fn name(&self) -> String {
+            "Navigate".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Monitor;
+
+    impl State for Monitor {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = Data;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.monitored += 1;
+            if data.monitored == 5 {
+                ().into_outcome()
+            } else {
+                ContinueOutcome::<Self>::default().into_outcome()
+            }
+        }
+
+        fn exit(&mut self, data: &mut Self::Data) {
+            data.log.push("exit Monitor".to_string());
+        }
+
+        fn name(&self) -> String {
+            "Monitor".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Drift;
+
+    impl State for Drift {
+        type Income = ();
+        type Transition = OutcomeData<Navigate>;
+        type Data = Data;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::new(0)
+        }
+
+        fn name(&self) -> String {
+            "Drift".to_string()
+        }
+    }
+
+    fn build(machine: &mut StateMachine<Data>) {
+        machine.add_state::<Navigate>();
+        machine.add_state::<Monitor>();
+    }
+
+    /// The regions a Patrol is entered with
+    enum Scenario {
+        NavigateAndMonitor,
+        MissingMonitor,
+        MonitorAndDrift,
+        Empty,
+    }
+
+    #[derive(Default)]
+    struct PatrolInner;
+
+    impl ParallelState for PatrolInner {
+        type Income = (Scenario, JoinPolicy);
+        type Transition = ();
+        type Data = Data;
+
+        fn regions(&mut self, previous: Box<Self::Income>, regions: &mut Regions<Data>) {
+            let (scenario, join) = *previous;
+            match scenario {
+                Scenario::NavigateAndMonitor => {
+                    regions.add::<Navigate>(build, 0).add::<Monitor>(build, ());
+                }
+                Scenario::MissingMonitor => {
+                    regions
+                        .add::<Navigate>(build, 0)
+                        .add::<Monitor>(|machine| machine.add_state::<Navigate>(), ());
+                }
+                Scenario::MonitorAndDrift => {
+                    regions
+                        .add::<Monitor>(build, ())
+                        .add::<Drift>(|machine| machine.add_state::<Drift>(), ());
+                }
+                Scenario::Empty => {}
+            }
+            regions.set_join_policy(join);
+        }
+
+        fn handle_complete(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.log.push("complete".to_string());
+        }
+
+        fn handle_error(
+            &mut self,
+            region: usize,
+            error: StepReport,
+            data: &mut Self::Data,
+        ) -> Self::Transition {
+            let StepReport::StateNotFound { start, end, .. } = error else {
+                panic!("Unexpeced region error {error}");
+            };
+            let end = if end == TypeId::of::<Navigate>() {
+                "Navigate"
+            } else {
+                "Monitor"
+            };
+            data.log.push(format!("error {region} {start} {end}"));
+        }
+
+        fn on_region_step(&mut self, region: usize, report: &StepReport, data: &mut Self::Data) {
+            if matches!(report, StepReport::Complete { .. }) {
+                data.log.push(format!("{region} complete"));
+            }
+        }
+
+        fn name(&self) -> String {
+            "Patrol".to_string()
+        }
+    }
+
+    type Patrol = Parallel<PatrolInner>;
+
+    fn run(scenario: Scenario, join: JoinPolicy) -> Data {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Patrol>();
+        let runner = machine
+            .runner::<Patrol>(Data::default(), (scenario, join))
+            .unwrap();
+        runner.run_to_completion().expect("Should not error")
+    }
+
+    #[test]
+    fn join_all() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Patrol>();
+        let mut runner = machine
+            .runner::<Patrol>(
+                Data::default(),
+                (Scenario::NavigateAndMonitor, JoinPolicy::All),
+            )
+            .unwrap();
+        for _ in 0..4 {
+            runner = match runner.step() {
+                StepOutcome::Continue { machine } => machine,
+                e => panic!("Unexpeced runner outcome {e:?}"),
+            };
+        }
+        assert_eq!(runner.data.log, ["0 complete"]);
+        let data = match runner.step() {
+            StepOutcome::Complete { data, .. } => data,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        assert_eq!((data.navigated, data.monitored), (3, 5));
+        assert_eq!(
+            data.log,
+            ["0 complete", "exit Monitor", "1 complete", "complete"]
+        );
+    }
+
+    #[test]
+    fn join_any() {
+        let data = run(Scenario::NavigateAndMonitor, JoinPolicy::Any);
+        assert_eq!((data.navigated, data.monitored), (3, 3));
+        assert_eq!(data.log, ["0 complete", "exit Monitor", "complete"]);
+    }
+
+    #[test]
+    fn missing_region_start() {
+        let data = run(Scenario::MissingMonitor, JoinPolicy::All);
+        assert_eq!((data.navigated, data.monitored), (0, 0));
+        assert_eq!(data.log, ["error 1 Patrol Monitor"]);
+    }
+
+    #[test]
+    fn no_regions() {
+        for join in [JoinPolicy::All, JoinPolicy::Any] {
+            assert_eq!(run(Scenario::Empty, join).log, ["complete"]);
+        }
+    }
+
+    #[test]
+    fn region_error() {
+        let data = run(Scenario::MonitorAndDrift, JoinPolicy::All);
+        assert_eq!((data.navigated, data.monitored), (0, 1));
+        assert_eq!(data.log, ["exit Monitor", "error 1 Drift Navigate"]);
+    }
+}
@@ -0,0 +1,272 @@
+use core::fmt;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::sm::{RunError, StateMachineRunner, Threading};
+
+/// Timing statistics of the steps of a single state while running at a fixed rate
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StateRateStatistics {
+    pub steps: u64,
+    /// The number of steps which took longer than the period
+    pub overruns: u64,
+    pub total_step_time: Duration,
+    pub max_step_time: Duration,
+}
+
+impl StateRateStatistics {
+    pub fn mean_step_time(&self) -> Duration {
+        mean(self.total_step_time, self.steps)
+    }
+}
+
+/// Timing statistics of a runner which was run at a fixed rate
+///
+/// Jitter is how late a step started compared to when it was scheduled.
+/// A step overruns if it takes longer than the period,
+/// in which case every tick which passed during the step is skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateStatistics {
+    pub period: Duration,
+    pub steps: u64,
+    pub overruns: u64,
+    pub skipped_ticks: u64,
+    pub total_jitter: Duration,
+    pub max_jitter: Duration,
+    /// Keyed by the name of the state which was stepped
+    pub states: BTreeMap<String, StateRateStatistics>,
+}
+
+impl RateStatistics {
+    fn new(period: Duration) -> Self {
+        Self {
+            period,
+            steps: 0,
+            overruns: 0,
+            skipped_ticks: 0,
+            total_jitter: Duration::ZERO,
+            max_jitter: Duration::ZERO,
+            states: BTreeMap::new(),
+        }
+    }
+
+    pub fn mean_jitter(&self) -> Duration {
+        mean(self.total_jitter, self.steps)
+    }
+
+    fn record(&mut self, state: String, jitter: Duration, step_time: Duration) {
+        let overrun = step_time > self.period;
+        self.steps += 1;
+        self.overruns += overrun as u64;
+        self.total_jitter += jitter;
+        self.max_jitter = self.max_jitter.max(jitter);
+        let state = self.states.entry(state).or_default();
+        state.steps += 1;
+        state.overruns += overrun as u64;
+        state.total_step_time += step_time;
+        state.max_step_time = state.max_step_time.max(step_time);
+    }
+}
+
+impl Display for RateStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} steps at {:?} per step: {} overruns, {} skipped ticks, jitter mean {:?} max {:?}",
+            self.steps,
+            self.period,
+            self.overruns,
+            self.skipped_ticks,
+            self.mean_jitter(),
+            self.max_jitter
+        )?;
+        for (state, statistics) in &self.states {
+            writeln!(
+                f,
+                "    {state}: {} steps, {} overruns, step time mean {:?} max {:?}",
+                statistics.steps,
+                statistics.overruns,
+                statistics.mean_step_time(),
+                statistics.max_step_time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn mean(total: Duration, count: u64) -> Duration {
+    match count {
+        0 => Duration::ZERO,
+        count => Duration::from_nanos((total.as_nanos() / count as u128) as u64),
+    }
+}
+
+impl<'a, D, K: Threading> StateMachineRunner<'a, D, K> {
+    /// Run the state machine until it either errors or completes,
+    /// starting one step every 1 / hz seconds
+    ///
+    /// Panics if hz is not positive and finite,
+    /// or so large that its period rounds to zero nanoseconds
+    pub fn run_at_rate(self, hz: f64) -> (Result<D, RunError<D>>, RateStatistics) {
+        assert!(
+            hz.is_finite() && hz > 0.0,
+            "The rate of a state machine must be positive and finite, not {hz}"
+        );
+        let period = Duration::from_secs_f64(1.0 / hz);
+        assert!(
+            !period.is_zero(),
+            "The rate of a state machine must have a period of at least a nanosecond, not {hz}"
+        );
+        self.run_with_period(period)
+    }
+
+    /// Run the state machine until it either errors or completes,
+    /// starting one step every period
+    ///
+    /// Steps are scheduled relative to the first step so that the rate does not drift.
+    /// Returns the result of the state machine along with the timing statistics of every step
+    ///
+    /// Panics if period is zero
+    pub fn run_with_period(mut self, period: Duration) -> (Result<D, RunError<D>>, RateStatistics) {
+        assert!(
+            !period.is_zero(),
+            "The period of a state machine must not be zero"
+        );
+        let mut statistics = RateStatistics::new(period);
+        let mut scheduled = Instant::now();
+        loop {
+            let now = Instant::now();
+            if scheduled > now {
+                thread::sleep(scheduled - now);
+            }
+            let started = Instant::now();
+            let state = self.state_name();
+            let outcome = self.step();
+            let finished = Instant::now();
+            statistics.record(
+                state,
+                started.saturating_duration_since(scheduled),
+                finished - started,
+            );
+
+            scheduled += period;
+            if scheduled < finished {
+                let skipped = (finished - scheduled)
+                    .as_nanos()
+                    .div_ceil(period.as_nanos()) as u64;
+                scheduled += Duration::from_nanos(skipped.saturating_mul(period.as_nanos() as u64));
+                statistics.skipped_ticks += skipped;
+            }
+            self = match outcome.into_runner() {
+                Ok(machine) => machine,
+                Err(result) => return (result, statistics),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::sm::{BoxedOutcome, ContinueOutcome, IntoOutcome, OutcomeData, State, StateMachine};
+
+    #[derive(Default)]
+    struct Hold;
+
+    impl State for Hold {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+            if *data < 5 {
+                ContinueOutcome::<Self>::default().into_outcome()
+            } else {
+                OutcomeData::<Calibrate>::new(()).into_outcome()
+            }
+        }
+
+        fn name(&self) -> String {
+            "Hold".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Calibrate;
+
+    impl State for Calibrate {
+        type Income = ();
+        type Transition = ();
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+            thread::sleep(Duration::from_millis(25));
+        }
+
+        fn name(&self) -> String {
+            "Calibrate".to_string()
+        }
+    }
+
+    #[test]
+    fn run_at_rate() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Hold>();
+        machine.add_state::<Calibrate>();
+
+        let runner = machine.runner::<Hold>(0, ()).unwrap();
+        let start = Instant::now();
+        let (result, statistics) = runner.run_at_rate(100.0);
+        assert_eq!(result.unwrap(), 6);
+        // The fifth step of Hold starts four periods after the first
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        assert_eq!(statistics.period, Duration::from_millis(10));
+        assert_eq!(statistics.steps, 6);
+        assert_eq!(statistics.states["Hold"].steps, 5);
+
+        let calibrate = &statistics.states["Calibrate"];
+        assert_eq!(calibrate.steps, 1);
+        assert_eq!(calibrate.overruns, 1);
+        assert!(calibrate.max_step_time >= Duration::from_millis(25));
+        assert!(statistics.skipped_ticks >= 2);
+        assert!(statistics.overruns >= 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be positive and finite")]
+    fn invalid_rate() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Hold>();
+        let _ = machine.runner::<Hold>(0, ()).unwrap().run_at_rate(0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least a nanosecond")]
+    fn rate_too_high() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Hold>();
+        let _ = machine.runner::<Hold>(0, ()).unwrap().run_at_rate(1e12);
+    }
+
+    #[test]
+    #[should_panic(expected = "must not be zero")]
+    fn zero_period() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Hold>();
+        let _ = machine
+            .runner::<Hold>(0, ())
+            .unwrap()
+            .run_with_period(Duration::ZERO);
+    }
+}
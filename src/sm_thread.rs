@@ -0,0 +1,507 @@
+use core::fmt;
+use std::{
+    any::TypeId,
+    fmt::Display,
+    panic,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::sm::{
+    OwnedStateMachineRunner, RunError, Runner, State, StateMachine, StepOutcome, Threading,
+};
+
+/// Commands sent from a RunnerHandle to its worker thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Pause,
+    Resume,
+    Step,
+    Stop,
+}
+
+/// Notable events of a state machine running on a worker thread
+///
+/// Unlike StepOutcome, events only contain names so that they can be sent between threads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunnerEvent {
+    Transition {
+        start: String,
+        transition: String,
+        end: String,
+    },
+    Complete {
+        start: String,
+        transition: String,
+    },
+    /// An interceptor vetoed a transition, so the state machine stayed in start
+    Vetoed {
+        start: String,
+        transition: String,
+    },
+    /// The state machine transitioned to its error state instead of stopping,
+    /// where error is described by the Display of the RunErrorKind
+    Recovered {
+        error: String,
+        end: String,
+    },
+    /// The state machine stopped without completing,
+    /// described by the Display of the StepOutcome
+    Error(String),
+    Paused,
+    Resumed,
+    /// The state machine was stopped through RunnerHandle::stop
+    Stopped,
+}
+
+impl RunnerEvent {
+    /// Returns the event corresponding to a step, or None if the step was not notable
+    pub fn from_outcome<R: Runner>(outcome: &StepOutcome<R>) -> Option<Self> {
+        match outcome {
+            StepOutcome::Continue { .. } | StepOutcome::Unhandled { .. } => None,
+            StepOutcome::Transition {
+                start,
+                transition,
+                end,
+                ..
+            } => Some(RunnerEvent::Transition {
+                start: start.clone(),
+                transition: transition.clone(),
+                end: end.clone(),
+            }),
+            StepOutcome::Complete {
+                start, transition, ..
+            } => Some(RunnerEvent::Complete {
+                start: start.clone(),
+                transition: transition.clone(),
+            }),
+            StepOutcome::BailOut {
+                start, limit, end, ..
+            } => Some(RunnerEvent::Transition {
+                start: start.clone(),
+                transition: limit.to_string(),
+                end: end.clone(),
+            }),
+            StepOutcome::Vetoed {
+                start, transition, ..
+            } => Some(RunnerEvent::Vetoed {
+                start: start.clone(),
+                transition: transition.clone(),
+            }),
+            StepOutcome::Recovered { error, end, .. } => Some(RunnerEvent::Recovered {
+                error: error.to_string(),
+                end: end.clone(),
+            }),
+            error => Some(RunnerEvent::Error(error.to_string())),
+        }
+    }
+}
+
+impl Display for RunnerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunnerEvent::Transition {
+                start,
+                transition,
+                end,
+            } => write!(f, "{start} --[{transition}]--> {end}"),
+            RunnerEvent::Complete { start, transition } => {
+                write!(f, "{start} --[{transition}]--> END")
+            }
+            RunnerEvent::Vetoed { start, transition } => {
+                write!(f, "{start} --[{transition}]--X VETOED")
+            }
+            RunnerEvent::Recovered { error, end } => write!(f, "{error} RECOVERING --> {end}"),
+            RunnerEvent::Error(error) => write!(f, "{error}"),
+            RunnerEvent::Paused => write!(f, "(Paused)"),
+            RunnerEvent::Resumed => write!(f, "(Resumed)"),
+            RunnerEvent::Stopped => write!(f, "(Stopped)"),
+        }
+    }
+}
+
+/// Controls a state machine running on a worker thread
+///
+/// Commands sent after the state machine has finished are ignored.
+/// Dropping the handle stops the state machine like stop does,
+/// without waiting for the worker thread to finish
+pub struct RunnerHandle<Data> {
+    commands: Sender<Command>,
+    events: Receiver<RunnerEvent>,
+    thread: JoinHandle<Result<Data, RunError<Data>>>,
+}
+
+impl<D> fmt::Debug for RunnerHandle<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunnerHandle")
+            .field("thread", &self.thread.thread().id())
+            .field("finished", &self.thread.is_finished())
+            .finish()
+    }
+}
+
+impl<D> RunnerHandle<D> {
+    /// Stops stepping the state machine until resume or stop is called
+    pub fn pause(&self) {
+        let _ = self.commands.send(Command::Pause);
+    }
+
+    /// Continues stepping a paused state machine
+    pub fn resume(&self) {
+        let _ = self.commands.send(Command::Resume);
+    }
+
+    /// Performs exactly one step of a paused state machine
+    pub fn step_once(&self) {
+        let _ = self.commands.send(Command::Step);
+    }
+
+    /// Stops the state machine before its next step, running the exit of its current state
+    pub fn stop(&self) {
+        let _ = self.commands.send(Command::Stop);
+    }
+
+    /// The events of the state machine, in the order they happened
+    pub fn events(&self) -> &Receiver<RunnerEvent> {
+        &self.events
+    }
+
+    /// Returns true once the worker thread has finished
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the worker thread to finish
+    ///
+    /// Returns the final data if the state machine completed, or the error it stopped with.
+    /// A state machine stopped through stop returns RunErrorKind::Stopped along with its data.
+    /// If a state panicked, the panic is resumed on the calling thread
+    pub fn join(self) -> Result<D, RunError<D>> {
+        match self.thread.join() {
+            Ok(data) => data,
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<D: Send + 'static, K: Threading> StateMachine<D, K> {
+    /// Runs an instance of the state machine on a new worker thread
+    /// Returns None if the provided start state is not present in the state machine
+    pub fn spawn<Start: State>(
+        self: &Arc<Self>,
+        initial_data: D,
+        start_transition_data: Start::Income,
+    ) -> Option<RunnerHandle<D>>
+    where
+        Start::Income: Send,
+    {
+        self.spawn_with::<Start>(initial_data, start_transition_data, false)
+    }
+
+    /// The same as spawn, but the state machine does not step until resume or step_once is called
+    pub fn spawn_paused<Start: State>(
+        self: &Arc<Self>,
+        initial_data: D,
+        start_transition_data: Start::Income,
+    ) -> Option<RunnerHandle<D>>
+    where
+        Start::Income: Send,
+    {
+        self.spawn_with::<Start>(initial_data, start_transition_data, true)
+    }
+
+    fn spawn_with<Start: State>(
+        self: &Arc<Self>,
+        initial_data: D,
+        start_transition_data: Start::Income,
+        paused: bool,
+    ) -> Option<RunnerHandle<D>>
+    where
+        Start::Income: Send,
+    {
+        self.registration(TypeId::of::<Start>())?;
+        let machine = self.clone();
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let runner =
+                OwnedStateMachineRunner::new::<Start>(machine, initial_data, start_transition_data)
+                    .expect("Start was checked to be present in the state machine");
+            run_worker(runner, command_receiver, event_sender, paused)
+        });
+        Some(RunnerHandle {
+            commands,
+            events,
+            thread,
+        })
+    }
+}
+
+/// The loop run on the worker thread of a RunnerHandle
+fn run_worker<D, K: Threading>(
+    mut runner: OwnedStateMachineRunner<D, K>,
+    commands: Receiver<Command>,
+    events: Sender<RunnerEvent>,
+    mut paused: bool,
+) -> Result<D, RunError<D>> {
+    loop {
+        let command = if paused {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => Some(Command::Stop),
+            }
+        } else {
+            match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => Some(Command::Stop),
+            }
+        };
+        match command {
+            Some(Command::Pause) => {
+                paused = true;
+                let _ = events.send(RunnerEvent::Paused);
+                continue;
+            }
+            Some(Command::Resume) => {
+                paused = false;
+                let _ = events.send(RunnerEvent::Resumed);
+                continue;
+            }
+            Some(Command::Stop) => {
+                let _ = events.send(RunnerEvent::Stopped);
+                return Err(runner.stop());
+            }
+            Some(Command::Step) | None => {}
+        }
+        let outcome = runner.step();
+        if let Some(event) = RunnerEvent::from_outcome(&outcome) {
+            let _ = events.send(event);
+        }
+        runner = match outcome.into_runner() {
+            Ok(machine) => machine,
+            Err(result) => return result,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::RunnerEvent;
+    use crate::sm::{
+        BoxedOutcome, ContinueOutcome, IntoOutcome, OutcomeData, RunErrorKind, State, StateMachine,
+    };
+
+    #[derive(Default)]
+    struct Ping;
+
+    impl State for Ping {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+            if *data == 9 {
+                ().into_outcome()
+            } else {
+                OutcomeData::<Pong>::new(()).into_outcome()
+            }
+        }
+
+        fn name(&self) -> String {
+            "Ping".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Pong;
+
+    impl State for Pong {
+        type Income = ();
+        type Transition = OutcomeData<Ping>;
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+            OutcomeData::new(())
+        }
+
+        fn name(&self) -> String {
+            "Pong".to_string()
+        }
+    }
+
+    /// Counts its steps, and adds 100 once it is exited
+    #[derive(Default)]
+    struct Idle;
+
+    impl State for Idle {
+        type Income = ();
+        type Transition = ContinueOutcome<Self>;
+        type Data = Arc<AtomicU32>;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            data.fetch_add(1, Ordering::SeqCst);
+            ContinueOutcome::default()
+        }
+
+        fn exit(&mut self, data: &mut Self::Data) {
+            data.fetch_add(100, Ordering::SeqCst);
+        }
+
+        fn name(&self) -> String {
+            "Idle".to_string()
+        }
+    }
+
+    fn idle_machine() -> Arc<StateMachine<Arc<AtomicU32>>> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Idle>();
+        Arc::new(machine)
+    }
+
+    fn machine() -> Arc<StateMachine<u32>> {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Ping>();
+        machine.add_state::<Pong>();
+        Arc::new(machine)
+    }
+
+    #[test]
+    fn run_to_completion() {
+        let handle = machine().spawn::<Ping>(0, ()).unwrap();
+        let events: Vec<RunnerEvent> = handle.events().iter().collect();
+        assert_eq!(events.len(), 9);
+        assert_eq!(
+            events[8],
+            RunnerEvent::Complete {
+                start: "Ping".to_string(),
+                transition: "(Complete)".to_string(),
+            }
+        );
+        assert_eq!(handle.join().unwrap(), 9);
+    }
+
+    #[test]
+    fn step_and_stop() {
+        let handle = machine().spawn_paused::<Ping>(0, ()).unwrap();
+        for _ in 0..3 {
+            handle.step_once();
+        }
+        handle.stop();
+        let events: Vec<RunnerEvent> = handle.events().iter().collect();
+        assert_eq!(
+            events[..2],
+            [
+                RunnerEvent::Transition {
+                    start: "Ping".to_string(),
+                    transition: std::any::type_name::<Pong>().to_string(),
+                    end: "Pong".to_string(),
+                },
+                RunnerEvent::Transition {
+                    start: "Pong".to_string(),
+                    transition: std::any::type_name::<Ping>().to_string(),
+                    end: "Ping".to_string(),
+                },
+            ]
+        );
+        assert_eq!(events[3..], [RunnerEvent::Stopped]);
+        let error = handle.join().unwrap_err();
+        assert_eq!(
+            error.kind,
+            RunErrorKind::Stopped {
+                state: "Pong".to_string()
+            }
+        );
+        assert_eq!(error.data, 3);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let handle = machine().spawn_paused::<Ping>(0, ()).unwrap();
+        handle.pause();
+        handle.step_once();
+        handle.resume();
+        let events: Vec<RunnerEvent> = handle.events().iter().collect();
+        let transition = |start: &str, end: &str| RunnerEvent::Transition {
+            start: start.to_string(),
+            transition: match end {
+                "Ping" => std::any::type_name::<Ping>().to_string(),
+                _ => std::any::type_name::<Pong>().to_string(),
+            },
+            end: end.to_string(),
+        };
+        assert_eq!(
+            events,
+            [
+                RunnerEvent::Paused,
+                transition("Ping", "Pong"),
+                RunnerEvent::Resumed,
+                transition("Pong", "Ping"),
+                transition("Ping", "Pong"),
+                transition("Pong", "Ping"),
+                transition("Ping", "Pong"),
+                transition("Pong", "Ping"),
+                transition("Ping", "Pong"),
+                transition("Pong", "Ping"),
+                RunnerEvent::Complete {
+                    start: "Ping".to_string(),
+                    transition: "(Complete)".to_string(),
+                },
+            ]
+        );
+        assert_eq!(handle.join().unwrap(), 9);
+    }
+
+    #[test]
+    fn missing_start() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Pong>();
+        assert!(Arc::new(machine).spawn::<Ping>(0, ()).is_none());
+    }
+
+    #[test]
+    fn stop_exits_current_state() {
+        let handle = idle_machine()
+            .spawn_paused::<Idle>(Arc::default(), ())
+            .unwrap();
+        handle.step_once();
+        handle.step_once();
+        handle.stop();
+        let error = handle.join().unwrap_err();
+        assert_eq!(
+            error.kind,
+            RunErrorKind::Stopped {
+                state: "Idle".to_string()
+            }
+        );
+        assert_eq!(error.data.load(Ordering::SeqCst), 102);
+    }
+
+    #[test]
+    fn drop_stops_running_machine() {
+        let steps = Arc::new(AtomicU32::new(0));
+        let handle = idle_machine().spawn::<Idle>(steps.clone(), ()).unwrap();
+        drop(handle);
+        // The worker drops its data once it has finished
+        let timeout = Instant::now() + Duration::from_secs(5);
+        while Arc::strong_count(&steps) > 1 {
+            assert!(Instant::now() < timeout, "The worker kept running");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(steps.load(Ordering::SeqCst) >= 100);
+    }
+}
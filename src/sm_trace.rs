@@ -0,0 +1,946 @@
+use core::fmt;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, BufRead, Read, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    sm::{Limit, RunErrorKind},
+    sm_observer::RunnerObserver,
+};
+
+/// What a trace entry records
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TraceKind {
+    /// A transition of the state machine, or its completion if the entry has no end
+    #[default]
+    Transition,
+    /// An error, whether or not it was recovered from, described by the transition of the entry
+    Error,
+    /// A transition to the error state after the error described by the transition of the entry
+    Recovery,
+    /// A transition to the bail out state after the runner reached a limit
+    BailOut,
+}
+
+impl TraceKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            TraceKind::Transition => "transition",
+            TraceKind::Error => "error",
+            TraceKind::Recovery => "recovery",
+            TraceKind::BailOut => "bail_out",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        [
+            TraceKind::Transition,
+            TraceKind::Error,
+            TraceKind::Recovery,
+            TraceKind::BailOut,
+        ]
+        .into_iter()
+        .find(|known| known.as_str() == kind)
+    }
+}
+
+/// A single transition or error of a traced state machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The index of the step which caused the entry, counting from 1 like the runner does
+    ///
+    /// Events are not steps, so an entry caused by an event processed with process_event
+    /// has the index of the last step before the event
+    pub step: u64,
+    pub kind: TraceKind,
+    /// The state the state machine was in
+    pub start: String,
+    pub transition: String,
+    /// None if the transition completed the state machine or if the entry is an error
+    pub end: Option<String>,
+    pub wall_time: SystemTime,
+    /// Time since the recorder was created
+    pub monotonic: Duration,
+    /// The number of steps `start` ran for, including the step which caused the transition
+    /// and not counting events
+    pub steps_in_state: u64,
+}
+
+/// An in-memory log of the transitions and errors of a state machine
+///
+/// Traces are written as JSON Lines with one object per entry, or as CSV with a header row.
+/// Times are written as integer nanoseconds, wall clock time since the Unix epoch
+/// and monotonic time since the recorder was created.
+/// In CSV, an empty end marks a transition which completed the state machine or an error
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+}
+
+/// The columns of a trace, in the order they are written
+const FIELDS: [&str; 8] = [
+    "step",
+    "kind",
+    "start",
+    "transition",
+    "end",
+    "wall_time_ns",
+    "monotonic_ns",
+    "steps_in_state",
+];
+
+/// An error while reading a trace
+#[derive(Debug)]
+pub enum TraceReadError {
+    Io(io::Error),
+    /// The trace is malformed at the given line, counting from 1
+    Parse { line: usize, message: String },
+}
+
+impl Display for TraceReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceReadError::Io(error) => write!(f, "Could not read trace: {error}"),
+            TraceReadError::Parse { line, message } => write!(f, "Line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for TraceReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TraceReadError::Io(error) => Some(error),
+            TraceReadError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for TraceReadError {
+    fn from(error: io::Error) -> Self {
+        TraceReadError::Io(error)
+    }
+}
+
+impl Trace {
+    /// Writes every entry as a JSON object on its own line
+    pub fn write_jsonl(&self, mut writer: impl Write) -> io::Result<()> {
+        for entry in &self.entries {
+            let end = match &entry.end {
+                Some(end) => json_string(end),
+                None => "null".to_string(),
+            };
+            writeln!(
+                writer,
+                "{{\"step\":{},\"kind\":\"{}\",\"start\":{},\"transition\":{},\"end\":{end},\"wall_time_ns\":{},\"monotonic_ns\":{},\"steps_in_state\":{}}}",
+                entry.step,
+                entry.kind.as_str(),
+                json_string(&entry.start),
+                json_string(&entry.transition),
+                wall_time_ns(entry.wall_time),
+                entry.monotonic.as_nanos(),
+                entry.steps_in_state,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes a header row followed by one row for every entry
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", FIELDS.join(","))?;
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                entry.step,
+                entry.kind.as_str(),
+                csv_field(&entry.start),
+                csv_field(&entry.transition),
+                entry.end.as_deref().map(csv_field).unwrap_or_default(),
+                wall_time_ns(entry.wall_time),
+                entry.monotonic.as_nanos(),
+                entry.steps_in_state,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reads a trace written by write_jsonl
+    ///
+    /// Blank lines are ignored
+    pub fn read_jsonl(reader: impl BufRead) -> Result<Self, TraceReadError> {
+        let mut entries = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = parse_json_object(&line)
+                .and_then(|object| entry_from_fields(|field| object.get(field).cloned()))
+                .map_err(|message| TraceReadError::Parse {
+                    line: index + 1,
+                    message,
+                })?;
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Reads a trace written by write_csv
+    ///
+    /// The header row must contain the same columns as written by write_csv, in any order
+    pub fn read_csv(mut reader: impl Read) -> Result<Self, TraceReadError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut records = parse_csv(&text)?.into_iter();
+        let Some((_, header)) = records.next() else {
+            return Ok(Self::default());
+        };
+        let mut entries = Vec::new();
+        for (line, record) in records {
+            let parse_error = |message| TraceReadError::Parse { line, message };
+            if record.len() != header.len() {
+                return Err(parse_error(format!(
+                    "Expected {} fields but found {}",
+                    header.len(),
+                    record.len()
+                )));
+            }
+            let entry = entry_from_fields(|field| {
+                let index = header.iter().position(|column| column == field)?;
+                let value = &record[index];
+                Some(match field {
+                    "kind" | "start" | "transition" => JsonValue::String(value.clone()),
+                    "end" if value.is_empty() => JsonValue::Null,
+                    "end" => JsonValue::String(value.clone()),
+                    _ => match value.parse() {
+                        Ok(number) => JsonValue::Number(number),
+                        Err(_) => JsonValue::String(value.clone()),
+                    },
+                })
+            })
+            .map_err(parse_error)?;
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// Records every transition, error, recovery and bail out of the runners it observes
+/// into a Trace
+///
+/// The recorder is attached to a runner through a shared reference so that it can be read
+/// once the runner has finished:
+///
+/// ```
+/// use std::{cell::RefCell, rc::Rc};
+/// use umrsm::{sm::{OutcomeData, State, StateMachine}, sm_trace::TraceRecorder};
+///
+/// #[derive(Default)]
+/// struct Launch;
+///
+/// impl State for Launch {
+///     type Income = ();
+///     type Transition = OutcomeData<Land>;
+///     type Data = ();
+///
+///     fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+///         OutcomeData::with_name((), "apogee".to_string())
+///     }
+///
+///     fn name(&self) -> String {
+///         "Launch".to_string()
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Land;
+///
+/// impl State for Land {
+///     type Income = ();
+///     type Transition = ();
+///     type Data = ();
+///
+///     fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+/// }
+///
+/// let mut machine = StateMachine::default();
+/// machine.add_state::<Launch>();
+/// machine.add_state::<Land>();
+///
+/// let recorder = Rc::new(RefCell::new(TraceRecorder::new()));
+/// let mut runner = machine.runner::<Launch>((), ()).expect("Launch exists in the machine");
+/// runner.add_observer(recorder.clone());
+/// runner.run_to_completion().expect("Should not error");
+///
+/// let trace = recorder.borrow().trace().clone();
+/// assert_eq!(trace.entries[0].transition, "apogee");
+/// assert_eq!(trace.entries[1].end, None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TraceRecorder {
+    trace: Trace,
+    origin: Instant,
+    steps: u64,
+    steps_in_state: u64,
+}
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceRecorder {
+    /// Create a recorder with an empty trace, measuring monotonic time from now
+    pub fn new() -> Self {
+        Self {
+            trace: Trace::default(),
+            origin: Instant::now(),
+            steps: 0,
+            steps_in_state: 0,
+        }
+    }
+
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    pub fn into_trace(self) -> Trace {
+        self.trace
+    }
+
+    fn record(
+        &mut self,
+        kind: TraceKind,
+        start: &str,
+        transition: &str,
+        end: Option<&str>,
+        at: Instant,
+    ) {
+        self.trace.entries.push(TraceEntry {
+            step: self.steps,
+            kind,
+            start: start.to_string(),
+            transition: transition.to_string(),
+            end: end.map(str::to_string),
+            wall_time: SystemTime::now(),
+            monotonic: at.saturating_duration_since(self.origin),
+            steps_in_state: self.steps_in_state,
+        });
+    }
+}
+
+impl<D> RunnerObserver<D> for TraceRecorder {
+    fn on_step(&mut self, _state: &str, _at: Instant, _data: &D) {
+        self.steps += 1;
+        self.steps_in_state += 1;
+    }
+
+    fn on_event(&mut self, _state: &str, _event: &str, _at: Instant, _data: &D) {}
+
+    fn on_transition(&mut self, start: &str, transition: &str, end: &str, at: Instant, _data: &D) {
+        self.record(TraceKind::Transition, start, transition, Some(end), at);
+    }
+
+    fn on_bail_out(
+        &mut self,
+        start: &str,
+        transition: &str,
+        end: &str,
+        _limit: Limit,
+        at: Instant,
+        _data: &D,
+    ) {
+        self.record(TraceKind::BailOut, start, transition, Some(end), at);
+    }
+
+    fn on_recover(&mut self, error: &RunErrorKind, end: &str, at: Instant, _data: &D) {
+        self.record(
+            TraceKind::Recovery,
+            error.state(),
+            &error.to_string(),
+            Some(end),
+            at,
+        );
+    }
+
+    fn on_enter(&mut self, _state: &str, _at: Instant, _data: &D) {
+        self.steps_in_state = 0;
+    }
+
+    fn on_complete(&mut self, start: &str, transition: &str, at: Instant, _data: &D) {
+        self.record(TraceKind::Transition, start, transition, None, at);
+    }
+
+    fn on_error(&mut self, error: &RunErrorKind, at: Instant, _data: &D) {
+        self.record(
+            TraceKind::Error,
+            error.state(),
+            &error.to_string(),
+            None,
+            at,
+        );
+    }
+}
+
+fn wall_time_ns(wall_time: SystemTime) -> u128 {
+    wall_time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+/// A value of a flat JSON object
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JsonValue {
+    String(String),
+    Number(u128),
+    Null,
+}
+
+/// Builds an entry from a lookup of each of its fields by name
+fn entry_from_fields(get: impl Fn(&str) -> Option<JsonValue>) -> Result<TraceEntry, String> {
+    let field = |name: &str| get(name).ok_or_else(|| format!("Missing field {name}"));
+    let string = |name: &str| match field(name)? {
+        JsonValue::String(value) => Ok(value),
+        _ => Err(format!("Field {name} should be a string")),
+    };
+    let number = |name: &str| match field(name)? {
+        JsonValue::Number(value) => Ok(value),
+        _ => Err(format!("Field {name} should be a non-negative integer")),
+    };
+    let small_number = |name: &str| {
+        number(name)?
+            .try_into()
+            .map_err(|_| format!("Field {name} is too large"))
+    };
+    let duration = |name: &str| {
+        let nanos = number(name)?;
+        let secs = (nanos / 1_000_000_000)
+            .try_into()
+            .map_err(|_| format!("Field {name} is too large"))?;
+        Ok::<_, String>(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+    };
+    let step = small_number("step")?;
+    let kind = string("kind")?;
+    let kind = TraceKind::parse(&kind).ok_or_else(|| format!("Unknown kind {kind}"))?;
+    let start = string("start")?;
+    let transition = string("transition")?;
+    let end = match field("end")? {
+        JsonValue::String(end) => Some(end),
+        JsonValue::Null => None,
+        JsonValue::Number(_) => return Err("Field end should be a string or null".to_string()),
+    };
+    Ok(TraceEntry {
+        step,
+        kind,
+        start,
+        transition,
+        end,
+        wall_time: UNIX_EPOCH + duration("wall_time_ns")?,
+        monotonic: duration("monotonic_ns")?,
+        steps_in_state: small_number("steps_in_state")?,
+    })
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Parses a single line JSON object whose values are strings, non-negative integers or null
+fn parse_json_object(line: &str) -> Result<HashMap<String, JsonValue>, String> {
+    let mut parser = JsonParser {
+        chars: line.trim().chars().peekable(),
+    };
+    let mut object = HashMap::new();
+    parser.expect('{')?;
+    if parser.eat('}') {
+        return parser.finish(object);
+    }
+    loop {
+        let key = parser.string()?;
+        parser.expect(':')?;
+        let value = parser.value()?;
+        object.insert(key, value);
+        if parser.eat('}') {
+            return parser.finish(object);
+        }
+        parser.expect(',')?;
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    /// Consumes `expected` if it is the next character
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            match self.chars.peek() {
+                Some(found) => Err(format!("Expected '{expected}' but found '{found}'")),
+                None => Err(format!("Expected '{expected}' but found the end of the line")),
+            }
+        }
+    }
+
+    fn finish<T>(&mut self, value: T) -> Result<T, String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Ok(value),
+            Some(found) => Err(format!("Unexpected '{found}' after the end of the object")),
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('"') => self.string().map(JsonValue::String),
+            Some('n') => {
+                for expected in "null".chars() {
+                    self.expect(expected)?;
+                }
+                Ok(JsonValue::Null)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(digit) = self.chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                digits
+                    .parse()
+                    .map(JsonValue::Number)
+                    .map_err(|_| format!("Number {digits} is too large"))
+            }
+            Some(found) => Err(format!("Unexpected '{found}' at the start of a value")),
+            None => Err("Expected a value but found the end of the line".to_string()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(value),
+                Some('\\') => value.push(self.escape()?),
+                Some(c) => value.push(c),
+                None => return Err("Unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        match self.chars.next() {
+            Some('"') => Ok('"'),
+            Some('\\') => Ok('\\'),
+            Some('/') => Ok('/'),
+            Some('b') => Ok('\u{8}'),
+            Some('f') => Ok('\u{c}'),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('u') => {
+                let high = self.hex()?;
+                if !(0xD800..0xDC00).contains(&high) {
+                    return char::from_u32(high).ok_or_else(|| "Invalid unicode escape".to_string());
+                }
+                self.expect('\\')?;
+                self.expect('u')?;
+                let low = self.hex()?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return Err("Invalid surrogate pair".to_string());
+                }
+                char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+                    .ok_or_else(|| "Invalid surrogate pair".to_string())
+            }
+            Some(c) => Err(format!("Invalid escape '\\{c}'")),
+            None => Err("Unterminated string".to_string()),
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.by_ref().take(4).collect();
+        if digits.len() != 4 {
+            return Err("Unterminated unicode escape".to_string());
+        }
+        u32::from_str_radix(&digits, 16).map_err(|_| format!("Invalid unicode escape {digits}"))
+    }
+}
+
+/// Quotes a CSV field if it contains a delimiter, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Splits CSV text into records of fields, each with the line the record starts on
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>, TraceReadError> {
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start_line = line;
+        let mut record = Vec::new();
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some('"') if field.is_empty() => loop {
+                    match chars.next() {
+                        Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(TraceReadError::Parse {
+                                line: start_line,
+                                message: "Unterminated quoted field".to_string(),
+                            })
+                        }
+                    }
+                },
+                Some(',') => record.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    record.push(field);
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        if record.len() > 1 || !record[0].is_empty() {
+            records.push((start_line, record));
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{Trace, TraceEntry, TraceKind, TraceReadError, TraceRecorder};
+    use crate::sm::{
+        BoxedOutcome, ContinueOutcome, EventState, IntoOutcome, Limit, OutcomeData, RunErrorKind,
+        State, StateMachine, StepOutcome,
+    };
+
+    #[derive(Default)]
+    struct Search;
+
+    impl State for Search {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+            if *data < 3 {
+                ContinueOutcome::<Self>::default().into_outcome()
+            } else {
+                OutcomeData::<Approach>::with_name((), "found \"gate\", left".to_string())
+                    .into_outcome()
+            }
+        }
+
+        fn name(&self) -> String {
+            "Search".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Approach;
+
+    impl State for Approach {
+        type Income = ();
+        type Transition = ();
+        type Data = u32;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+
+        fn name(&self) -> String {
+            "Approach\nGate".to_string()
+        }
+    }
+
+    struct Reason;
+
+    impl From<RunErrorKind> for Reason {
+        fn from(_error: RunErrorKind) -> Self {
+            Reason
+        }
+    }
+
+    impl From<Limit> for Reason {
+        fn from(_limit: Limit) -> Self {
+            Reason
+        }
+    }
+
+    #[derive(Default)]
+    struct Abort;
+
+    impl State for Abort {
+        type Income = Reason;
+        type Transition = ();
+        type Data = u32;
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+
+        fn name(&self) -> String {
+            "Abort".to_string()
+        }
+    }
+
+    fn recorded_trace() -> Trace {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Search>();
+        machine.add_state::<Approach>();
+
+        let recorder = Rc::new(RefCell::new(TraceRecorder::new()));
+        let mut runner = machine.runner::<Search>(0, ()).unwrap();
+        runner.add_observer(recorder.clone());
+        runner.run_to_completion().unwrap();
+        let trace = recorder.borrow().trace().clone();
+        trace
+    }
+
+    #[test]
+    fn records_transitions() {
+        let trace = recorded_trace();
+        assert_eq!(trace.entries.len(), 2);
+
+        let search = &trace.entries[0];
+        assert_eq!(search.step, 3);
+        assert_eq!(search.start, "Search");
+        assert_eq!(search.transition, "found \"gate\", left");
+        assert_eq!(search.end.as_deref(), Some("Approach\nGate"));
+        assert_eq!(search.steps_in_state, 3);
+
+        let approach = &trace.entries[1];
+        assert_eq!(approach.step, 4);
+        assert_eq!(approach.end, None);
+        assert_eq!(approach.steps_in_state, 1);
+        assert!(approach.monotonic >= search.monotonic);
+        assert!(approach.wall_time >= search.wall_time);
+    }
+
+    #[test]
+    fn records_errors() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Search>();
+        machine.add_state::<Abort>();
+        assert!(machine.set_error_state::<Abort>());
+
+        let recorder = Rc::new(RefCell::new(TraceRecorder::new()));
+        let mut runner = machine.runner::<Search>(0, ()).unwrap();
+        runner.add_observer(recorder.clone());
+        runner.run_to_completion().unwrap();
+        let trace = recorder.borrow().trace().clone();
+        let kinds: Vec<_> = trace.entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(
+            kinds,
+            [TraceKind::Error, TraceKind::Recovery, TraceKind::Transition]
+        );
+        let error = &trace.entries[0];
+        assert_eq!(error.step, 3);
+        assert_eq!(error.start, "Search");
+        assert!(error
+            .transition
+            .starts_with("Search --[found \"gate\", left]-->"));
+        assert_eq!(error.end, None);
+        let recovery = &trace.entries[1];
+        assert_eq!(recovery.transition, error.transition);
+        assert_eq!(recovery.end.as_deref(), Some("Abort"));
+        assert!(recovery.wall_time >= error.wall_time);
+
+        let recorder = Rc::new(RefCell::new(TraceRecorder::new()));
+        let mut runner = machine.runner::<Search>(0, ()).unwrap();
+        runner.set_step_budget(1);
+        assert!(runner.set_bail_out_state::<Abort>());
+        runner.add_observer(recorder.clone());
+        runner.run_to_completion().unwrap();
+        let trace = recorder.borrow().trace().clone();
+        let bail_out = &trace.entries[0];
+        assert_eq!(bail_out.kind, TraceKind::BailOut);
+        assert_eq!(bail_out.start, "Search");
+        assert_eq!(bail_out.transition, "(Step budget of 1)");
+        assert_eq!(bail_out.end.as_deref(), Some("Abort"));
+        assert_eq!(trace.entries[1].kind, TraceKind::Transition);
+        assert_eq!(trace.entries.len(), 2);
+    }
+
+    enum Signal {
+        Ping,
+        Resume,
+    }
+
+    #[derive(Default)]
+    struct Hold;
+
+    impl State for Hold {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = u32;
+
+        fn handle(&mut self, data: &mut Self::Data) -> Self::Transition {
+            *data += 1;
+            ContinueOutcome::<Self>::default().into_outcome()
+        }
+
+        fn name(&self) -> String {
+            "Hold".to_string()
+        }
+    }
+
+    impl EventState<Signal> for Hold {
+        fn on_event(&mut self, event: &Signal, _data: &mut Self::Data) -> Self::Transition {
+            match event {
+                Signal::Ping => ContinueOutcome::<Self>::default().into_outcome(),
+                Signal::Resume => {
+                    OutcomeData::<Approach>::with_name((), "resume".to_string()).into_outcome()
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn events_are_not_steps() {
+        let mut machine = StateMachine::default();
+        machine.add_event_state::<Hold, Signal>();
+        machine.add_state::<Approach>();
+
+        let recorder = Rc::new(RefCell::new(TraceRecorder::new()));
+        let mut runner = machine.runner::<Hold>(0, ()).unwrap();
+        runner.add_observer(recorder.clone());
+        runner.dispatch(Signal::Ping);
+        runner.dispatch(Signal::Ping);
+        runner.dispatch(Signal::Resume);
+        for _ in 0..2 {
+            runner = match runner.step() {
+                StepOutcome::Continue { machine } => machine,
+                e => panic!("Unexpeced runner outcome {e:?}"),
+            };
+            runner = match runner.process_event() {
+                StepOutcome::Continue { machine } => machine,
+                e => panic!("Unexpeced runner outcome {e:?}"),
+            };
+        }
+        runner = match runner.process_event() {
+            StepOutcome::Transition { machine, .. } => machine,
+            e => panic!("Unexpeced runner outcome {e:?}"),
+        };
+        assert_eq!(runner.steps(), 2);
+        let resume = recorder.borrow().trace().entries[0].clone();
+        assert_eq!(resume.step, runner.steps());
+        assert_eq!(resume.start, "Hold");
+        assert_eq!(resume.transition, "resume");
+        assert_eq!(resume.steps_in_state, 2);
+
+        runner.step().into_runner().unwrap_err().unwrap();
+        let trace = recorder.borrow().trace().clone();
+        assert_eq!(trace.entries.len(), 2);
+        assert_eq!(trace.entries[1].step, 3);
+        assert_eq!(trace.entries[1].steps_in_state, 1);
+    }
+
+    #[test]
+    fn jsonl_round_trip() {
+        let trace = recorded_trace();
+        let mut jsonl = Vec::new();
+        trace.write_jsonl(&mut jsonl).unwrap();
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        assert_eq!(jsonl.lines().count(), 2);
+        assert!(jsonl.contains("\"end\":\"Approach\\nGate\""));
+        assert!(jsonl.contains("\"end\":null"));
+        assert_eq!(Trace::read_jsonl(jsonl.as_bytes()).unwrap(), trace);
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let trace = recorded_trace();
+        let mut csv = Vec::new();
+        trace.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with(
+            "step,kind,start,transition,end,wall_time_ns,monotonic_ns,steps_in_state\n3,transition,Search,\"found \"\"gate\"\", left\",\"Approach\nGate\","
+        ));
+        assert_eq!(Trace::read_csv(csv.as_bytes()).unwrap(), trace);
+    }
+
+    #[test]
+    fn read_errors() {
+        let jsonl = "{\"step\":1,\"kind\":\"transition\",\"start\":\"A\",\"transition\":\"t\",\"end\":null,\"wall_time_ns\":5,\"monotonic_ns\":2,\"steps_in_state\":1}\n\n{\"step\":2,\"kind\":\"transition\"}";
+        match Trace::read_jsonl(jsonl.as_bytes()) {
+            Err(TraceReadError::Parse { line, message }) => {
+                assert_eq!(line, 3);
+                assert_eq!(message, "Missing field start");
+            }
+            result => panic!("Unexpected result {result:?}"),
+        }
+
+        let csv = "step,kind,start,transition,end,wall_time_ns,monotonic_ns,steps_in_state\n1,transition,A,t,,5,2,1\n2,error,A,t,B,x,2,1\n";
+        match Trace::read_csv(csv.as_bytes()) {
+            Err(TraceReadError::Parse { line, message }) => {
+                assert_eq!(line, 3);
+                assert_eq!(message, "Field wall_time_ns should be a non-negative integer");
+            }
+            result => panic!("Unexpected result {result:?}"),
+        }
+
+        let csv = "step,kind,start,transition,end,wall_time_ns,monotonic_ns,steps_in_state\n1,error,A,t,,5,2,1\n";
+        assert_eq!(
+            Trace::read_csv(csv.as_bytes()).unwrap().entries,
+            vec![TraceEntry {
+                step: 1,
+                kind: TraceKind::Error,
+                start: "A".to_string(),
+                transition: "t".to_string(),
+                end: None,
+                wall_time: UNIX_EPOCH + Duration::from_nanos(5),
+                monotonic: Duration::from_nanos(2),
+                steps_in_state: 1,
+            }]
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use core::fmt;
+use std::{any::TypeId, fmt::Display};
+
+use crate::{
+    sm::{StateMachine, StateMachineRunner, Threading},
+    sm_mission,
+};
+
+/// A problem with the declared transitions of a state machine
+///
+/// Each issue would otherwise be reported as a StepOutcome error when the transition is taken
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValidationIssue {
+    /// A declared transition targets a state which is not in the state machine
+    MissingTarget {
+        state: String,
+        transition: String,
+        target: TypeId,
+        target_name: &'static str,
+    },
+    /// A declared transition provides data which does not match the Income of its target
+    IncomeMismatch {
+        state: String,
+        transition: String,
+        target: String,
+        expected_type: TypeId,
+        expected_name: &'static str,
+        received_type: TypeId,
+        received_name: &'static str,
+    },
+    /// A declared Wired outcome is not wired to any state
+    Unwired { state: String, transition: String },
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::MissingTarget {
+                state,
+                transition,
+                target_name,
+                ..
+            } => {
+                write!(f, "{state} --[{transition}]--> {target_name}? ")
+                    .and(write!(f, "Type {target_name} does not exist in the state machine"))
+            }
+            ValidationIssue::IncomeMismatch {
+                state,
+                transition,
+                target,
+                expected_name,
+                received_name,
+                ..
+            } => {
+                write!(f, "{state} --[{transition}!]--> {target} ").and(
+                    write!(f, "{target} expected incoming data of type {expected_name} but transition {transition} provides data of type {received_name}"))
+            }
+            ValidationIssue::Unwired { state, transition } => {
+                write!(f, "{state} --[{transition}]--> ? Outcome {transition} is not wired to a state")
+            }
+        }
+    }
+}
+
+/// The result of StateMachine::validate
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Every problem found, sorted so that the report is the same for every run
+    pub issues: Vec<ValidationIssue>,
+    /// The names of states which do not declare their transitions and so could not be checked
+    pub undeclared: Vec<String>,
+}
+
+impl ValidationReport {
+    /// Returns true if no issues were found
+    ///
+    /// Undeclared states do not make a report invalid
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{issue}")?;
+        }
+        for state in &self.undeclared {
+            writeln!(f, "{state} does not declare its transitions")?;
+        }
+        Ok(())
+    }
+}
+
+impl<D, K: Threading> StateMachine<D, K> {
+    /// Checks that every declared transition of every state in the state machine
+    /// targets a state in the state machine with a matching Income
+    ///
+    /// An Income matches if it is the type of the data of the transition,
+    /// or if an income adapter converts the data into it.
+    /// Wired outcomes are checked against the state they are wired to.
+    /// The targets of global transitions and the error state are checked as well,
+    /// reported as transitions of the state "(Any)"
+    ///
+    /// Transitions are declared through State::declared_transitions
+    pub fn validate(&self) -> ValidationReport {
+        self.validate_with(None)
+    }
+
+    /// Validates the state machine, also checking `bail_out` if a runner has a bail out state
+    fn validate_with(&self, bail_out: Option<(TypeId, &'static str)>) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let mut targets = Vec::new();
+        if let Some(hook) = self.global_hook() {
+            targets.extend(hook.targets());
+        }
+        if let Some((target, target_name)) = self.error_state() {
+            targets.push(("(Error)".to_string(), target, target_name));
+        }
+        if let Some((target, target_name)) = bail_out {
+            targets.push(("(BailOut)".to_string(), target, target_name));
+        }
+        for (transition, target, target_name) in targets {
+            if self.registration(target).is_none() {
+                report.issues.push(ValidationIssue::MissingTarget {
+                    state: "(Any)".to_string(),
+                    transition,
+                    target,
+                    target_name,
+                });
+            }
+        }
+        for (state_id, registration) in self.registrations() {
+            let state = registration.name.clone();
+            let Some(declared) = (registration.declared_transitions)() else {
+                report.undeclared.push(state);
+                continue;
+            };
+            for transition in declared {
+                let Some(target_id) = sm_mission::declared_target(self, *state_id, &transition)
+                else {
+                    report.issues.push(ValidationIssue::Unwired {
+                        state: state.clone(),
+                        transition: transition.name,
+                    });
+                    continue;
+                };
+                if target_id == TypeId::of::<()>() || target_id == *state_id {
+                    continue;
+                }
+                let Some(target) = self.registration(target_id) else {
+                    report.issues.push(ValidationIssue::MissingTarget {
+                        state: state.clone(),
+                        transition: transition.name,
+                        target: target_id,
+                        target_name: transition.target_name,
+                    });
+                    continue;
+                };
+                if target.income != transition.income
+                    && !self
+                        .income_adapters()
+                        .contains(transition.income, target.income)
+                {
+                    report.issues.push(ValidationIssue::IncomeMismatch {
+                        state: state.clone(),
+                        transition: transition.name,
+                        target: target.name.clone(),
+                        expected_type: target.income,
+                        expected_name: target.income_name,
+                        received_type: transition.income,
+                        received_name: transition.income_name,
+                    });
+                }
+            }
+        }
+        report.issues.sort();
+        report.undeclared.sort();
+        report
+    }
+}
+
+impl<'a, D, K: Threading> StateMachineRunner<'a, D, K> {
+    /// Validates the state machine of the runner, see StateMachine::validate
+    ///
+    /// The bail out state of the runner is checked as well,
+    /// reported as the transition "(BailOut)" of the state "(Any)"
+    pub fn validate(&self) -> ValidationReport {
+        self.machine().validate_with(self.bail_out_state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        any::{Any, TypeId},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::ValidationIssue;
+    use crate::{
+        sm::{
+            BoxedOutcome, DeclaredTransition, Limit, Outcome, OutcomeData, RunErrorKind, State,
+            StateMachine,
+        },
+        sm_global::GlobalTransition,
+    };
+
+    enum DiveTransition {
+        Up,
+        Fire,
+    }
+
+    impl Outcome for DiveTransition {
+        fn state_type(&self) -> TypeId {
+            match self {
+                DiveTransition::Up => TypeId::of::<Surface>(),
+                DiveTransition::Fire => TypeId::of::<Torpedo>(),
+            }
+        }
+
+        fn data(self: Box<Self>) -> Box<dyn Any> {
+            match *self {
+                DiveTransition::Up => Box::new(0u8),
+                DiveTransition::Fire => Box::new(()),
+            }
+        }
+
+        fn name(&self) -> String {
+            match self {
+                DiveTransition::Up => "Up".to_string(),
+                DiveTransition::Fire => "Fire".to_string(),
+            }
+        }
+
+        fn declared_transitions() -> Option<Vec<DeclaredTransition>> {
+            Some(vec![
+                DeclaredTransition::with_income::<Surface, u8>("Up"),
+                DeclaredTransition::to::<Torpedo>("Fire"),
+            ])
+        }
+    }
+
+    #[derive(Default)]
+    struct Dive {
+        armed: bool,
+    }
+
+    impl State for Dive {
+        type Income = ();
+        type Transition = DiveTransition;
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            if self.armed {
+                DiveTransition::Fire
+            } else {
+                DiveTransition::Up
+            }
+        }
+
+        fn name(&self) -> String {
+            "Dive".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Surface;
+
+    impl State for Surface {
+        type Income = ();
+        type Transition = ();
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+
+        fn name(&self) -> String {
+            "Surface".to_string()
+        }
+    }
+
+    #[derive(Default)]
+    struct Torpedo;
+
+    impl State for Torpedo {
+        type Income = ();
+        type Transition = OutcomeData<Surface>;
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::new(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Drift;
+
+    impl State for Drift {
+        type Income = ();
+        type Transition = BoxedOutcome;
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            Box::new(())
+        }
+
+        fn name(&self) -> String {
+            "Drift".to_string()
+        }
+    }
+
+    #[test]
+    fn valid_machine() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Torpedo>();
+        machine.add_state::<Surface>();
+
+        let report = machine.validate();
+        assert!(report.is_valid());
+        assert!(report.undeclared.is_empty());
+    }
+
+    #[test]
+    fn invalid_machine() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Dive>();
+        machine.add_state::<Surface>();
+        machine.add_state::<Drift>();
+
+        let report = machine.validate();
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue::MissingTarget {
+                    state: "Dive".to_string(),
+                    transition: "Fire".to_string(),
+                    target: TypeId::of::<Torpedo>(),
+                    target_name: std::any::type_name::<Torpedo>(),
+                },
+                ValidationIssue::IncomeMismatch {
+                    state: "Dive".to_string(),
+                    transition: "Up".to_string(),
+                    target: "Surface".to_string(),
+                    expected_type: TypeId::of::<()>(),
+                    expected_name: "()",
+                    received_type: TypeId::of::<u8>(),
+                    received_name: "u8",
+                },
+            ]
+        );
+        assert_eq!(report.undeclared, vec!["Drift".to_string()]);
+    }
+
+    struct Fault;
+
+    impl From<RunErrorKind> for Fault {
+        fn from(_error: RunErrorKind) -> Self {
+            Fault
+        }
+    }
+
+    impl From<Limit> for Fault {
+        fn from(_limit: Limit) -> Self {
+            Fault
+        }
+    }
+
+    #[derive(Default)]
+    struct Abort;
+
+    impl State for Abort {
+        type Income = Fault;
+        type Transition = ();
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {}
+
+        fn name(&self) -> String {
+            "Abort".to_string()
+        }
+    }
+
+    #[test]
+    fn global_error_and_bail_out_targets() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Torpedo>();
+        machine.add_state::<Surface>();
+        machine.add_state::<Abort>();
+        assert!(machine.set_error_state::<Abort>());
+        machine.add_global_transition(GlobalTransition::to::<Surface>("Recall", |_| false, |_| ()));
+        machine.add_global_transition(GlobalTransition::to::<Torpedo>("Engage", |_| false, |_| ()));
+        assert!(machine.validate().is_valid());
+
+        let mut runner = machine.runner::<Surface>((), ()).unwrap();
+        assert!(runner.validate().is_valid());
+        assert!(runner.set_bail_out_state::<Abort>());
+        assert!(runner.validate().is_valid());
+        drop(runner);
+
+        // Removing a state leaves the global transitions to it in place
+        assert!(machine.remove_state::<Torpedo>());
+        assert_eq!(
+            machine.validate().issues,
+            vec![ValidationIssue::MissingTarget {
+                state: "(Any)".to_string(),
+                transition: "Engage".to_string(),
+                target: TypeId::of::<Torpedo>(),
+                target_name: std::any::type_name::<Torpedo>(),
+            }]
+        );
+        let report = format!("{}", machine.validate());
+        assert!(report.starts_with("(Any) --[Engage]--> "));
+    }
+
+    #[test]
+    fn adapted_income() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Dive>();
+        machine.add_state::<Surface>();
+        machine.add_state::<Torpedo>();
+        machine.add_income_adapter(|_depth: u8| ());
+
+        assert!(machine.validate().is_valid());
+    }
+
+    static SONARS_BUILT: AtomicUsize = AtomicUsize::new(0);
+
+    struct Sonar;
+
+    impl Default for Sonar {
+        fn default() -> Self {
+            SONARS_BUILT.fetch_add(1, Ordering::SeqCst);
+            Self
+        }
+    }
+
+    impl State for Sonar {
+        type Income = ();
+        type Transition = OutcomeData<Surface>;
+        type Data = ();
+
+        fn handle(&mut self, _data: &mut Self::Data) -> Self::Transition {
+            OutcomeData::new(())
+        }
+
+        fn name(&self) -> String {
+            "Sonar".to_string()
+        }
+    }
+
+    #[test]
+    fn validate_builds_no_states() {
+        let mut machine = StateMachine::default();
+        machine.add_state::<Sonar>();
+        machine.add_state::<Surface>();
+        let built = SONARS_BUILT.load(Ordering::SeqCst);
+
+        assert!(machine.validate().is_valid());
+        assert_eq!(SONARS_BUILT.load(Ordering::SeqCst), built);
+    }
+}